@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use clap::Args;
+use tracing::error;
+
+use crate::{
+    core::{
+        agent::{Agent, AgentRouter, HealthProbe},
+        client::{StoreClient, StoreClientActor},
+    },
+    error::TraefikResult,
+    TraefikConfig,
+};
+
+#[derive(Args, Debug)]
+pub struct AgentCommand {
+    /// The traefik service to register the server under
+    #[arg(long)]
+    service: String,
+
+    /// The url traefik forwards requests to, e.g. http://10.0.0.1:8080
+    #[arg(long)]
+    url: String,
+
+    /// Also register a router named after the service with this rule
+    #[arg(long)]
+    rule: Option<String>,
+
+    /// The entry points of the router
+    #[arg(long = "entry-point", default_value = "websecure", requires = "rule")]
+    entry_points: Vec<String>,
+
+    /// Enable tls on the router
+    #[arg(long, requires = "rule")]
+    tls: bool,
+
+    /// Only stay registered while this url answers with a 2xx status
+    #[cfg(feature = "http-probe")]
+    #[arg(long, conflicts_with = "health_cmd")]
+    health_url: Option<String>,
+
+    /// Only stay registered while this shell command exits successfully
+    #[arg(long)]
+    health_cmd: Option<String>,
+
+    /// How long the registration outlives the agent, in seconds
+    #[arg(long, default_value = "15")]
+    ttl: i64,
+
+    /// How often to run the health probe and renew the lease, in seconds
+    #[arg(long, default_value = "5")]
+    interval: u64,
+}
+
+pub async fn run<T: StoreClientActor>(
+    command: &AgentCommand,
+    client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    let probe = match &command.health_cmd {
+        Some(cmd) => HealthProbe::Command(cmd.clone()),
+        None => HealthProbe::None,
+    };
+    #[cfg(feature = "http-probe")]
+    let probe = command
+        .health_url
+        .clone()
+        .map(HealthProbe::Http)
+        .unwrap_or(probe);
+    let agent = Agent {
+        rule_prefix: traefik_config.rule_prefix.clone(),
+        service: command.service.clone(),
+        url: command.url.clone(),
+        router: command.rule.as_ref().map(|rule| AgentRouter {
+            rule: rule.clone(),
+            entry_points: command.entry_points.clone(),
+            tls: command.tls,
+        }),
+        ttl: command.ttl,
+        interval: Duration::from_secs(command.interval),
+        probe,
+    };
+    client.run_agent(&agent, shutdown_signal()).await
+}
+
+/// Completes on ctrl-c, or on SIGTERM when running under a service manager
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if let Err(e) = result {
+                error!("Failed to listen for ctrl-c: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+        _ = terminate => {}
+    }
+}
@@ -1,6 +1,9 @@
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 use clap::Args;
-use tracing::{error, info};
 
 #[derive(Args, Debug)]
 pub struct ApplyCommand {
@@ -8,7 +11,7 @@ pub struct ApplyCommand {
     #[arg(short, long)]
     dry_run: bool,
 
-    /// Clean the etcd before applying the config
+    /// Remove keys under the rule prefix that are not part of the config
     #[arg(short, long, default_value_t = false)]
     clean: bool,
 
@@ -17,22 +20,11 @@ pub struct ApplyCommand {
     rules: bool,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &ApplyCommand,
-    client: &StoreClient<Etcd>,
+    client: &StoreClient<T>,
     traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
-    if command.clean && !command.dry_run {
-        match traefik_config.clean_etcd(client).await {
-            Ok(_) => {
-                info!("Cleaned etcd");
-            }
-            Err(e) => {
-                error!("Failed to clean etcd: {e}");
-            }
-        }
-    }
-
     traefik_config
         .apply_to_etcd(client, command.dry_run, command.rules, command.clean)
         .await?;
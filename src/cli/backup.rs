@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::{
+    core::{
+        backup::Backup,
+        client::{StoreClient, StoreClientActor},
+    },
+    error::TraefikResult,
+    TraefikConfig,
+};
+
+#[derive(Args, Debug)]
+pub struct BackupCommand {
+    /// The file to write the backup to, printed when not given
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub async fn run<T: StoreClientActor>(
+    command: &BackupCommand,
+    client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    let snapshot = client.snapshot(traefik_config.rule_prefix.as_str()).await?;
+    let backup = Backup::new(&traefik_config.rule_prefix, snapshot);
+
+    match &command.output {
+        Some(output) => {
+            backup.write(output)?;
+            println!(
+                "Backed up {} keys under '{}' at revision {} to {}",
+                backup.pairs.len(),
+                backup.rule_prefix,
+                backup.revision,
+                output.display()
+            );
+        }
+        None => println!("{}", serde_json::to_string_pretty(&backup)?),
+    }
+
+    Ok(())
+}
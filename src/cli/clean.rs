@@ -1,13 +1,17 @@
 use clap::Args;
 
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 
 #[derive(Args, Debug)]
 pub struct CleanCommand {}
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     _command: &CleanCommand,
-    client: &StoreClient<Etcd>,
+    client: &StoreClient<T>,
     traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
     traefik_config.clean_etcd(client).await?;
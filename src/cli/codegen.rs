@@ -6,7 +6,11 @@ use std::{
 use clap::Args;
 use tracing::debug;
 
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 
 #[derive(Args, Debug)]
 pub struct CodegenCommand {
@@ -17,9 +21,9 @@ pub struct CodegenCommand {
     language: Option<String>,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &CodegenCommand,
-    _client: &StoreClient<Etcd>,
+    _client: &StoreClient<T>,
     _traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
     let output = command.output_dir.as_ref().map(PathBuf::from);
@@ -1,13 +1,25 @@
 use crate::core::etcd_trait::{EtcdPair, ToEtcdPairs};
 use crate::{
-    core::client::StoreClient,
+    core::client::{StoreClient, StoreClientActor},
     error::TraefikResult,
-    features::etcd::{Etcd, EtcdDiff},
+    features::etcd::EtcdDiff,
     TraefikConfig,
 };
 use clap::Args;
 use std::path::PathBuf;
 
+use super::EXIT_CHANGES;
+
+#[derive(Debug, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum DiffFormat {
+    Text,
+    Json,
+    Unified,
+    Markdown,
+    /// The changes grouped by router, service and middleware
+    Semantic,
+}
+
 #[derive(Args, Debug)]
 pub struct DiffCommand {
     #[arg(short, long)]
@@ -15,11 +27,61 @@ pub struct DiffCommand {
 
     #[arg(short = 'F', long)]
     from_file: Option<PathBuf>,
+
+    /// Compare two config files instead of the config and the store, this is
+    /// the old side and needs no store access
+    #[arg(long, requires = "to", conflicts_with = "from_file")]
+    from: Option<PathBuf>,
+
+    /// The new side of a comparison between two config files
+    #[arg(long, requires = "from")]
+    to: Option<PathBuf>,
+
+    /// The variable files the `--from` config is rendered with
+    #[arg(long, requires = "from")]
+    from_variables: Vec<PathBuf>,
+
+    /// The variable files the `--to` config is rendered with
+    #[arg(long, requires = "to")]
+    to_variables: Vec<PathBuf>,
+
+    /// Print the diff as text, JSON, a unified patch, a markdown table or
+    /// grouped by router, service and middleware
+    #[arg(long, value_enum, default_value = "text")]
+    format: DiffFormat,
+
+    /// Exit with 1 when there are changes and 2 on errors, 0 otherwise
+    #[arg(long)]
+    pub exit_code: bool,
 }
 
-pub async fn run(
+impl DiffCommand {
+    /// Whether both sides are config files, so no store is needed
+    pub fn is_offline(&self) -> bool {
+        self.from.is_some() && self.to.is_some()
+    }
+}
+
+/// Compare the `--from` and `--to` config files, each with its own variables
+pub fn run_offline(command: &DiffCommand) -> TraefikResult<()> {
+    let (Some(from), Some(to)) = (&command.from, &command.to) else {
+        return Ok(());
+    };
+    let old_config = TraefikConfig::from_file(from, &command.from_variables)?;
+    let new_config = TraefikConfig::from_file(to, &command.to_variables)?;
+    let diff = new_config.diff_against(&old_config)?;
+    print(
+        command,
+        &diff,
+        &new_config.rule_prefix,
+        &from.display().to_string(),
+        &to.display().to_string(),
+    )
+}
+
+pub async fn run<T: StoreClientActor>(
     command: &DiffCommand,
-    client: &StoreClient<Etcd>,
+    client: &StoreClient<T>,
     traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
     // Get the pairs from our current config
@@ -53,7 +115,46 @@ pub async fn run(
 
     // Create diff
     let diff = EtcdDiff::create(&current_pairs, &comparison_pairs);
-    diff.display(command.detailed);
+    let from = match &command.from_file {
+        Some(file_path) => file_path.display().to_string(),
+        None => format!("live/{}", traefik_config.rule_prefix),
+    };
+    print(
+        command,
+        &diff,
+        &traefik_config.rule_prefix,
+        &from,
+        &format!("config/{}", traefik_config.rule_prefix),
+    )
+}
+
+/// Print `diff` in the requested format, exiting with [`EXIT_CHANGES`] when
+/// asked to and there are changes
+fn print(
+    command: &DiffCommand,
+    diff: &EtcdDiff,
+    rule_prefix: &str,
+    from: &str,
+    to: &str,
+) -> TraefikResult<()> {
+    match command.format {
+        DiffFormat::Text => diff.display(command.detailed),
+        DiffFormat::Json => println!("{}", serde_json::to_string(&diff.report())?),
+        DiffFormat::Unified => print!("{}", diff.to_unified(from, to)),
+        DiffFormat::Markdown => print!("{}", diff.to_markdown()),
+        DiffFormat::Semantic => {
+            let changes = diff.semantic(rule_prefix);
+            if changes.is_empty() {
+                println!("No changes");
+            }
+            for change in changes {
+                println!("{change}");
+            }
+        }
+    }
+    if command.exit_code && diff.has_changes() {
+        std::process::exit(EXIT_CHANGES);
+    }
 
     Ok(())
 }
@@ -0,0 +1,66 @@
+use clap::Args;
+
+use crate::{
+    core::{
+        client::{StoreClient, StoreClientActor},
+        drift::{DriftKind, DriftReport},
+    },
+    error::TraefikResult,
+    TraefikConfig,
+};
+
+use super::EXIT_CHANGES;
+
+#[derive(Args, Debug)]
+pub struct DriftCommand {
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// Compare the configuration, the last apply and the store
+///
+/// Exits with [`EXIT_CHANGES`] when a key was changed in the store since the
+/// last apply, changes to the configuration alone are not reported this way.
+/// Errors exit with [`super::EXIT_ERROR`].
+pub async fn run<T: StoreClientActor>(
+    command: &DriftCommand,
+    client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    let report = traefik_config.drift(client).await?;
+    match command.json {
+        true => println!("{}", serde_json::to_string(&report)?),
+        false => display(&report, &traefik_config.rule_prefix),
+    }
+    if report.has_live_changes() {
+        std::process::exit(EXIT_CHANGES);
+    }
+    Ok(())
+}
+
+fn display(report: &DriftReport, rule_prefix: &str) {
+    if report.drifts.is_empty() {
+        println!(
+            "'{}' matches the configuration and the apply at {}",
+            rule_prefix, report.applied_at
+        );
+        return;
+    }
+    println!(
+        "Compared with the apply at {}: {} local, {} live, {} conflicting",
+        report.applied_at,
+        report.count(DriftKind::Local),
+        report.count(DriftKind::Live),
+        report.count(DriftKind::Conflict)
+    );
+    for kind in [DriftKind::Conflict, DriftKind::Live, DriftKind::Local] {
+        for drift in report.drifts.iter().filter(|drift| drift.kind == kind) {
+            println!("  {:<8} {}", kind, drift.key);
+            let side = |value: &Option<String>| value.as_deref().unwrap_or("<absent>").to_string();
+            println!("    config:  {}", side(&drift.desired));
+            println!("    applied: {}", side(&drift.last_applied));
+            println!("    live:    {}", side(&drift.live));
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use clap::{Args, Subcommand};
+
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::{TraefikError, TraefikResult},
+    features::etcd::{EndpointStatus, Etcd},
+    TraefikConfig,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum EndpointAction {
+    /// Show the health, leader and database size of every etcd endpoint
+    Status {
+        /// Print the statuses as a table or as JSON
+        #[arg(short = 'F', long, default_value = "text")]
+        format: Format,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct EndpointCommand {
+    #[command(subcommand)]
+    action: EndpointAction,
+}
+
+pub async fn run<T: StoreClientActor>(
+    command: &EndpointCommand,
+    _client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    match &command.action {
+        EndpointAction::Status { format } => {
+            let statuses = Etcd::endpoint_status(&traefik_config.etcd).await?;
+            match format {
+                Format::Json => println!("{}", serde_json::to_string(&statuses)?),
+                Format::Text => print_statuses(&statuses),
+            }
+            let unhealthy = statuses.iter().filter(|status| !status.healthy).count();
+            if unhealthy > 0 {
+                return Err(TraefikError::Unavailable(format!(
+                    "{unhealthy} of {} etcd endpoints are unhealthy",
+                    statuses.len()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_statuses(statuses: &[EndpointStatus]) {
+    println!(
+        "{:<30}  {:<9}  {:>16}  {:<6}  {:>9}  {:<8}  ERRORS",
+        "ENDPOINT", "HEALTH", "ID", "LEADER", "DB SIZE", "VERSION"
+    );
+    for status in statuses {
+        println!(
+            "{:<30}  {:<9}  {:>16}  {:<6}  {:>9}  {:<8}  {}",
+            status.endpoint,
+            if status.healthy {
+                "healthy"
+            } else {
+                "unhealthy"
+            },
+            status.member_id.as_deref().unwrap_or("-"),
+            if status.is_leader { "yes" } else { "no" },
+            status.db_size.map_or_else(|| "-".to_string(), format_size),
+            status.version.as_deref().unwrap_or("-"),
+            status.errors.join("; ")
+        );
+    }
+}
+
+fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "kB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit + 1 < UNITS.len() {
+        size /= 1000.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
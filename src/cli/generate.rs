@@ -2,7 +2,11 @@ use std::path::PathBuf;
 
 use clap::Args;
 
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 
 #[derive(Args, Debug)]
 pub struct GenerateCommand {
@@ -14,9 +18,9 @@ pub struct GenerateCommand {
     domain: Option<String>,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &GenerateCommand,
-    _client: &StoreClient<Etcd>,
+    _client: &StoreClient<T>,
     _traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
     let config = TraefikConfig::generate_config(command.domain.clone());
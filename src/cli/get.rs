@@ -1,6 +1,10 @@
 use clap::Args;
 
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 
 #[derive(Args, Debug)]
 pub struct GetCommand {
@@ -11,9 +15,9 @@ pub struct GetCommand {
     keys: bool,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &GetCommand,
-    client: &StoreClient<Etcd>,
+    client: &StoreClient<T>,
     _traefik_config: &TraefikConfig,
 ) -> TraefikResult<()> {
     let key = command.name.as_str();
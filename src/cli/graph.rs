@@ -1,6 +1,10 @@
 use clap::Args;
 
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 
 #[derive(Args, Debug)]
 pub struct GraphCommand {
@@ -9,9 +13,9 @@ pub struct GraphCommand {
     pub dot: bool,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &GraphCommand,
-    _client: &StoreClient<Etcd>,
+    _client: &StoreClient<T>,
     traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
     match traefik_config.into_graph(command.dot) {
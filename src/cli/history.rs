@@ -0,0 +1,63 @@
+use clap::Args;
+
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryCommand {
+    /// Only show the most recent revisions
+    #[arg(short = 'n', long, default_value_t = 20)]
+    limit: usize,
+
+    /// Print revisions as a table or as JSON
+    #[arg(short = 'F', long, default_value = "text")]
+    format: Format,
+}
+
+pub async fn run<T: StoreClientActor>(
+    command: &HistoryCommand,
+    client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    let history = client.history(traefik_config.rule_prefix.as_str()).await?;
+    let recent = history
+        .iter()
+        .rev()
+        .take(command.limit)
+        .copied()
+        .collect::<Vec<_>>();
+
+    match command.format {
+        Format::Json => println!("{}", serde_json::to_string(&recent)?),
+        Format::Text => {
+            if recent.is_empty() {
+                println!("No revisions changed '{}'", traefik_config.rule_prefix);
+                return Ok(());
+            }
+            println!(
+                "{:>10}  {:>7}  {:>5}  {:>7}",
+                "REVISION", "CHANGED", "PUTS", "DELETES"
+            );
+            for summary in recent.iter() {
+                println!(
+                    "{:>10}  {:>7}  {:>5}  {:>7}",
+                    summary.revision,
+                    summary.changed(),
+                    summary.puts,
+                    summary.deletes
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,27 +1,49 @@
 use std::path::PathBuf;
 
 use clap::Args;
+use tracing::warn;
 
 use crate::{
-    core::{client::StoreClient, etcd_trait::EtcdPair},
+    core::{
+        client::{StoreClient, StoreClientActor},
+        etcd_trait::EtcdPair,
+        import,
+    },
     error::TraefikResult,
-    features::etcd::Etcd,
     TraefikConfig,
 };
 
 #[derive(Args, Debug)]
 pub struct LoadCommand {
+    /// Read the keys from a key-value file instead of the store
     #[arg(long, short)]
     from_file: Option<PathBuf>,
+    /// Write the imported config here instead of printing it
+    #[arg(long, short)]
+    output: Option<PathBuf>,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &LoadCommand,
-    _client: &StoreClient<Etcd>,
-    _traefik_config: &mut TraefikConfig,
+    client: &StoreClient<T>,
+    traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
-    let pairs = EtcdPair::from_file(command.from_file.as_ref().unwrap())?;
-    let config = TraefikConfig::parse_etcd_to_traefik_config(pairs)?;
-    println!("Loaded config: {:#?}", config);
+    let imported = match &command.from_file {
+        Some(from_file) => {
+            let pairs = EtcdPair::from_file(from_file)?;
+            import::import(&traefik_config.rule_prefix, &pairs)
+        }
+        None => traefik_config.import(client).await?,
+    };
+    for warning in imported.warnings.iter() {
+        warn!("{}", warning);
+        eprintln!("Skipped: {warning}");
+    }
+
+    let serialized = serde_yaml::to_string(&imported.config)?;
+    match &command.output {
+        Some(output) => std::fs::write(output, serialized)?,
+        None => print!("{serialized}"),
+    }
     Ok(())
 }
@@ -0,0 +1,40 @@
+use clap::{Args, Subcommand};
+
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
+
+#[derive(Debug, Subcommand)]
+pub enum LockAction {
+    /// Show who holds the lock on the rule prefix
+    Status,
+    /// Forcibly release a stuck lock on the rule prefix
+    Break,
+}
+
+#[derive(Args, Debug)]
+pub struct LockCommand {
+    #[command(subcommand)]
+    action: LockAction,
+}
+
+pub async fn run<T: StoreClientActor>(
+    command: &LockCommand,
+    client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    let rule_prefix = traefik_config.rule_prefix.as_str();
+    match command.action {
+        LockAction::Status => match client.lock_holder(rule_prefix).await? {
+            Some(holder) => println!("'{}' is locked by {}", rule_prefix, holder),
+            None => println!("'{}' is not locked", rule_prefix),
+        },
+        LockAction::Break => match client.break_lock(rule_prefix).await? {
+            Some(holder) => println!("Broke the lock on '{}' held by {}", rule_prefix, holder),
+            None => println!("'{}' is not locked", rule_prefix),
+        },
+    }
+    Ok(())
+}
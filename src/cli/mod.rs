@@ -1,35 +1,57 @@
 use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
-use color_eyre::eyre::eyre;
-use tracing::{debug, error, instrument};
+use tracing::{debug, instrument};
 
+#[cfg(feature = "consul")]
+use crate::features::consul::Consul;
+#[cfg(feature = "redis")]
+use crate::features::redis::Redis;
 use crate::{
     config::traefik_config::TraefikConfig,
-    core::client::StoreClient,
+    core::client::{StoreClient, StoreClientActor, StoreKind},
     error::{TraefikError, TraefikResult},
-    features::etcd::{Etcd, EtcdConfig, PartialEtcdConfig},
+    features::{
+        etcd::{Etcd, EtcdConfig, PartialEtcdConfig},
+        memory::MemoryStore,
+    },
     tracing::{init_tracing, LogConfig},
     NAME,
 };
 
+mod agent;
 mod apply;
+mod backup;
 mod clean;
 mod codegen;
 #[cfg(feature = "etcd")]
 mod diff;
+#[cfg(feature = "drift")]
+mod drift;
+#[cfg(feature = "etcd")]
+mod endpoint;
 mod generate;
 mod get;
 mod graph;
+mod history;
 #[cfg(feature = "etcd")]
 mod load;
+mod lock;
+mod prune;
 mod render;
+mod restore;
+mod rollback;
 #[cfg(feature = "api")]
 pub(crate) mod serve;
 mod show;
 mod ssl;
 mod tofile;
 mod validate;
+mod watch;
+
+/// The exit codes of `diff --exit-code` and `drift`, as with `git diff`
+pub const EXIT_CHANGES: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None, name = NAME)]
@@ -59,6 +81,14 @@ pub struct Cli {
     /// The variable files
     #[arg(long, short = 'v', global = true)]
     pub variable_files: Vec<String>,
+
+    /// The key-value store backend, overrides the `store` set in the config file
+    #[arg(long, global = true, value_enum)]
+    pub store: Option<StoreKind>,
+
+    /// A JSON or YAML file used to seed the memory store
+    #[arg(long, global = true)]
+    pub store_seed: Option<PathBuf>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -82,7 +112,7 @@ pub enum Commands {
     Codegen(codegen::CodegenCommand),
     /// Diff the current traefik configuration
     Diff(diff::DiffCommand),
-    /// Load the traefik configuration from a key-value file
+    /// Rebuild a config file from the live keys or a key-value file
     Load(load::LoadCommand),
     /// Render the traefik configuration
     Render(render::RenderCommand),
@@ -92,12 +122,60 @@ pub enum Commands {
     Graph(graph::GraphCommand),
     /// Write the traefik configuration to a file
     ToFile(tofile::ToFileCommand),
+    /// Watch the key-value store for changes
+    Watch(watch::WatchCommand),
+    /// List the store revisions that changed the rule prefix
+    History(history::HistoryCommand),
+    /// Restore the rule prefix to a previous store revision
+    Rollback(rollback::RollbackCommand),
+    /// Write every key under the rule prefix to a backup file
+    Backup(backup::BackupCommand),
+    /// Restore the rule prefix from a backup file
+    Restore(restore::RestoreCommand),
+    /// Inspect or break the lock taken by commands that write the rule prefix
+    Lock(lock::LockCommand),
+    /// Delete keys the configuration no longer generates
+    Prune(prune::PruneCommand),
+    #[cfg(feature = "drift")]
+    /// Report keys changed in the configuration or the store since the last apply
+    Drift(drift::DriftCommand),
+    /// Keep a server registered while a local health probe passes
+    Agent(agent::AgentCommand),
+    #[cfg(feature = "etcd")]
+    /// Inspect the etcd endpoints
+    Endpoint(endpoint::EndpointCommand),
+}
+
+impl Commands {
+    /// Whether failures exit with the command's own error code
+    fn error_exit_code(&self) -> Option<i32> {
+        match self {
+            #[cfg(feature = "etcd")]
+            Commands::Diff(diff_command) if diff_command.exit_code => Some(EXIT_ERROR),
+            #[cfg(feature = "drift")]
+            Commands::Drift(_) => Some(EXIT_ERROR),
+            _ => None,
+        }
+    }
 }
 
 #[instrument]
 pub async fn run() -> TraefikResult<()> {
     color_eyre::install()?;
     let cli: Cli = Cli::parse();
+    match cli.command.error_exit_code() {
+        Some(code) => {
+            if let Err(e) = run_cli(cli).await {
+                eprintln!("Error: {e}");
+                std::process::exit(code);
+            }
+            Ok(())
+        }
+        None => run_cli(cli).await,
+    }
+}
+
+async fn run_cli(cli: Cli) -> TraefikResult<()> {
     let log_level = cli.log_level.clone();
     let log_config = LogConfig {
         max_level: log_level.clone(),
@@ -106,6 +184,13 @@ pub async fn run() -> TraefikResult<()> {
     };
     init_tracing(NAME, &log_config)?;
 
+    #[cfg(feature = "etcd")]
+    if let Commands::Diff(diff_command) = &cli.command {
+        if diff_command.is_offline() {
+            return diff::run_offline(diff_command);
+        }
+    }
+
     let config_file = cli.config_file.unwrap_or_default();
     debug!("Using config file: {:?}", config_file);
 
@@ -121,64 +206,132 @@ pub async fn run() -> TraefikResult<()> {
 
     let mut traefik_config = parse_config_file(&config_file, cli.variable_files)?;
 
-    #[cfg(feature = "etcd")]
-    let etcd_client = match cli.etcd_config {
-        Some(config) => {
-            let default_config = EtcdConfig::default();
-            let partial_config = PartialEtcdConfig::from(config);
-            let config = default_config.merge(partial_config);
-            Etcd::new(&config).await?
-        }
-        None => Etcd::new(&traefik_config.etcd).await?,
-    };
+    let store = cli.store.or(traefik_config.store).unwrap_or_default();
+    debug!("Using store: {:?}", store);
+    match store {
+        #[cfg(feature = "etcd")]
+        StoreKind::Etcd => {
+            if let Some(config) = cli.etcd_config {
+                let default_config = EtcdConfig::default();
+                let partial_config = PartialEtcdConfig::from(config);
+                traefik_config.etcd = default_config.merge(partial_config);
+            }
+            let etcd_client = Etcd::new(&traefik_config.etcd).await?;
+            let client = StoreClient::new(etcd_client);
+            run_command(cli.command, &client, &mut traefik_config).await?;
+        }
+        StoreKind::Memory => {
+            let memory_store = match &cli.store_seed {
+                Some(seed_file) => MemoryStore::from_file(seed_file)?,
+                None => MemoryStore::new(),
+            };
+            let client = StoreClient::new(memory_store);
+            run_command(cli.command, &client, &mut traefik_config).await?;
+        }
+        #[cfg(feature = "consul")]
+        StoreKind::Consul => {
+            let consul_config = traefik_config.consul.clone().unwrap_or_default();
+            let client = StoreClient::new(Consul::new(&consul_config)?);
+            run_command(cli.command, &client, &mut traefik_config).await?;
+        }
+        #[cfg(feature = "redis")]
+        StoreKind::Redis => {
+            let redis_config = traefik_config.redis.clone().unwrap_or_default();
+            let client = StoreClient::new(Redis::new(&redis_config).await?);
+            run_command(cli.command, &client, &mut traefik_config).await?;
+        }
+        #[allow(unreachable_patterns)]
+        store => {
+            return Err(TraefikError::ConfigError(format!(
+                "the {store:?} store is not enabled in this build"
+            )));
+        }
+    }
 
-    #[cfg(feature = "etcd")]
-    let client = StoreClient::new(etcd_client);
+    Ok(())
+}
 
-    match cli.command {
+async fn run_command<T: StoreClientActor>(
+    command: Commands,
+    client: &StoreClient<T>,
+    traefik_config: &mut TraefikConfig,
+) -> TraefikResult<()> {
+    match command {
         Commands::Get(get_command) => {
-            get::run(&get_command, &client, &traefik_config).await?;
+            get::run(&get_command, client, traefik_config).await?;
         }
         Commands::Show(show_command) => {
-            show::run(&show_command, &client, &traefik_config).await?;
+            show::run(&show_command, client, traefik_config).await?;
         }
         Commands::Apply(apply_command) => {
-            apply::run(&apply_command, &client, &mut traefik_config).await?;
+            apply::run(&apply_command, client, traefik_config).await?;
         }
         Commands::Clean(clean_command) => {
-            clean::run(&clean_command, &client, &mut traefik_config).await?;
+            clean::run(&clean_command, client, traefik_config).await?;
         }
         Commands::Validate => {
-            validate::run(&client, &mut traefik_config).await?;
+            validate::run(client, traefik_config).await?;
         }
         Commands::Generate(generate_command) => {
-            generate::run(&generate_command, &client, &mut traefik_config).await?;
+            generate::run(&generate_command, client, traefik_config).await?;
         }
         #[cfg(feature = "api")]
         Commands::Serve(serve_command) => {
-            serve::run(&serve_command, &client, &mut traefik_config).await?;
+            serve::run(&serve_command, client, traefik_config).await?;
         }
         Commands::Codegen(codegen_command) => {
-            codegen::run(&codegen_command, &client, &mut traefik_config).await?;
+            codegen::run(&codegen_command, client, traefik_config).await?;
         }
         #[cfg(feature = "etcd")]
         Commands::Diff(diff_command) => {
-            diff::run(&diff_command, &client, &mut traefik_config).await?;
+            diff::run(&diff_command, client, traefik_config).await?;
         }
         Commands::Load(load_command) => {
-            load::run(&load_command, &client, &mut traefik_config).await?;
+            load::run(&load_command, client, traefik_config).await?;
         }
         Commands::Render(render_command) => {
-            render::run(&render_command, &client, &mut traefik_config).await?;
+            render::run(&render_command, client, traefik_config).await?;
         }
         Commands::Ssl(ssl_command) => {
-            ssl::run(&ssl_command, &client, &mut traefik_config).await?;
+            ssl::run(&ssl_command, client, traefik_config).await?;
         }
         Commands::Graph(graph_command) => {
-            graph::run(&graph_command, &client, &mut traefik_config).await?;
+            graph::run(&graph_command, client, traefik_config).await?;
         }
         Commands::ToFile(tofile_command) => {
-            tofile::run(&tofile_command, &client, &mut traefik_config).await?;
+            tofile::run(&tofile_command, client, traefik_config).await?;
+        }
+        Commands::Watch(watch_command) => {
+            watch::run(&watch_command, client, traefik_config).await?;
+        }
+        Commands::History(history_command) => {
+            history::run(&history_command, client, traefik_config).await?;
+        }
+        Commands::Rollback(rollback_command) => {
+            rollback::run(&rollback_command, client, traefik_config).await?;
+        }
+        Commands::Backup(backup_command) => {
+            backup::run(&backup_command, client, traefik_config).await?;
+        }
+        Commands::Restore(restore_command) => {
+            restore::run(&restore_command, client, traefik_config).await?;
+        }
+        Commands::Lock(lock_command) => {
+            lock::run(&lock_command, client, traefik_config).await?;
+        }
+        Commands::Prune(prune_command) => {
+            prune::run(&prune_command, client, traefik_config).await?;
+        }
+        #[cfg(feature = "drift")]
+        Commands::Drift(drift_command) => {
+            drift::run(&drift_command, client, traefik_config).await?;
+        }
+        Commands::Agent(agent_command) => {
+            agent::run(&agent_command, client, traefik_config).await?;
+        }
+        #[cfg(feature = "etcd")]
+        Commands::Endpoint(endpoint_command) => {
+            endpoint::run(&endpoint_command, client, traefik_config).await?;
         }
     }
 
@@ -189,39 +342,7 @@ fn parse_config_file(
     config_file: &PathBuf,
     variable_files: Vec<String>,
 ) -> TraefikResult<TraefikConfig> {
-    let config = std::fs::read_to_string(&config_file).unwrap_or_default();
-    if config.is_empty() {
-        return Err(TraefikError::ParsingError(eyre!("Config file is empty")));
-    }
-    let mut config_ctx = tera::Context::new();
-
-    for variable_file in variable_files.iter() {
-        let file = std::fs::File::open(variable_file)?;
-        let variables: std::collections::HashMap<String, serde_json::Value> =
-            serde_json::from_reader(file)?;
-        for (key, value) in variables.iter() {
-            debug!("Adding variable: {} = {:#?}", key, value);
-            config_ctx.insert(key, value);
-        }
-    }
-
-    match tera::Tera::one_off(&config, &config_ctx, false) {
-        Ok(rendered_config) => {
-            println!("-- {}", rendered_config);
-            let traefik_config: TraefikConfig = match serde_yaml::from_str(&rendered_config) {
-                Ok(config) => config,
-                Err(e) => {
-                    let err = eyre!("Parse error: {e}");
-                    error!("{err}");
-                    return Err(TraefikError::ParsingError(err));
-                }
-            };
-            Ok(traefik_config)
-        }
-        Err(_e) => {
-            let traefik_config: TraefikConfig = serde_yaml::from_str(&config)?;
-            println!("-- {:?}", traefik_config);
-            Ok(traefik_config)
-        }
-    }
+    let traefik_config = TraefikConfig::from_file(config_file, &variable_files)?;
+    debug!("Parsed config: {:?}", traefik_config);
+    Ok(traefik_config)
 }
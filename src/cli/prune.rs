@@ -0,0 +1,59 @@
+use std::io::{BufRead, Write};
+
+use clap::Args;
+
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
+
+#[derive(Args, Debug)]
+pub struct PruneCommand {
+    /// Report the orphan keys and dangling references without deleting anything
+    #[arg(short, long)]
+    dry_run: bool,
+
+    /// Delete the orphan keys without asking for confirmation
+    #[arg(short, long, conflicts_with = "dry_run")]
+    yes: bool,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run<T: StoreClientActor>(
+    command: &PruneCommand,
+    client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    let plan = traefik_config.prune_plan(client).await?;
+    match command.json {
+        true => println!("{}", serde_json::to_string(&plan)?),
+        false => plan.display(),
+    }
+    let orphans = plan.orphan_keys().len();
+    if command.dry_run || orphans == 0 {
+        return Ok(());
+    }
+    if !command.yes && !confirm(&format!("Delete {orphans} orphan keys?"))? {
+        println!("Nothing was deleted");
+        return Ok(());
+    }
+
+    let pruned = traefik_config.prune(client, &plan).await?;
+    println!(
+        "Pruned {} keys from '{}'",
+        pruned, traefik_config.rule_prefix
+    );
+    Ok(())
+}
+
+fn confirm(question: &str) -> TraefikResult<bool> {
+    print!("{question} [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
@@ -1,29 +1,55 @@
 use clap::Args;
+use serde::Serialize;
 
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, clap::ValueEnum)]
 pub enum Format {
     Yaml,
     Json,
+    Toml,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Target {
+    /// The traefikctl configuration after templating
+    Config,
+    /// A traefik file provider dynamic configuration
+    FileProvider,
 }
 
 #[derive(Args, Debug)]
 pub struct RenderCommand {
     #[arg(short = 'F', long, default_value = "yaml")]
     pub format: Format,
+
+    #[arg(short, long, default_value = "config")]
+    pub target: Target,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &RenderCommand,
-    _client: &StoreClient<Etcd>,
+    _client: &StoreClient<T>,
     traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
-    let rendered = match command.format {
-        Format::Yaml => serde_yaml::to_string(&traefik_config)?,
-        Format::Json => serde_json::to_string(&traefik_config)?,
+    let rendered = match command.target {
+        Target::Config => render(&*traefik_config, &command.format)?,
+        Target::FileProvider => render(&traefik_config.to_file_provider()?, &command.format)?,
     };
 
     println!("{}", rendered);
     Ok(())
 }
+
+fn render<S: Serialize>(document: &S, format: &Format) -> TraefikResult<String> {
+    let rendered = match format {
+        Format::Yaml => serde_yaml::to_string(document)?,
+        Format::Json => serde_json::to_string(document)?,
+        Format::Toml => toml::to_string(document)?,
+    };
+    Ok(rendered)
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::{
+    core::{
+        backup::Backup,
+        client::{StoreClient, StoreClientActor},
+    },
+    error::TraefikResult,
+    TraefikConfig,
+};
+
+#[derive(Args, Debug)]
+pub struct RestoreCommand {
+    /// The backup file written by `backup`
+    #[arg(index(1))]
+    file: PathBuf,
+
+    /// Show the diff without writing it
+    #[arg(short, long)]
+    dry_run: bool,
+
+    /// Show a detailed diff
+    #[arg(long)]
+    detailed: bool,
+}
+
+pub async fn run<T: StoreClientActor>(
+    command: &RestoreCommand,
+    client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    let backup = Backup::read(&command.file)?;
+    println!(
+        "Restoring {} keys under '{}' from revision {} taken at {}",
+        backup.pairs.len(),
+        backup.rule_prefix,
+        backup.revision,
+        backup.created_at
+    );
+
+    let diff = TraefikConfig::replace_prefix(
+        client,
+        &backup.rule_prefix,
+        traefik_config.owner.as_deref(),
+        &backup.pairs,
+        &traefik_config.lock,
+        command.dry_run,
+        command.detailed,
+    )
+    .await?;
+    if !command.dry_run {
+        println!(
+            "Restored '{}': {} added, {} modified, {} removed, {} unchanged",
+            backup.rule_prefix,
+            diff.added.len(),
+            diff.modified.len(),
+            diff.removed.len(),
+            diff.unchanged.len()
+        );
+    }
+
+    Ok(())
+}
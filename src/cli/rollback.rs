@@ -0,0 +1,39 @@
+use clap::Args;
+
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
+
+#[derive(Args, Debug)]
+pub struct RollbackCommand {
+    /// The revision to restore the rule prefix to, see `history`
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    to_revision: i64,
+
+    /// Show the diff without writing it
+    #[arg(short, long)]
+    dry_run: bool,
+
+    /// Show a detailed diff
+    #[arg(long)]
+    detailed: bool,
+}
+
+pub async fn run<T: StoreClientActor>(
+    command: &RollbackCommand,
+    client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    traefik_config
+        .rollback_to_revision(
+            client,
+            command.to_revision,
+            command.dry_run,
+            command.detailed,
+        )
+        .await?;
+
+    Ok(())
+}
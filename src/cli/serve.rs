@@ -1,9 +1,9 @@
 use clap::Args;
 
 use crate::{
-    core::client::StoreClient,
+    core::client::{StoreClient, StoreClientActor},
     error::TraefikResult,
-    features::{api::ServerConfig, etcd::Etcd},
+    features::api::ServerConfig,
     TraefikConfig,
 };
 
@@ -21,9 +21,9 @@ pub struct ServeCommand {
     pub base_config_path: String,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &ServeCommand,
-    _client: &StoreClient<Etcd>,
+    _client: &StoreClient<T>,
     _traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
     let server_config = ServerConfig {
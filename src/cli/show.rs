@@ -1,7 +1,11 @@
 use clap::Args;
 use tracing::debug;
 
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 
 #[derive(Args, Debug)]
 pub struct ShowCommand {
@@ -11,9 +15,9 @@ pub struct ShowCommand {
     output: Option<String>,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &ShowCommand,
-    client: &StoreClient<Etcd>,
+    client: &StoreClient<T>,
     _traefik_config: &TraefikConfig,
 ) -> TraefikResult<()> {
     debug!("Show command");
@@ -1,6 +1,10 @@
 use clap::Args;
 
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 
 #[derive(Args, Debug)]
 pub struct SslCommand {
@@ -17,9 +21,9 @@ pub struct SslCommand {
     subject_alt_names: String,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     _command: &SslCommand,
-    _client: &StoreClient<Etcd>,
+    _client: &StoreClient<T>,
     _traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
     println!("TODO: implement ssl command");
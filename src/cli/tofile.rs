@@ -6,9 +6,11 @@ use serde::Serialize;
 use tracing::debug;
 
 use crate::{
-    core::{client::StoreClient, templating::TemplateOr},
+    core::{
+        client::{StoreClient, StoreClientActor},
+        templating::TemplateOr,
+    },
     error::TraefikResult,
-    features::etcd::Etcd,
     TraefikConfig,
 };
 
@@ -28,9 +30,9 @@ pub struct ToFileCommand {
     pub prefix: String,
 }
 
-pub async fn run(
+pub async fn run<T: StoreClientActor>(
     command: &ToFileCommand,
-    client: &StoreClient<Etcd>,
+    client: &StoreClient<T>,
     traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
     let tree = build_tree(client, &command.prefix, traefik_config).await?;
@@ -90,8 +92,8 @@ impl Serialize for Node {
     }
 }
 
-async fn build_tree(
-    client: &StoreClient<Etcd>,
+async fn build_tree<T: StoreClientActor>(
+    client: &StoreClient<T>,
     prefix: &str,
     config: &TraefikConfig,
 ) -> TraefikResult<Node> {
@@ -1,7 +1,11 @@
-use crate::{core::client::StoreClient, error::TraefikResult, features::etcd::Etcd, TraefikConfig};
+use crate::{
+    core::client::{StoreClient, StoreClientActor},
+    error::TraefikResult,
+    TraefikConfig,
+};
 
-pub async fn run(
-    _client: &StoreClient<Etcd>,
+pub async fn run<T: StoreClientActor>(
+    _client: &StoreClient<T>,
     traefik_config: &mut TraefikConfig,
 ) -> TraefikResult<()> {
     println!("Validating Traefik configuration...");
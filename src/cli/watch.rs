@@ -0,0 +1,58 @@
+use clap::Args;
+use futures_util::StreamExt;
+
+use crate::{
+    core::client::{StoreClient, StoreClientActor, WatchEvent, WatchEventKind},
+    error::TraefikResult,
+    TraefikConfig,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct WatchCommand {
+    /// The prefix to watch, defaults to the rule prefix of the config
+    #[arg(index(1))]
+    prefix: Option<String>,
+
+    /// Print events as text or as JSON lines
+    #[arg(short = 'F', long, default_value = "text")]
+    format: Format,
+}
+
+pub async fn run<T: StoreClientActor>(
+    command: &WatchCommand,
+    client: &StoreClient<T>,
+    traefik_config: &TraefikConfig,
+) -> TraefikResult<()> {
+    let prefix = command
+        .prefix
+        .clone()
+        .unwrap_or_else(|| traefik_config.rule_prefix.clone());
+    let mut events = client.watch(prefix.as_str()).await?;
+    if command.format == Format::Text {
+        println!("Watching '{}' for changes", prefix);
+    }
+
+    while let Some(event) = events.next().await {
+        let event = event?;
+        let line = match command.format {
+            Format::Text => format_event(&event),
+            Format::Json => serde_json::to_string(&event)?,
+        };
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+fn format_event(event: &WatchEvent) -> String {
+    match event.kind {
+        WatchEventKind::Put => format!("[{}] PUT {} = {}", event.revision, event.key, event.value),
+        WatchEventKind::Delete => format!("[{}] DELETE {}", event.revision, event.key),
+    }
+}
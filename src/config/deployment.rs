@@ -1,4 +1,7 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
 use crate::{
     core::{
@@ -12,7 +15,10 @@ use export_type::ExportType;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use super::{middleware::MiddlewareConfig, selections::SelectionConfig};
+use super::{
+    health_check::HealthCheckConfig, middleware::MiddlewareConfig, selections::SelectionConfig,
+    sticky::StickyConfig,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default, JsonSchema)]
 #[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::Type))]
@@ -26,6 +32,8 @@ pub enum DeploymentProtocol {
     Https,
     #[serde(rename = "tcp")]
     Tcp,
+    #[serde(rename = "udp")]
+    Udp,
     #[serde(other)]
     Invalid,
 }
@@ -42,6 +50,7 @@ impl From<DeploymentProtocol> for String {
             DeploymentProtocol::Http => "http".to_string(),
             DeploymentProtocol::Https => "https".to_string(),
             DeploymentProtocol::Tcp => "tcp".to_string(),
+            DeploymentProtocol::Udp => "udp".to_string(),
             DeploymentProtocol::Invalid => "invalid".to_string(),
         }
     }
@@ -53,6 +62,7 @@ impl From<&str> for DeploymentProtocol {
             "http" => DeploymentProtocol::Http,
             "https" => DeploymentProtocol::Https,
             "tcp" => DeploymentProtocol::Tcp,
+            "udp" => DeploymentProtocol::Udp,
             _ => DeploymentProtocol::Invalid,
         }
     }
@@ -64,6 +74,7 @@ impl From<i32> for DeploymentProtocol {
             1 => DeploymentProtocol::Http,
             2 => DeploymentProtocol::Https,
             3 => DeploymentProtocol::Tcp,
+            4 => DeploymentProtocol::Udp,
             _ => DeploymentProtocol::Invalid,
         }
     }
@@ -75,6 +86,7 @@ impl Display for DeploymentProtocol {
             DeploymentProtocol::Http => write!(f, "http"),
             DeploymentProtocol::Https => write!(f, "https"),
             DeploymentProtocol::Tcp => write!(f, "tcp"),
+            DeploymentProtocol::Udp => write!(f, "udp"),
             DeploymentProtocol::Invalid => write!(f, "invalid"),
         }
     }
@@ -113,6 +125,51 @@ impl Validate for IPAndPort {
     }
 }
 
+/// How a tcp deployment is routed
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default, JsonSchema)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct TcpConfig {
+    /// Match the SNI against this regular expression instead of the domain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sni_regexp: Option<String>,
+    /// Forward the TLS connection as is instead of terminating it
+    #[serde(default)]
+    pub passthrough: bool,
+}
+
+/// Where a udp deployment listens
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default, JsonSchema)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct UdpConfig {
+    /// The udp entry points of the router, traefik has no default one
+    #[serde(default)]
+    pub entry_points: Vec<String>,
+}
+
+/// One backend of a deployment load balanced over several servers
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct ServerConfig {
+    pub ip: String,
+    pub port: u16,
+    /// The share of the deployment's traffic sent to the server, relative to
+    /// the other servers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+}
+
+impl Display for ServerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.ip, self.port)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 #[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
 #[serde(untagged)]
@@ -120,9 +177,44 @@ impl Validate for IPAndPort {
 #[export_type(rename_all = "camelCase", path = "generated/types")]
 pub enum DeploymentTarget {
     IpAndPort { ip: String, port: u16 },
+    Servers { servers: Vec<ServerConfig> },
     Service { service_name: String },
 }
 
+impl DeploymentTarget {
+    /// The servers of the load balancer generated for the target, none for a
+    /// named service
+    pub fn servers(&self) -> Vec<ServerConfig> {
+        match self {
+            DeploymentTarget::IpAndPort { ip, port } => vec![ServerConfig {
+                ip: ip.clone(),
+                port: *port,
+                weight: None,
+            }],
+            DeploymentTarget::Servers { servers } => servers.clone(),
+            DeploymentTarget::Service { .. } => vec![],
+        }
+    }
+
+    fn validate_servers(servers: &[ServerConfig]) -> TraefikResult<()> {
+        if servers.is_empty() {
+            return Err(TraefikError::DeploymentConfig(
+                "servers must list at least one server".to_string(),
+            ));
+        }
+        let mut seen = HashSet::new();
+        for server in servers {
+            if !seen.insert((server.ip.as_str(), server.port)) {
+                return Err(TraefikError::DeploymentConfig(format!(
+                    "server {} is listed more than once",
+                    server
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Default for DeploymentTarget {
     fn default() -> Self {
         DeploymentTarget::IpAndPort {
@@ -136,6 +228,15 @@ impl Display for DeploymentTarget {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DeploymentTarget::IpAndPort { ip, port } => write!(f, "{}:{}", ip, port),
+            DeploymentTarget::Servers { servers } => write!(
+                f,
+                "{}",
+                servers
+                    .iter()
+                    .map(|server| server.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             DeploymentTarget::Service { service_name } => write!(f, "{}", service_name),
         }
     }
@@ -152,6 +253,13 @@ impl Validate for DeploymentTarget {
                 validate_port(*port)?;
                 validate_ip(ip)?;
             }
+            DeploymentTarget::Servers { servers } => {
+                Self::validate_servers(servers)?;
+                for server in servers {
+                    validate_port(server.port)?;
+                    validate_ip(&server.ip)?;
+                }
+            }
             DeploymentTarget::Service { service_name } => validate_is_alphanumeric(service_name)?,
         }
         Ok(())
@@ -185,6 +293,19 @@ pub struct DeploymentConfig {
     pub middlewares: Option<Vec<String>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub middleware_templates: Option<HashMap<String, MiddlewareConfig>>,
+    /// The tcp routing of the deployment, for the tcp protocol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<TcpConfig>,
+    /// The udp entry points of the deployment, for the udp protocol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub udp: Option<UdpConfig>,
+    /// The health check of the generated service, overrides the one of the
+    /// host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheckConfig>,
+    /// Cookie affinity to one server of the generated service
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sticky: Option<StickyConfig>,
 }
 
 impl Default for DeploymentConfig {
@@ -198,6 +319,10 @@ impl Default for DeploymentConfig {
             middlewares: None,
             middleware_templates: None,
             variables: None,
+            tcp: None,
+            udp: None,
+            health_check: None,
+            sticky: None,
         }
     }
 }
@@ -245,6 +370,10 @@ pub struct DeploymentConfigBuilder {
     variables: Option<HashMap<String, TemplateOr<String>>>,
     middlewares: Option<Vec<String>>,
     middleware_templates: Option<HashMap<String, MiddlewareConfig>>,
+    tcp: Option<TcpConfig>,
+    udp: Option<UdpConfig>,
+    health_check: Option<HealthCheckConfig>,
+    sticky: Option<StickyConfig>,
 }
 
 impl DeploymentConfigBuilder {
@@ -258,6 +387,11 @@ impl DeploymentConfigBuilder {
         self
     }
 
+    pub fn servers(mut self, servers: Vec<ServerConfig>) -> Self {
+        self.target = Some(DeploymentTarget::Servers { servers });
+        self
+    }
+
     pub fn service_name(mut self, service_name: String) -> Self {
         self.target = Some(DeploymentTarget::Service { service_name });
         self
@@ -296,6 +430,26 @@ impl DeploymentConfigBuilder {
         self
     }
 
+    pub fn tcp(mut self, tcp: TcpConfig) -> Self {
+        self.tcp = Some(tcp);
+        self
+    }
+
+    pub fn udp(mut self, udp: UdpConfig) -> Self {
+        self.udp = Some(udp);
+        self
+    }
+
+    pub fn health_check(mut self, health_check: HealthCheckConfig) -> Self {
+        self.health_check = Some(health_check);
+        self
+    }
+
+    pub fn sticky(mut self, sticky: StickyConfig) -> Self {
+        self.sticky = Some(sticky);
+        self
+    }
+
     pub fn build(self) -> DeploymentConfig {
         let target = self.target.unwrap_or(DeploymentTarget::default());
         DeploymentConfig {
@@ -307,6 +461,10 @@ impl DeploymentConfigBuilder {
             middleware_templates: self.middleware_templates,
             name: self.name.unwrap_or("deployment".to_string()),
             variables: self.variables,
+            tcp: self.tcp,
+            udp: self.udp,
+            health_check: self.health_check,
+            sticky: self.sticky,
         }
     }
 }
@@ -314,15 +472,12 @@ impl DeploymentConfigBuilder {
 impl Validate for DeploymentConfig {
     fn validate(
         &self,
-        _resolver: &mut impl TemplateResolver,
-        _context: &TemplateContext,
+        resolver: &mut impl TemplateResolver,
+        context: &TemplateContext,
     ) -> TraefikResult<()> {
-        if self.protocol != DeploymentProtocol::Http
-            && self.protocol != DeploymentProtocol::Https
-            && self.protocol != DeploymentProtocol::Tcp
-        {
+        if self.protocol == DeploymentProtocol::Invalid {
             return Err(TraefikError::DeploymentConfig(format!(
-                "protocol must be http, https, or tcp, got {}",
+                "protocol must be http, https, tcp or udp, got {}",
                 self.protocol
             )));
         }
@@ -333,6 +488,22 @@ impl Validate for DeploymentConfig {
                 validate_port(*port)?;
                 self.is_valid_ip_or_hostname(ip)?;
             }
+            DeploymentTarget::Servers { servers } => {
+                DeploymentTarget::validate_servers(servers)?;
+                for server in servers {
+                    validate_port(server.port)?;
+                    self.is_valid_ip_or_hostname(&server.ip)?;
+                }
+                // Only http servers take a weight, tcp and udp ones an address
+                if self.router_protocol() != "http"
+                    && servers.iter().any(|server| server.weight.is_some())
+                {
+                    return Err(TraefikError::DeploymentConfig(format!(
+                        "{} deployment {} cannot weigh its servers",
+                        self.protocol, self.name
+                    )));
+                }
+            }
             DeploymentTarget::Service { service_name } => {
                 validate_is_alphanumeric(service_name)?;
             }
@@ -345,11 +516,166 @@ impl Validate for DeploymentConfig {
             )));
         }
 
+        self.validate_tcp()?;
+        self.validate_udp()?;
+        self.validate_health_check(resolver, context)?;
+        self.validate_sticky(resolver, context)?;
+
         Ok(())
     }
 }
 
 impl DeploymentConfig {
+    pub fn is_tcp(&self) -> bool {
+        self.protocol == DeploymentProtocol::Tcp
+    }
+
+    pub fn is_udp(&self) -> bool {
+        self.protocol == DeploymentProtocol::Udp
+    }
+
+    /// The traefik section the deployment is written to, `http`, `tcp` or
+    /// `udp`
+    pub fn router_protocol(&self) -> &'static str {
+        match self.protocol {
+            DeploymentProtocol::Tcp => "tcp",
+            DeploymentProtocol::Udp => "udp",
+            _ => "http",
+        }
+    }
+
+    /// Tcp routers only see the SNI and the client ip
+    fn validate_tcp(&self) -> TraefikResult<()> {
+        if !self.is_tcp() {
+            if self.tcp.is_some() {
+                return Err(TraefikError::DeploymentConfig(format!(
+                    "deployment {} sets tcp routing but its protocol is {}",
+                    self.name, self.protocol
+                )));
+            }
+            return Ok(());
+        }
+
+        if self
+            .selection
+            .as_ref()
+            .is_some_and(|selection| selection.with_cookie.is_some())
+        {
+            return Err(TraefikError::DeploymentConfig(format!(
+                "tcp deployment {} cannot select on a cookie",
+                self.name
+            )));
+        }
+        if let Some(sni_regexp) = self.tcp.as_ref().and_then(|tcp| tcp.sni_regexp.as_ref()) {
+            if sni_regexp.is_empty() || sni_regexp.contains('`') {
+                return Err(TraefikError::DeploymentConfig(format!(
+                    "tcp deployment {} has an invalid sni_regexp: {:?}",
+                    self.name, sni_regexp
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Health checks are only written for the http services generated from
+    /// an ip and port, a named service carries its own
+    fn validate_health_check(
+        &self,
+        resolver: &mut impl TemplateResolver,
+        context: &TemplateContext,
+    ) -> TraefikResult<()> {
+        let Some(health_check) = &self.health_check else {
+            return Ok(());
+        };
+        if self.router_protocol() != "http" {
+            return Err(TraefikError::DeploymentConfig(format!(
+                "{} deployment {} cannot have a health check",
+                self.protocol, self.name
+            )));
+        }
+        if let DeploymentTarget::Service { service_name } = &self.target {
+            return Err(TraefikError::DeploymentConfig(format!(
+                "deployment {} points to service {}, set the health check on the service",
+                self.name, service_name
+            )));
+        }
+        health_check.validate(resolver, context)
+    }
+
+    /// Stickiness is a cookie, so only http services generated from an ip and
+    /// port or servers have it. A sticky cookie sharing the name of the
+    /// selection cookie would overwrite it and break the routing
+    fn validate_sticky(
+        &self,
+        resolver: &mut impl TemplateResolver,
+        context: &TemplateContext,
+    ) -> TraefikResult<()> {
+        let Some(sticky) = &self.sticky else {
+            return Ok(());
+        };
+        if self.router_protocol() != "http" {
+            return Err(TraefikError::DeploymentConfig(format!(
+                "{} deployment {} cannot be sticky",
+                self.protocol, self.name
+            )));
+        }
+        if let DeploymentTarget::Service { service_name } = &self.target {
+            return Err(TraefikError::DeploymentConfig(format!(
+                "deployment {} points to service {}, set sticky on the service",
+                self.name, service_name
+            )));
+        }
+        if let Some(with_cookie) = self
+            .selection
+            .as_ref()
+            .and_then(|selection| selection.with_cookie.as_ref())
+        {
+            if sticky.name.as_ref() == Some(&with_cookie.name) {
+                return Err(TraefikError::DeploymentConfig(format!(
+                    "deployment {} selects on cookie {}, the sticky cookie needs another name",
+                    self.name, with_cookie.name
+                )));
+            }
+        }
+        sticky.validate(resolver, context)
+    }
+
+    /// Udp routers have no rule, so nothing can be selected on, and no
+    /// middlewares
+    fn validate_udp(&self) -> TraefikResult<()> {
+        if !self.is_udp() {
+            if self.udp.is_some() {
+                return Err(TraefikError::DeploymentConfig(format!(
+                    "deployment {} sets udp entry points but its protocol is {}",
+                    self.name, self.protocol
+                )));
+            }
+            return Ok(());
+        }
+
+        if self
+            .selection
+            .as_ref()
+            .is_some_and(|selection| *selection != SelectionConfig::default())
+        {
+            return Err(TraefikError::DeploymentConfig(format!(
+                "udp deployment {} cannot select on a cookie or client ip",
+                self.name
+            )));
+        }
+        if self.middlewares.as_ref().is_some_and(|m| !m.is_empty())
+            || self.middleware_templates.is_some()
+        {
+            return Err(TraefikError::DeploymentConfig(format!(
+                "udp deployment {} cannot use middlewares",
+                self.name
+            )));
+        }
+
+        Ok(())
+    }
+
     pub fn validate_path(&self, path: &str) -> TraefikResult<()> {
         validate_is_alphanumeric(path)?;
 
@@ -380,7 +706,10 @@ impl DeploymentConfig {
 
 #[cfg(test)]
 mod tests {
-    use crate::test_helpers::{create_test_resolver, create_test_template_context};
+    use crate::{
+        config::selections::FromClientIpConfig,
+        test_helpers::{create_test_resolver, create_test_template_context},
+    };
 
     use super::*;
 
@@ -396,6 +725,7 @@ mod tests {
         assert_eq!(DeploymentProtocol::from("http"), DeploymentProtocol::Http);
         assert_eq!(DeploymentProtocol::from("https"), DeploymentProtocol::Https);
         assert_eq!(DeploymentProtocol::from("tcp"), DeploymentProtocol::Tcp);
+        assert_eq!(DeploymentProtocol::from("udp"), DeploymentProtocol::Udp);
         assert_eq!(
             DeploymentProtocol::from("invalid"),
             DeploymentProtocol::Invalid
@@ -538,4 +868,205 @@ mod tests {
         let context = create_test_template_context();
         assert!(deployment.validate(&mut resolver, &context).is_ok());
     }
+
+    #[test]
+    fn test_tcp_deployment_accepts_passthrough_and_sni_regexp() {
+        let deployment_config = r#"
+        ip: 10.0.0.5
+        port: 5432
+        protocol: tcp
+        tcp:
+          sni_regexp: "^db-[a-z]+\\.example\\.com$"
+          passthrough: true
+        "#;
+        let deployment: DeploymentConfig = serde_yaml::from_str(deployment_config).unwrap();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        assert!(deployment.validate(&mut resolver, &context).is_ok());
+        assert!(deployment.tcp.unwrap().passthrough);
+    }
+
+    #[test]
+    fn test_tcp_deployment_rejects_cookie_selection_and_http_tcp_settings() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let deployment: DeploymentConfig = serde_yaml::from_str(
+            r#"
+        ip: 10.0.0.5
+        port: 5432
+        protocol: tcp
+        with_cookie:
+          name: beta
+        "#,
+        )
+        .unwrap();
+        assert!(deployment.validate(&mut resolver, &context).is_err());
+
+        let deployment = DeploymentConfig {
+            tcp: Some(TcpConfig::default()),
+            ..Default::default()
+        };
+        assert!(deployment.validate(&mut resolver, &context).is_err());
+    }
+
+    #[test]
+    fn test_deployment_with_servers() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let deployment: DeploymentConfig = serde_yaml::from_str(
+            r#"
+        servers:
+          - ip: 10.0.0.1
+            port: 8080
+            weight: 3
+          - ip: 10.0.0.2
+            port: 8080
+        "#,
+        )
+        .unwrap();
+        assert!(deployment.validate(&mut resolver, &context).is_ok());
+        assert_eq!(deployment.target.servers().len(), 2);
+        assert_eq!(deployment.target.servers()[0].weight, Some(3));
+        assert_eq!(
+            deployment.target.to_string(),
+            "10.0.0.1:8080, 10.0.0.2:8080"
+        );
+
+        let tcp = DeploymentConfig {
+            protocol: DeploymentProtocol::Tcp,
+            ..deployment.clone()
+        };
+        assert!(tcp.validate(&mut resolver, &context).is_err());
+
+        let duplicated = DeploymentConfig::builder()
+            .servers(vec![
+                ServerConfig {
+                    ip: "10.0.0.1".to_string(),
+                    port: 8080,
+                    weight: None,
+                },
+                ServerConfig {
+                    ip: "10.0.0.1".to_string(),
+                    port: 8080,
+                    weight: Some(2),
+                },
+            ])
+            .build();
+        assert!(duplicated.validate(&mut resolver, &context).is_err());
+
+        let empty = DeploymentConfig::builder().servers(vec![]).build();
+        assert!(empty.validate(&mut resolver, &context).is_err());
+    }
+
+    #[test]
+    fn test_sticky_cookie_cannot_be_the_selection_cookie() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let deployment: DeploymentConfig = serde_yaml::from_str(
+            r#"
+        ip: 10.0.0.7
+        port: 8080
+        with_cookie:
+          name: beta
+        sticky:
+          name: affinity
+          secure: true
+          same_site: none
+        "#,
+        )
+        .unwrap();
+        assert!(deployment.validate(&mut resolver, &context).is_ok());
+
+        let same_name = DeploymentConfig {
+            sticky: Some(StickyConfig {
+                name: Some("beta".to_string()),
+                ..Default::default()
+            }),
+            ..deployment.clone()
+        };
+        assert!(same_name.validate(&mut resolver, &context).is_err());
+
+        let tcp = DeploymentConfig {
+            protocol: DeploymentProtocol::Tcp,
+            selection: None,
+            ..deployment
+        };
+        assert!(tcp.validate(&mut resolver, &context).is_err());
+    }
+
+    #[test]
+    fn test_health_check_only_on_generated_http_services() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let deployment = DeploymentConfig::builder()
+            .ip_and_port("10.0.0.7".to_string(), 8080)
+            .health_check(HealthCheckConfig::default())
+            .build();
+        assert!(deployment.validate(&mut resolver, &context).is_ok());
+
+        let tcp = DeploymentConfig {
+            protocol: DeploymentProtocol::Tcp,
+            ..deployment.clone()
+        };
+        assert!(tcp.validate(&mut resolver, &context).is_err());
+
+        let with_service = DeploymentConfig {
+            target: DeploymentTarget::Service {
+                service_name: "redirector".to_string(),
+            },
+            ..deployment.clone()
+        };
+        assert!(with_service.validate(&mut resolver, &context).is_err());
+
+        let bad_interval = DeploymentConfig {
+            health_check: Some(HealthCheckConfig {
+                interval: "often".to_string(),
+                ..Default::default()
+            }),
+            ..deployment
+        };
+        assert!(bad_interval.validate(&mut resolver, &context).is_err());
+    }
+
+    #[test]
+    fn test_udp_deployment_rejects_selection_and_middlewares() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let deployment: DeploymentConfig = serde_yaml::from_str(
+            r#"
+        ip: 10.0.0.53
+        port: 53
+        protocol: udp
+        udp:
+          entry_points: [dns]
+        "#,
+        )
+        .unwrap();
+        assert!(deployment.validate(&mut resolver, &context).is_ok());
+        assert_eq!(deployment.router_protocol(), "udp");
+
+        let with_middlewares = DeploymentConfig {
+            middlewares: Some(vec!["limit".to_string()]),
+            ..deployment.clone()
+        };
+        assert!(with_middlewares.validate(&mut resolver, &context).is_err());
+
+        let with_selection = DeploymentConfig {
+            selection: Some(SelectionConfig {
+                from_client_ip: Some(FromClientIpConfig {
+                    ip: Some("10.0.0.1".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..deployment.clone()
+        };
+        assert!(with_selection.validate(&mut resolver, &context).is_err());
+
+        let http_with_udp = DeploymentConfig {
+            udp: deployment.udp.clone(),
+            ..Default::default()
+        };
+        assert!(http_with_udp.validate(&mut resolver, &context).is_err());
+    }
 }
@@ -1,21 +1,45 @@
+use std::collections::HashMap;
+
 use crate::{
     core::{
+        etcd_trait::{EtcdPair, ToEtcdPairs},
         templating::{TemplateContext, TemplateResolver},
+        util::{is_duration, validate_port},
         Validate,
     },
     error::{TraefikError, TraefikResult},
 };
 use export_type::ExportType;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// The active health check of a load balancer, traefik stops sending
+/// traffic to servers that fail it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 #[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
 #[cfg_attr(feature = "codegen", derive(ExportType))]
 #[export_type(rename_all = "camelCase", path = "generated/types")]
+#[serde(default)]
 pub struct HealthCheckConfig {
     pub path: String,
     pub interval: String,
     pub timeout: String,
+    /// Replaces the scheme of the server url, `http`, `https` or `h2c`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    /// The host header sent with the check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Replaces the port of the server url
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// The status the server must answer with, any 2xx or 3xx otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follow_redirects: Option<bool>,
 }
 
 impl Default for HealthCheckConfig {
@@ -24,7 +48,65 @@ impl Default for HealthCheckConfig {
             path: "/health".to_string(),
             interval: "10s".to_string(),
             timeout: "5s".to_string(),
+            scheme: None,
+            hostname: None,
+            port: None,
+            expected_status: None,
+            headers: None,
+            follow_redirects: None,
+        }
+    }
+}
+
+impl ToEtcdPairs for HealthCheckConfig {
+    fn to_etcd_pairs(
+        &self,
+        base_key: &str,
+        _resolver: &mut impl TemplateResolver,
+        _context: &TemplateContext,
+    ) -> TraefikResult<Vec<EtcdPair>> {
+        // The base_key: `{service}/loadBalancer`
+        let base_key = format!("{}/healthCheck", base_key);
+        let mut pairs = vec![
+            EtcdPair::new(format!("{}/path", base_key), self.path.clone()),
+            EtcdPair::new(format!("{}/interval", base_key), self.interval.clone()),
+            EtcdPair::new(format!("{}/timeout", base_key), self.timeout.clone()),
+        ];
+        if let Some(scheme) = &self.scheme {
+            pairs.push(EtcdPair::new(format!("{}/scheme", base_key), scheme));
+        }
+        if let Some(hostname) = &self.hostname {
+            pairs.push(EtcdPair::new(format!("{}/hostname", base_key), hostname));
+        }
+        if let Some(port) = self.port {
+            pairs.push(EtcdPair::new(
+                format!("{}/port", base_key),
+                port.to_string(),
+            ));
+        }
+        if let Some(status) = self.expected_status {
+            pairs.push(EtcdPair::new(
+                format!("{}/status", base_key),
+                status.to_string(),
+            ));
+        }
+        if let Some(headers) = &self.headers {
+            let mut headers = headers.iter().collect::<Vec<_>>();
+            headers.sort();
+            for (name, value) in headers {
+                pairs.push(EtcdPair::new(
+                    format!("{}/headers/{}", base_key, name),
+                    value,
+                ));
+            }
+        }
+        if let Some(follow_redirects) = self.follow_redirects {
+            pairs.push(EtcdPair::new(
+                format!("{}/followRedirects", base_key),
+                follow_redirects.to_string(),
+            ));
         }
+        Ok(pairs)
     }
 }
 
@@ -40,13 +122,74 @@ impl Validate for HealthCheckConfig {
             ));
         }
 
+        if !self.path.starts_with('/') {
+            return Err(TraefikError::HealthCheckConfig(format!(
+                "path must start with /: {}",
+                self.path
+            )));
+        }
+
+        for (name, value) in [("interval", &self.interval), ("timeout", &self.timeout)] {
+            if !is_duration(value) {
+                return Err(TraefikError::HealthCheckConfig(format!(
+                    "{} must be a duration like 10s or 500ms, got {}",
+                    name, value
+                )));
+            }
+        }
+
+        if let Some(scheme) = &self.scheme {
+            if !["http", "https", "h2c"].contains(&scheme.as_str()) {
+                return Err(TraefikError::HealthCheckConfig(format!(
+                    "scheme must be http, https or h2c, got {}",
+                    scheme
+                )));
+            }
+        }
+
+        if self
+            .hostname
+            .as_ref()
+            .is_some_and(|hostname| hostname.is_empty())
+        {
+            return Err(TraefikError::HealthCheckConfig(
+                "hostname must not be empty".to_string(),
+            ));
+        }
+
+        if let Some(port) = self.port {
+            validate_port(port)?;
+        }
+
+        if let Some(status) = self.expected_status {
+            if !(100..=599).contains(&status) {
+                return Err(TraefikError::HealthCheckConfig(format!(
+                    "expected_status must be between 100 and 599, got {}",
+                    status
+                )));
+            }
+        }
+
+        if let Some(headers) = &self.headers {
+            if headers
+                .keys()
+                .any(|name| name.is_empty() || name.contains('/'))
+            {
+                return Err(TraefikError::HealthCheckConfig(
+                    "header names must not be empty or contain /".to_string(),
+                ));
+            }
+        }
+
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::test_helpers::{create_test_resolver, create_test_template_context};
+    use crate::test_helpers::{
+        assert_contains_pair, create_test_resolver, create_test_template_context,
+    };
 
     use super::*;
 
@@ -83,6 +226,76 @@ mod tests {
         assert!(health_check.validate(&mut resolver, &context).is_err());
     }
 
+    #[test]
+    fn test_health_check_config_is_invalid_if_interval_is_not_a_duration() {
+        let health_check = HealthCheckConfig {
+            interval: "10 seconds".to_string(),
+            ..Default::default()
+        };
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        assert!(health_check.validate(&mut resolver, &context).is_err());
+    }
+
+    #[test]
+    fn test_health_check_config_is_invalid_with_unknown_scheme_or_status() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let health_check = HealthCheckConfig {
+            scheme: Some("ftp".to_string()),
+            ..Default::default()
+        };
+        assert!(health_check.validate(&mut resolver, &context).is_err());
+        let health_check = HealthCheckConfig {
+            expected_status: Some(42),
+            ..Default::default()
+        };
+        assert!(health_check.validate(&mut resolver, &context).is_err());
+    }
+
+    #[test]
+    fn test_health_check_config_to_etcd_pairs() {
+        let health_check: HealthCheckConfig = serde_yaml::from_str(
+            r#"
+        path: /ready
+        interval: 30s
+        scheme: https
+        hostname: api.internal
+        port: 8443
+        expected_status: 204
+        headers:
+          X-Check: traefik
+        follow_redirects: false
+        "#,
+        )
+        .unwrap();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        assert!(health_check.validate(&mut resolver, &context).is_ok());
+
+        let pairs = health_check
+            .to_etcd_pairs(
+                "test/http/services/api/loadBalancer",
+                &mut resolver,
+                &context,
+            )
+            .unwrap();
+        let base = "test/http/services/api/loadBalancer/healthCheck";
+        for pair in [
+            "path /ready",
+            "interval 30s",
+            "timeout 5s",
+            "scheme https",
+            "hostname api.internal",
+            "port 8443",
+            "status 204",
+            "headers/X-Check traefik",
+            "followRedirects false",
+        ] {
+            assert_contains_pair(&pairs, &format!("{base}/{pair}"));
+        }
+    }
+
     #[test]
     fn test_health_check_config_is_valid() {
         let health_check = HealthCheckConfig::default();
@@ -1,16 +1,17 @@
 use std::collections::{HashMap, HashSet};
 
-use super::{deployment::DeploymentConfig, selections::SelectionConfig};
+use super::{
+    deployment::DeploymentConfig, health_check::HealthCheckConfig, selections::SelectionConfig,
+};
 use crate::{
     core::{
-        client::StoreClient,
+        client::{StoreClient, StoreClientActor},
         rules::{add_selection_rules, RuleConfig},
         templating::{TemplateContext, TemplateOr, TemplateResolver},
         util::{get_safe_key, validate_is_alphanumeric},
         Validate,
     },
     error::{TraefikError, TraefikResult},
-    features::etcd::Etcd,
 };
 use color_eyre::eyre::eyre;
 use export_type::ExportType;
@@ -43,6 +44,10 @@ pub struct HostConfig {
     pub forward_host: bool,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub variables: Option<HashMap<String, TemplateOr<String>>>,
+    /// The health check of the http services generated for the host's
+    /// deployments, unless they set their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheckConfig>,
 }
 
 impl Validate for HostConfig {
@@ -77,7 +82,13 @@ impl Validate for HostConfig {
                 .validate(resolver, &host_context)?;
         }
 
+        if let Some(health_check) = &self.health_check {
+            health_check.validate(resolver, &host_context)?;
+        }
+
         self.validate_paths(resolver, &host_context)?;
+        self.validate_udp_entry_points()?;
+        self.validate_sticky_cookies()?;
 
         Ok(())
     }
@@ -130,6 +141,71 @@ impl HostConfig {
             )));
         }
 
+        // Tcp routers only see the SNI and udp routers see nothing, never
+        // the path
+        if let Some((name, deployment)) = path
+            .deployments
+            .iter()
+            .find(|(_, deployment)| deployment.router_protocol() != "http")
+        {
+            return Err(TraefikError::HostConfig(format!(
+                "{} deployment {} cannot be routed by path {}",
+                deployment.protocol, name, path.path
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Traefik has no default udp entry point, so every udp router needs one
+    fn validate_udp_entry_points(&self) -> TraefikResult<()> {
+        if let Some((name, _)) = self.deployments.iter().find(|(_, deployment)| {
+            deployment.is_udp()
+                && deployment
+                    .udp
+                    .as_ref()
+                    .is_none_or(|udp| udp.entry_points.is_empty())
+        }) {
+            return Err(TraefikError::HostConfig(format!(
+                "udp deployment {} of {} needs at least one entry point in udp.entry_points",
+                name, self.domain
+            )));
+        }
+        Ok(())
+    }
+
+    /// Requests carrying a selection cookie are routed by it, so a sticky
+    /// cookie of the same name anywhere on the host would send clients to the
+    /// wrong deployment
+    fn validate_sticky_cookies(&self) -> TraefikResult<()> {
+        let deployments = self
+            .deployments
+            .iter()
+            .chain(self.paths.iter().flat_map(|path| path.deployments.iter()));
+        let selections = self.selection.iter().chain(
+            deployments
+                .clone()
+                .filter_map(|(_, d)| d.selection.as_ref()),
+        );
+        let selection_cookies = selections
+            .filter_map(|selection| selection.with_cookie.as_ref())
+            .map(|with_cookie| with_cookie.name.as_str())
+            .collect::<HashSet<_>>();
+        for (name, deployment) in deployments {
+            let Some(cookie) = deployment
+                .sticky
+                .as_ref()
+                .and_then(|sticky| sticky.name.as_deref())
+            else {
+                continue;
+            };
+            if selection_cookies.contains(cookie) {
+                return Err(TraefikError::HostConfig(format!(
+                    "deployment {} of {} uses the selection cookie {} as its sticky cookie",
+                    name, self.domain, cookie
+                )));
+            }
+        }
         Ok(())
     }
 
@@ -216,7 +292,10 @@ impl HostConfig {
     pub fn get_host_name(&self) -> String {
         format!("host-{}", get_safe_key(&self.domain))
     }
-    pub async fn clean_etcd(&self, etcd: &mut StoreClient<Etcd>) -> TraefikResult<()> {
+    pub async fn clean_etcd<T: StoreClientActor>(
+        &self,
+        etcd: &mut StoreClient<T>,
+    ) -> TraefikResult<()> {
         let safe_name = self.get_host_name();
         let base_key = "traefik/http";
 
@@ -266,6 +345,7 @@ pub struct HostConfigBuilder {
     paths: HashMap<String, PathConfig>,
     middlewares: Vec<String>,
     forward_host: bool,
+    health_check: Option<HealthCheckConfig>,
 }
 
 impl HostConfigBuilder {
@@ -294,6 +374,11 @@ impl HostConfigBuilder {
         self
     }
 
+    pub fn health_check(mut self, health_check: HealthCheckConfig) -> Self {
+        self.health_check = Some(health_check);
+        self
+    }
+
     pub fn build(self) -> TraefikResult<HostConfig> {
         let host_config = HostConfig {
             domain: self.domain,
@@ -303,6 +388,7 @@ impl HostConfigBuilder {
             forward_host: self.forward_host,
             selection: None,
             variables: None,
+            health_check: self.health_check,
         };
         Ok(host_config)
     }
@@ -409,7 +495,9 @@ impl Validate for PathConfig {
 #[cfg(test)]
 mod tests {
     use crate::{
-        config::deployment::DeploymentConfigBuilder,
+        config::{
+            deployment::DeploymentConfigBuilder, selections::WithCookieConfig, sticky::StickyConfig,
+        },
         test_helpers::{create_test_resolver, create_test_template_context},
     };
 
@@ -557,4 +645,45 @@ mod tests {
         let validate_result = host.validate(&mut resolver, &context);
         assert!(validate_result.is_err());
     }
+
+    #[test]
+    fn test_validate_fails_when_sticky_cookie_is_a_selection_cookie() {
+        let sticky = |name: &str| StickyConfig {
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+        let host = HostConfigBuilder::default()
+            .domain("test.com".to_string())
+            .deployment(
+                "blue".to_string(),
+                DeploymentConfigBuilder::default()
+                    .ip_and_port("10.0.0.1".to_string(), 80)
+                    .weight(50)
+                    .sticky(sticky("blue_affinity"))
+                    .build(),
+            )
+            .deployment(
+                "green".to_string(),
+                DeploymentConfigBuilder::default()
+                    .ip_and_port("10.0.0.2".to_string(), 80)
+                    .weight(50)
+                    .selection(SelectionConfig {
+                        with_cookie: Some(WithCookieConfig {
+                            name: "green".to_string(),
+                            value: None,
+                        }),
+                        ..Default::default()
+                    })
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        assert!(host.validate(&mut resolver, &context).is_ok());
+
+        let mut host = host;
+        host.deployments.get_mut("blue").unwrap().sticky = Some(sticky("green"));
+        assert!(host.validate(&mut resolver, &context).is_err());
+    }
 }
@@ -121,6 +121,8 @@ pub enum MiddlewareType {
     BasicAuth,
     Compress,
     CircuitBreaker,
+    InFlightConn,
+    IpAllowList,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
@@ -169,6 +171,12 @@ pub struct MiddlewareConfig {
     /// The circuit breaker configuration
     #[serde(skip_serializing_if = "Option::is_none")]
     pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// The in flight connections configuration, tcp only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_flight_conn: Option<InFlightConnConfig>,
+    /// The ip allow list configuration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_allow_list: Option<IpAllowListConfig>,
 }
 
 // Add configuration structs for each middleware type
@@ -412,6 +420,58 @@ impl ToEtcdPairs for CircuitBreakerConfig {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct InFlightConnConfig {
+    /// The maximum number of simultaneous connections per client ip
+    pub amount: TemplateOr<u32>,
+}
+
+impl ToEtcdPairs for InFlightConnConfig {
+    fn to_etcd_pairs(
+        &self,
+        _base_key: &str,
+        resolver: &mut impl TemplateResolver,
+        context: &TemplateContext,
+    ) -> TraefikResult<Vec<EtcdPair>> {
+        let base_key = "inFlightConn";
+        Ok(vec![EtcdPair::new(
+            format!("{}/amount", base_key),
+            self.amount.resolve(resolver, context)?.to_string(),
+        )])
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct IpAllowListConfig {
+    /// The ips or ranges allowed to connect
+    pub source_range: Vec<TemplateOr<String>>,
+}
+
+impl ToEtcdPairs for IpAllowListConfig {
+    fn to_etcd_pairs(
+        &self,
+        _base_key: &str,
+        resolver: &mut impl TemplateResolver,
+        context: &TemplateContext,
+    ) -> TraefikResult<Vec<EtcdPair>> {
+        let base_key = "ipAllowList";
+        let mut pairs = vec![];
+        for (idx, range) in self.source_range.iter().enumerate() {
+            pairs.push(EtcdPair::new(
+                format!("{}/sourceRange/{}", base_key, idx),
+                range.resolve(resolver, context)?,
+            ));
+        }
+        Ok(pairs)
+    }
+}
+
 impl Default for MiddlewareConfig {
     fn default() -> Self {
         MiddlewareConfig {
@@ -426,6 +486,8 @@ impl Default for MiddlewareConfig {
             circuit_breaker: None,
             redirect_regex: None,
             redirect_scheme: None,
+            in_flight_conn: None,
+            ip_allow_list: None,
         }
     }
 }
@@ -442,6 +504,32 @@ impl MiddlewareConfig {
     pub fn set_protocol(&mut self, protocol: &str) {
         self.protocol = protocol.to_string();
     }
+
+    /// The configured sections traefik only supports on http routers
+    pub fn http_only_sections(&self) -> Vec<&'static str> {
+        [
+            ("headers", self.headers.is_some()),
+            ("forwardAuth", self.forward_auth.is_some()),
+            ("redirectRegex", self.redirect_regex.is_some()),
+            ("redirectScheme", self.redirect_scheme.is_some()),
+            ("stripPrefix", self.strip_prefix.is_some()),
+            ("rateLimit", self.rate_limit.is_some()),
+            ("basicAuth", self.basic_auth.is_some()),
+            ("compress", self.compress),
+            ("circuitBreaker", self.circuit_breaker.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(section, set)| set.then_some(section))
+        .collect()
+    }
+
+    /// The configured sections traefik only supports on tcp routers
+    pub fn tcp_only_sections(&self) -> Vec<&'static str> {
+        match self.in_flight_conn.is_some() {
+            true => vec!["inFlightConn"],
+            false => vec![],
+        }
+    }
 }
 
 /// Convert the middleware configuration to etcd pairs
@@ -518,6 +606,16 @@ impl ToEtcdPairs for MiddlewareConfig {
             pairs.extend(circuit_breaker.to_etcd_pairs(&base_key, resolver, context)?);
         }
 
+        if let Some(in_flight_conn) = &self.in_flight_conn {
+            debug!("adding in flight conn pairs for: {}", base_key);
+            pairs.extend(in_flight_conn.to_etcd_pairs(base_key, resolver, context)?);
+        }
+
+        if let Some(ip_allow_list) = &self.ip_allow_list {
+            debug!("adding ip allow list pairs for: {}", base_key);
+            pairs.extend(ip_allow_list.to_etcd_pairs(base_key, resolver, context)?);
+        }
+
         let prefixed_pairs = pairs
             .iter()
             .map(|pair| {
@@ -556,6 +654,26 @@ impl Validate for MiddlewareConfig {
             headers.validate(resolver, context)?;
         }
 
+        if self.protocol == "udp" {
+            return Err(TraefikError::MiddlewareConfig(format!(
+                "middleware {} cannot use the udp protocol, udp routers have no middlewares",
+                self.name
+            )));
+        }
+
+        let unsupported = match self.protocol.as_str() {
+            "tcp" => self.http_only_sections(),
+            _ => self.tcp_only_sections(),
+        };
+        if !unsupported.is_empty() {
+            return Err(TraefikError::MiddlewareConfig(format!(
+                "middleware {} uses {}, which {} routers do not support",
+                self.name,
+                unsupported.join(", "),
+                self.protocol
+            )));
+        }
+
         debug!("Validated middleware: {}", self.name);
         Ok(())
     }
@@ -982,6 +1100,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_middleware_tcp() {
+        let middleware = MiddlewareConfig {
+            name: "limit-db".to_string(),
+            protocol: "tcp".to_string(),
+            in_flight_conn: Some(InFlightConnConfig {
+                amount: TemplateOr::Static(10),
+            }),
+            ip_allow_list: Some(IpAllowListConfig {
+                source_range: vec![TemplateOr::Static("10.0.0.0/8".to_string())],
+            }),
+            ..create_base_middleware_config()
+        };
+
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        assert!(middleware.validate(&mut resolver, &context).is_ok());
+        let pairs = middleware
+            .to_etcd_pairs("test/tcp/middlewares/limit-db", &mut resolver, &context)
+            .unwrap();
+        assert_contains_pair(
+            &pairs,
+            "test/tcp/middlewares/limit-db/inFlightConn/amount 10",
+        );
+        assert_contains_pair(
+            &pairs,
+            "test/tcp/middlewares/limit-db/ipAllowList/sourceRange/0 10.0.0.0/8",
+        );
+    }
+
+    #[test]
+    fn test_middleware_rejects_sections_of_the_other_protocol() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let tcp_with_compress = MiddlewareConfig {
+            protocol: "tcp".to_string(),
+            compress: true,
+            ..create_base_middleware_config()
+        };
+        assert!(tcp_with_compress.validate(&mut resolver, &context).is_err());
+
+        let http_with_in_flight_conn = MiddlewareConfig {
+            in_flight_conn: Some(InFlightConnConfig {
+                amount: TemplateOr::Static(10),
+            }),
+            ..create_base_middleware_config()
+        };
+        assert!(http_with_in_flight_conn
+            .validate(&mut resolver, &context)
+            .is_err());
+    }
+
     #[cfg(test)]
     mod tests {
 
@@ -6,4 +6,5 @@ pub mod host;
 pub mod middleware;
 pub mod selections;
 pub mod services;
+pub mod sticky;
 pub mod traefik_config;
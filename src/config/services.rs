@@ -18,6 +18,7 @@ use super::deployment::{DeploymentConfig, DeploymentTarget};
 #[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
 #[cfg_attr(feature = "codegen", derive(ExportType))]
 #[export_type(rename_all = "camelCase", path = "generated/types")]
+#[serde(try_from = "RawServiceConfig")]
 pub struct ServiceConfig {
     #[serde(default)]
     pub name: String,
@@ -25,6 +26,64 @@ pub struct ServiceConfig {
     pub deployment: DeploymentConfig,
     #[serde(default)]
     pub pass_host_header: bool,
+    /// Split the traffic between other services instead of load balancing
+    /// over servers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weighted: Option<Vec<WeightedServiceConfig>>,
+}
+
+/// A service a weighted service sends a share of its traffic to
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct WeightedServiceConfig {
+    pub name: String,
+    /// The share of the traffic, relative to the other services
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// A service as written in the config file, a weighted service has no
+/// servers of its own and may leave out the target
+#[derive(Deserialize)]
+struct RawServiceConfig {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    pass_host_header: bool,
+    #[serde(default)]
+    weighted: Option<Vec<WeightedServiceConfig>>,
+    #[serde(flatten)]
+    deployment: serde_json::Map<String, serde_json::Value>,
+}
+
+impl TryFrom<RawServiceConfig> for ServiceConfig {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: RawServiceConfig) -> Result<Self, Self::Error> {
+        let mut deployment = raw.deployment;
+        let has_target = ["ip", "port", "servers", "service_name"]
+            .iter()
+            .any(|key| deployment.contains_key(*key));
+        if raw.weighted.is_some() && !has_target {
+            if let serde_json::Value::Object(target) =
+                serde_json::to_value(DeploymentTarget::default())?
+            {
+                deployment.extend(target);
+            }
+        }
+        Ok(ServiceConfig {
+            name: raw.name,
+            deployment: serde_json::from_value(serde_json::Value::Object(deployment))?,
+            pass_host_header: raw.pass_host_header,
+            weighted: raw.weighted,
+        })
+    }
 }
 
 impl Default for ServiceConfig {
@@ -33,6 +92,7 @@ impl Default for ServiceConfig {
             name: "redirector".to_string(),
             deployment: DeploymentConfig::default(),
             pass_host_header: true,
+            weighted: None,
         }
     }
 }
@@ -42,6 +102,12 @@ impl ServiceConfig {
         format!("service-{}", self.name)
     }
 
+    /// The traefik section the service is written to, `http`, `tcp` or
+    /// `udp`
+    pub fn router_protocol(&self) -> &'static str {
+        self.deployment.router_protocol()
+    }
+
     pub fn set_name(&mut self, name: &str) -> &mut Self {
         self.name = name.to_string();
         self
@@ -59,6 +125,7 @@ pub struct ServiceConfigBuilder {
     name: String,
     deployment: DeploymentConfig,
     pass_host_header: bool,
+    weighted: Option<Vec<WeightedServiceConfig>>,
 }
 
 impl ServiceConfigBuilder {
@@ -82,11 +149,17 @@ impl ServiceConfigBuilder {
         self
     }
 
+    pub fn weighted(mut self, weighted: Vec<WeightedServiceConfig>) -> Self {
+        self.weighted = Some(weighted);
+        self
+    }
+
     pub fn build(self) -> ServiceConfig {
         ServiceConfig {
             name: self.name,
             deployment: self.deployment,
             pass_host_header: self.pass_host_header,
+            weighted: self.weighted,
         }
     }
 }
@@ -104,29 +177,45 @@ impl ToEtcdPairs for ServiceConfig {
     fn to_etcd_pairs(
         &self,
         base_key: &str,
-        _resolver: &mut impl TemplateResolver,
-        _context: &TemplateContext,
+        resolver: &mut impl TemplateResolver,
+        context: &TemplateContext,
     ) -> TraefikResult<Vec<EtcdPair>> {
         // The base_key: `{prefix}/{protocol}`
         let mut pairs = Vec::new();
 
         let service_base_key = format!("{}/services/{}", base_key, self.name);
-        // Create the url
-        let (ip, port) = match &self.deployment.target {
-            DeploymentTarget::IpAndPort { ip, port } => (ip, port),
-            _ => {
-                return Err(TraefikError::ServiceConfig(format!(
-                    "Service {} requires an ip and port: {}",
-                    self.name, self.deployment.target
-                )))
+        if let Some(weighted) = &self.weighted {
+            return self.weighted_pairs(&service_base_key, weighted, resolver, context);
+        }
+        // Create the urls
+        let servers = self.deployment.target.servers();
+        if servers.is_empty() {
+            return Err(TraefikError::ServiceConfig(format!(
+                "Service {} requires an ip and port or servers: {}",
+                self.name, self.deployment.target
+            )));
+        }
+        if self.deployment.is_tcp() || self.deployment.is_udp() {
+            for (idx, server) in servers.iter().enumerate() {
+                pairs.push(EtcdPair::new(
+                    format!("{}/loadBalancer/servers/{}/address", service_base_key, idx),
+                    server.to_string(),
+                ));
             }
-        };
-        let url = format!("{}://{}:{}", self.deployment.protocol, ip, port);
-        // TODO: handle multiple hosts?
-        pairs.push(EtcdPair::new(
-            format!("{}/loadBalancer/servers/0/url", service_base_key),
-            url,
-        ));
+            return Ok(pairs);
+        }
+        for (idx, server) in servers.iter().enumerate() {
+            pairs.push(EtcdPair::new(
+                format!("{}/loadBalancer/servers/{}/url", service_base_key, idx),
+                format!("{}://{}", self.deployment.protocol, server),
+            ));
+            if let Some(weight) = server.weight {
+                pairs.push(EtcdPair::new(
+                    format!("{}/loadBalancer/servers/{}/weight", service_base_key, idx),
+                    weight.to_string(),
+                ));
+            }
+        }
         pairs.push(EtcdPair::new(
             format!(
                 "{}/loadBalancer/responseForwarding/flushInterval",
@@ -140,11 +229,54 @@ impl ToEtcdPairs for ServiceConfig {
                 self.pass_host_header.to_string(),
             ));
         }
+        if let Some(health_check) = &self.deployment.health_check {
+            pairs.extend(health_check.to_etcd_pairs(
+                &format!("{}/loadBalancer", service_base_key),
+                resolver,
+                context,
+            )?);
+        }
+        if let Some(sticky) = &self.deployment.sticky {
+            pairs.extend(sticky.to_etcd_pairs(
+                &format!("{}/loadBalancer", service_base_key),
+                resolver,
+                context,
+            )?);
+        }
 
         Ok(pairs)
     }
 }
 
+impl ServiceConfig {
+    /// The keys of a weighted service, its sticky cookie goes under
+    /// `weighted` rather than `loadBalancer`
+    fn weighted_pairs(
+        &self,
+        service_base_key: &str,
+        weighted: &[WeightedServiceConfig],
+        resolver: &mut impl TemplateResolver,
+        context: &TemplateContext,
+    ) -> TraefikResult<Vec<EtcdPair>> {
+        let base_key = format!("{}/weighted", service_base_key);
+        let mut pairs = Vec::new();
+        for (idx, service) in weighted.iter().enumerate() {
+            pairs.push(EtcdPair::new(
+                format!("{}/services/{}/name", base_key, idx),
+                service.name.clone(),
+            ));
+            pairs.push(EtcdPair::new(
+                format!("{}/services/{}/weight", base_key, idx),
+                service.weight.to_string(),
+            ));
+        }
+        if let Some(sticky) = &self.deployment.sticky {
+            pairs.extend(sticky.to_etcd_pairs(&base_key, resolver, context)?);
+        }
+        Ok(pairs)
+    }
+}
+
 impl Validate for ServiceConfig {
     fn validate(
         &self,
@@ -158,6 +290,41 @@ impl Validate for ServiceConfig {
         validate_is_alphanumeric(&self.name)?;
         self.deployment.validate(resolver, context)?;
 
+        if let Some(weighted) = &self.weighted {
+            if self.router_protocol() != "http" {
+                return Err(TraefikError::ServiceConfig(format!(
+                    "service {} cannot be weighted, only http services can",
+                    self.name
+                )));
+            }
+            if self.deployment.health_check.is_some() {
+                return Err(TraefikError::ServiceConfig(format!(
+                    "weighted service {} cannot set a health check, the services it splits between carry their own",
+                    self.name
+                )));
+            }
+            if weighted.is_empty() {
+                return Err(TraefikError::ServiceConfig(format!(
+                    "weighted service {} has no services",
+                    self.name
+                )));
+            }
+            for service in weighted {
+                if service.name == self.name {
+                    return Err(TraefikError::ServiceConfig(format!(
+                        "weighted service {} cannot send traffic to itself",
+                        self.name
+                    )));
+                }
+                if service.name.trim().is_empty() {
+                    return Err(TraefikError::ServiceConfig(format!(
+                        "weighted service {} names an empty service",
+                        self.name
+                    )));
+                }
+            }
+        }
+
         Ok(())
     }
 }
@@ -165,6 +332,7 @@ impl Validate for ServiceConfig {
 #[cfg(test)]
 mod tests {
     use crate::{
+        config::deployment::DeploymentProtocol,
         core::templating::TeraResolver,
         test_helpers::{assert_contains_pair, create_test_resolver, create_test_template_context},
     };
@@ -234,4 +402,125 @@ mod tests {
             "traefik/http/services/redirector/loadBalancer/passHostHeader true",
         );
     }
+
+    #[test]
+    fn test_tcp_service_config_to_etcd_pairs() {
+        let service = ServiceConfig {
+            deployment: DeploymentConfig::builder()
+                .ip_and_port("10.0.0.5".to_string(), 5432)
+                .protocol(DeploymentProtocol::Tcp)
+                .build(),
+            ..Default::default()
+        };
+        let mut resolver = TeraResolver::new().unwrap();
+        let context = TemplateContext::default();
+        let pairs = service
+            .to_etcd_pairs("traefik/tcp", &mut resolver, &context)
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![EtcdPair::new(
+                "traefik/tcp/services/redirector/loadBalancer/servers/0/address",
+                "10.0.0.5:5432"
+            )]
+        );
+        assert_eq!(service.router_protocol(), "tcp");
+    }
+
+    #[test]
+    fn test_weighted_service_writes_sticky_under_weighted() {
+        let service: ServiceConfig = serde_yaml::from_str(
+            r#"
+        name: split
+        weighted:
+          - name: blue
+            weight: 3
+          - name: green
+        sticky:
+          name: split_affinity
+          secure: true
+        "#,
+        )
+        .unwrap();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        assert!(service.validate(&mut resolver, &context).is_ok());
+        let pairs = service
+            .to_etcd_pairs("traefik/http", &mut resolver, &context)
+            .unwrap();
+        let base = "traefik/http/services/split/weighted";
+        assert_contains_pair(&pairs, &format!("{base}/services/0/name blue"));
+        assert_contains_pair(&pairs, &format!("{base}/services/0/weight 3"));
+        assert_contains_pair(&pairs, &format!("{base}/services/1/name green"));
+        assert_contains_pair(&pairs, &format!("{base}/services/1/weight 1"));
+        assert_contains_pair(&pairs, &format!("{base}/sticky/cookie/name split_affinity"));
+        assert_contains_pair(&pairs, &format!("{base}/sticky/cookie/secure true"));
+        assert!(!pairs
+            .iter()
+            .any(|pair| pair.key().contains("/loadBalancer/")));
+    }
+
+    #[test]
+    fn test_validate_weighted_service() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let weighted = |name: &str| WeightedServiceConfig {
+            name: name.to_string(),
+            weight: 1,
+        };
+
+        let empty = ServiceConfig::builder()
+            .name("split".to_string())
+            .weighted(vec![])
+            .build();
+        assert!(empty.validate(&mut resolver, &context).is_err());
+
+        let itself = ServiceConfig::builder()
+            .name("split".to_string())
+            .weighted(vec![weighted("split")])
+            .build();
+        assert!(itself.validate(&mut resolver, &context).is_err());
+
+        let tcp = ServiceConfig::builder()
+            .name("split".to_string())
+            .deployment(
+                DeploymentConfig::builder()
+                    .protocol(DeploymentProtocol::Tcp)
+                    .build(),
+            )
+            .weighted(vec![weighted("blue")])
+            .build();
+        assert!(tcp.validate(&mut resolver, &context).is_err());
+
+        let valid = ServiceConfig::builder()
+            .name("split".to_string())
+            .weighted(vec![weighted("blue"), weighted("green@file")])
+            .build();
+        assert!(valid.validate(&mut resolver, &context).is_ok());
+    }
+
+    #[test]
+    fn test_service_config_writes_health_check() {
+        let service: ServiceConfig = serde_yaml::from_str(
+            r#"
+        name: redirector
+        ip: redirector
+        port: 3000
+        health_check:
+          path: /ping
+          interval: 30s
+        "#,
+        )
+        .unwrap();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        assert!(service.validate(&mut resolver, &context).is_ok());
+        let pairs = service
+            .to_etcd_pairs("traefik/http", &mut resolver, &context)
+            .unwrap();
+        let base = "traefik/http/services/redirector/loadBalancer/healthCheck";
+        assert_contains_pair(&pairs, &format!("{base}/path /ping"));
+        assert_contains_pair(&pairs, &format!("{base}/interval 30s"));
+        assert_contains_pair(&pairs, &format!("{base}/timeout 5s"));
+    }
 }
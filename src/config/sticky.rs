@@ -0,0 +1,210 @@
+use crate::{
+    core::{
+        etcd_trait::{EtcdPair, ToEtcdPairs},
+        templating::{TemplateContext, TemplateResolver},
+        Validate,
+    },
+    error::{TraefikError, TraefikResult},
+};
+use export_type::ExportType;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Cookie affinity of a load balancer, traefik sends a client back to the
+/// server named in the cookie
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default, JsonSchema)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+#[serde(default)]
+pub struct StickyConfig {
+    /// The name of the cookie, traefik derives one from the service when
+    /// unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secure: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_only: Option<bool>,
+    /// `none`, `lax` or `strict`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub same_site: Option<String>,
+    /// The lifetime of the cookie in seconds, a session cookie when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl ToEtcdPairs for StickyConfig {
+    fn to_etcd_pairs(
+        &self,
+        base_key: &str,
+        _resolver: &mut impl TemplateResolver,
+        _context: &TemplateContext,
+    ) -> TraefikResult<Vec<EtcdPair>> {
+        // The base_key: `{service}/loadBalancer`
+        let base_key = format!("{}/sticky/cookie", base_key);
+        let mut pairs = vec![];
+        if let Some(name) = &self.name {
+            pairs.push(EtcdPair::new(format!("{}/name", base_key), name));
+        }
+        if let Some(secure) = self.secure {
+            pairs.push(EtcdPair::new(
+                format!("{}/secure", base_key),
+                secure.to_string(),
+            ));
+        }
+        if let Some(http_only) = self.http_only {
+            pairs.push(EtcdPair::new(
+                format!("{}/httpOnly", base_key),
+                http_only.to_string(),
+            ));
+        }
+        if let Some(same_site) = &self.same_site {
+            pairs.push(EtcdPair::new(format!("{}/sameSite", base_key), same_site));
+        }
+        if let Some(max_age) = self.max_age {
+            pairs.push(EtcdPair::new(
+                format!("{}/maxAge", base_key),
+                max_age.to_string(),
+            ));
+        }
+        if let Some(path) = &self.path {
+            pairs.push(EtcdPair::new(format!("{}/path", base_key), path));
+        }
+        // An empty cookie still turns stickiness on, with traefik's defaults
+        if pairs.is_empty() {
+            pairs.push(EtcdPair::new(base_key, "true"));
+        }
+        Ok(pairs)
+    }
+}
+
+impl Validate for StickyConfig {
+    fn validate(
+        &self,
+        _resolver: &mut impl TemplateResolver,
+        _context: &TemplateContext,
+    ) -> TraefikResult<()> {
+        if let Some(name) = &self.name {
+            let is_token = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+            if name.is_empty() || !name.chars().all(is_token) {
+                return Err(TraefikError::StickyConfig(format!(
+                    "cookie name must be letters, digits, -, _ or ., got {:?}",
+                    name
+                )));
+            }
+        }
+
+        if let Some(same_site) = &self.same_site {
+            if !["none", "lax", "strict"].contains(&same_site.as_str()) {
+                return Err(TraefikError::StickyConfig(format!(
+                    "same_site must be none, lax or strict, got {}",
+                    same_site
+                )));
+            }
+            // Browsers drop SameSite=None cookies that are not secure
+            if same_site == "none" && self.secure != Some(true) {
+                return Err(TraefikError::StickyConfig(
+                    "same_site none requires secure: true".to_string(),
+                ));
+            }
+        }
+
+        if self
+            .path
+            .as_ref()
+            .is_some_and(|path| !path.starts_with('/'))
+        {
+            return Err(TraefikError::StickyConfig(format!(
+                "path must start with /: {}",
+                self.path.as_deref().unwrap_or_default()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::{
+        assert_contains_pair, create_test_resolver, create_test_template_context,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_sticky_config_to_etcd_pairs() {
+        let sticky: StickyConfig = serde_yaml::from_str(
+            r#"
+        name: affinity
+        secure: true
+        http_only: true
+        same_site: lax
+        max_age: 3600
+        path: /app
+        "#,
+        )
+        .unwrap();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        assert!(sticky.validate(&mut resolver, &context).is_ok());
+
+        let pairs = sticky
+            .to_etcd_pairs(
+                "test/http/services/app/loadBalancer",
+                &mut resolver,
+                &context,
+            )
+            .unwrap();
+        let base = "test/http/services/app/loadBalancer/sticky/cookie";
+        for pair in [
+            "name affinity",
+            "secure true",
+            "httpOnly true",
+            "sameSite lax",
+            "maxAge 3600",
+            "path /app",
+        ] {
+            assert_contains_pair(&pairs, &format!("{base}/{pair}"));
+        }
+    }
+
+    #[test]
+    fn test_empty_sticky_config_turns_stickiness_on() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let pairs = StickyConfig::default()
+            .to_etcd_pairs(
+                "test/http/services/app/loadBalancer",
+                &mut resolver,
+                &context,
+            )
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![EtcdPair::new(
+                "test/http/services/app/loadBalancer/sticky/cookie",
+                "true"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_sticky_config_is_invalid_with_unknown_same_site() {
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let sticky = StickyConfig {
+            same_site: Some("sometimes".to_string()),
+            ..Default::default()
+        };
+        assert!(sticky.validate(&mut resolver, &context).is_err());
+        let sticky = StickyConfig {
+            same_site: Some("none".to_string()),
+            ..Default::default()
+        };
+        assert!(sticky.validate(&mut resolver, &context).is_err());
+    }
+}
@@ -1,25 +1,42 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 
 use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
 use export_type::ExportType;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, trace};
+use tracing::{debug, trace};
 
 use crate::{
     core::{
-        client::StoreClient,
+        client::{StoreClient, StoreClientActor, StoreKind, TxnOp},
         etcd_trait::{EtcdPair, ToEtcdPairs},
+        file_provider,
+        import::{self, ImportedConfig},
+        lock::LockConfig,
+        meta_key,
+        ownership::{self, Ownership},
+        prune::PrunePlan,
         rules::{add_deployment_rules, get_sorted_deployments, RouterRule},
         templating::{TemplateContext, TemplateOr, TemplateResolver, TeraResolver},
         Validate,
     },
     error::{TraefikError, TraefikResult},
-    features::etcd::{self, Etcd},
+    features::etcd,
 };
 
+#[cfg(feature = "drift")]
+use crate::core::drift::{self, DriftReport, LastApplied};
+#[cfg(feature = "consul")]
+use crate::features::consul::ConsulConfig;
+#[cfg(feature = "redis")]
+use crate::features::redis::RedisConfig;
+
 use super::{
-    deployment::{DeploymentConfig, DeploymentProtocol, DeploymentTarget},
+    deployment::{DeploymentConfig, DeploymentTarget},
     entry_points::EntryPointsConfig,
     host::{HostConfig, PathConfig},
     middleware::MiddlewareConfig,
@@ -83,9 +100,22 @@ pub struct TraefikConfig {
     pub description: Option<String>,
     #[serde(default = "default_rule_prefix")]
     pub rule_prefix: String,
+    /// The team that owns the keys this config writes, configs sharing a
+    /// `rule_prefix` set distinct owners so they only apply and clean their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// The store to publish to when `--store` is not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store: Option<StoreKind>,
     #[cfg(feature = "etcd")]
     #[serde(default = "default_etcd_config")]
     pub etcd: etcd::EtcdConfig,
+    #[cfg(feature = "consul")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consul: Option<ConsulConfig>,
+    #[cfg(feature = "redis")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redis: Option<RedisConfig>,
     #[serde(default)]
     pub hosts: Vec<HostConfig>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -96,6 +126,9 @@ pub struct TraefikConfig {
     pub services: Option<HashMap<String, ServiceConfig>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub entry_points: Option<EntryPointsConfig>,
+    /// The lock taken by commands that write the rule prefix
+    #[serde(default)]
+    pub lock: LockConfig,
 }
 
 fn default_etcd_config() -> etcd::EtcdConfig {
@@ -130,6 +163,54 @@ impl TraefikConfig {
         }
         Ok(context)
     }
+
+    /// Read a config file, rendering it as a template with the variables of
+    /// `variable_files`, each a JSON object
+    ///
+    /// A file that does not render as a template is parsed as it is.
+    pub fn from_file(
+        config_file: &Path,
+        variable_files: &[impl AsRef<Path>],
+    ) -> TraefikResult<TraefikConfig> {
+        let config = std::fs::read_to_string(config_file).unwrap_or_default();
+        if config.is_empty() {
+            return Err(TraefikError::ParsingError(eyre!(
+                "Config file {} is empty",
+                config_file.display()
+            )));
+        }
+        let mut config_ctx = tera::Context::new();
+        for variable_file in variable_files.iter() {
+            let file = std::fs::File::open(variable_file)?;
+            let variables: HashMap<String, serde_json::Value> = serde_json::from_reader(file)?;
+            for (key, value) in variables.iter() {
+                debug!("Adding variable: {} = {:#?}", key, value);
+                config_ctx.insert(key, value);
+            }
+        }
+
+        match tera::Tera::one_off(&config, &config_ctx, false) {
+            Ok(rendered_config) => {
+                trace!("Rendered config: {}", rendered_config);
+                serde_yaml::from_str(&rendered_config)
+                    .map_err(|e| TraefikError::ParsingError(eyre!("Parse error: {e}")))
+            }
+            Err(_e) => Ok(serde_yaml::from_str(&config)?),
+        }
+    }
+
+    /// Compare the keys this config writes with the keys `old` writes
+    ///
+    /// Both sides are rendered under this config's `rule_prefix`, so configs
+    /// for different prefixes, like staging and prod, compare by content.
+    pub fn diff_against(&self, old: &TraefikConfig) -> TraefikResult<etcd::EtcdDiff> {
+        let pairs = |config: &TraefikConfig| -> TraefikResult<Vec<EtcdPair>> {
+            let mut resolver = config.resolver()?;
+            let context = config.context()?;
+            config.to_etcd_pairs(&self.rule_prefix, &mut resolver, &context)
+        };
+        Ok(etcd::EtcdDiff::create(&pairs(self)?, &pairs(old)?))
+    }
 }
 
 impl TraefikConfig {
@@ -171,7 +252,7 @@ impl ToEtcdPairs for TraefikConfig {
                 let mut service = service.clone();
                 service.set_name(service_name);
                 debug!("Adding global service: {}", service_name);
-                let service_base_key = format!("{}/http", base_key);
+                let service_base_key = format!("{}/{}", base_key, service.router_protocol());
                 let service_pairs = service.to_etcd_pairs(&service_base_key, resolver, &context)?;
                 pairs.extend(service_pairs.clone());
                 rule_set.extend(service_pairs.iter().cloned());
@@ -198,7 +279,8 @@ impl ToEtcdPairs for TraefikConfig {
         // // TODO: add middleware before deployment rules, within the scope of a deployment
         for (name, middleware) in self.middlewares.clone().iter_mut() {
             middleware.set_name(name);
-            let middleware_base_key = format!("{}/http/middlewares/{}", base_key, name);
+            let middleware_base_key =
+                format!("{}/{}/middlewares/{}", base_key, middleware.protocol, name);
             let new_rules = middleware.to_etcd_pairs(&middleware_base_key, resolver, &context)?;
             debug!("New rules middleware rules: {:?}", new_rules);
             for new_rule in new_rules.iter().cloned() {
@@ -228,6 +310,16 @@ impl TraefikConfig {
         let context = self.context()?;
         self.validate(&mut resolver, &context)
     }
+
+    /// Render the configuration as a traefik file provider dynamic config,
+    /// without touching the store
+    pub fn to_file_provider(&self) -> TraefikResult<serde_json::Value> {
+        let mut resolver = self.resolver()?;
+        let context = self.context()?;
+        self.validate(&mut resolver, &context)?;
+        let pairs = self.to_etcd_pairs(&self.rule_prefix, &mut resolver, &context)?;
+        Ok(file_provider::to_file_provider(&pairs, &self.rule_prefix))
+    }
 }
 
 impl Validate for TraefikConfig {
@@ -237,6 +329,14 @@ impl Validate for TraefikConfig {
         context: &TemplateContext,
     ) -> TraefikResult<()> {
         let mut validation_context = context.clone();
+        if self
+            .owner
+            .as_deref()
+            .is_some_and(|owner| owner.trim().is_empty())
+        {
+            return Err(TraefikError::ConfigError("owner must not be empty".into()));
+        }
+
         // Validate services
         debug!("Validating services");
         if let Some(services) = &self.services {
@@ -280,34 +380,224 @@ impl Validate for TraefikConfig {
 }
 
 impl TraefikConfig {
-    pub fn validate_middleware_references(&self, _host: &HostConfig) -> TraefikResult<()> {
-        // Validate host middleware references
-        // for middleware in host.middlewares.iter() {
-        //     self.validate_middleware_references_in_host(middleware)?;
-        // }
+    /// The middlewares and services a deployment uses must exist for its
+    /// protocol: tcp routers take neither http middlewares nor http services
+    pub fn validate_middleware_references(&self, host: &HostConfig) -> TraefikResult<()> {
+        let root = host
+            .deployments
+            .iter()
+            .map(|(name, deployment)| (name, deployment, None));
+        let paths = host.paths.iter().flat_map(|path| {
+            path.deployments
+                .iter()
+                .map(move |(name, deployment)| (name, deployment, Some(path)))
+        });
+        for (name, deployment, path) in root.chain(paths) {
+            let middlewares = host
+                .middlewares
+                .iter()
+                .chain(path.iter().flat_map(|path| path.middlewares.iter()))
+                .chain(deployment.middlewares.iter().flatten());
+            if deployment.is_udp() {
+                if let Some(middleware_name) = middlewares.into_iter().next() {
+                    return Err(TraefikError::MiddlewareConfig(format!(
+                        "udp deployment {} of {} cannot use middleware {}, udp routers have no middlewares",
+                        name, host.domain, middleware_name
+                    )));
+                }
+                continue;
+            }
+            for middleware_name in middlewares {
+                let Some(middleware) = self.middlewares.get(middleware_name) else {
+                    continue;
+                };
+                let unsupported = match deployment.is_tcp() {
+                    true => middleware.http_only_sections(),
+                    false => middleware.tcp_only_sections(),
+                };
+                if !unsupported.is_empty() {
+                    return Err(TraefikError::MiddlewareConfig(format!(
+                        "{} deployment {} of {} cannot use middleware {}, {} is not supported there",
+                        deployment.protocol,
+                        name,
+                        host.domain,
+                        middleware_name,
+                        unsupported.join(", ")
+                    )));
+                }
+            }
+
+            if let DeploymentTarget::Service { service_name } = &deployment.target {
+                let service = self
+                    .services
+                    .as_ref()
+                    .and_then(|services| services.get(service_name));
+                if service.is_some_and(|service| {
+                    service.router_protocol() != deployment.router_protocol()
+                }) {
+                    return Err(TraefikError::ServiceConfig(format!(
+                        "{} deployment {} of {} cannot use service {} of another protocol",
+                        deployment.protocol, name, host.domain, service_name
+                    )));
+                }
+            }
+        }
         Ok(())
     }
-
-    // fn validate_middleware_references_in_host(&self, middleware_name: &str) -> TraefikResult<()> {
-    //     if !self.middlewares.contains_key(middleware_name) {
-    //         return Err(TraefikError::MiddlewareConfig(format!(
-    //             "middleware {} not found",
-    //             middleware_name
-    //         )));
-    //     }
-    //     Ok(())
-    // }
 }
 
 impl TraefikConfig {
-    pub async fn clean_etcd(&self, client: &StoreClient<Etcd>) -> TraefikResult<()> {
-        client.delete_with_prefix(self.rule_prefix.as_str()).await?;
+    /// Delete the keys held by the config's owner, or every unowned key
+    /// when it has none
+    pub async fn clean_etcd<T: StoreClientActor>(
+        &self,
+        client: &StoreClient<T>,
+    ) -> TraefikResult<()> {
+        client
+            .with_lock(
+                &self.rule_prefix,
+                &self.lock,
+                Self::write_owned_pairs(
+                    client,
+                    &self.rule_prefix,
+                    self.owner.as_deref(),
+                    &[],
+                    true,
+                    false,
+                ),
+            )
+            .await?;
         Ok(())
     }
 
-    pub async fn apply_to_etcd(
+    /// Compare the config, what its owner last applied and the live store
+    #[cfg(feature = "drift")]
+    pub async fn drift<T: StoreClientActor>(
+        &self,
+        client: &StoreClient<T>,
+    ) -> TraefikResult<DriftReport> {
+        let mut resolver = self.resolver()?;
+        let context = self.context()?;
+        self.validate(&mut resolver, &context)?;
+        let pairs = self.to_etcd_pairs(&self.rule_prefix, &mut resolver, &context)?;
+
+        let owner = self.owner.as_deref();
+        let last_applied = match client
+            .get(drift::last_applied_key(&self.rule_prefix, owner))
+            .await
+        {
+            Ok(kv) => LastApplied::decode(&kv.value)?,
+            Err(TraefikError::NotFound(_)) => {
+                return Err(TraefikError::NotFound(format!(
+                    "no apply to '{}' was recorded{}, apply once to record a base",
+                    self.rule_prefix,
+                    owner.map_or_else(String::new, |owner| format!(" for '{owner}'"))
+                )))
+            }
+            Err(e) => return Err(e),
+        };
+
+        let snapshot = client.snapshot(self.rule_prefix.as_str()).await?;
+        let markers = client
+            .snapshot(ownership::markers_prefix(&self.rule_prefix))
+            .await?;
+        let ownership = Ownership::new(&self.rule_prefix, markers.kvs);
+        let live_pairs: Vec<EtcdPair> = snapshot.kvs.into_iter().map(Into::into).collect();
+        // Keys of other owners only matter where this config writes
+        let known: HashSet<&str> = pairs
+            .iter()
+            .chain(last_applied.pairs.iter())
+            .map(|pair| pair.key())
+            .collect();
+        let live_scope = live_pairs
+            .iter()
+            .filter(|pair| known.contains(pair.key()) || ownership.owner_of(pair.key()) == owner)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Ok(DriftReport {
+            applied_at: last_applied.applied_at,
+            drifts: drift::detect(&pairs, &last_applied.pairs, &live_scope),
+        })
+    }
+
+    /// Find the keys held by the config's owner that the config no longer
+    /// generates, and the references that would dangle without them
+    pub async fn prune_plan<T: StoreClientActor>(
+        &self,
+        client: &StoreClient<T>,
+    ) -> TraefikResult<PrunePlan> {
+        let mut resolver = self.resolver()?;
+        let context = self.context()?;
+        self.validate(&mut resolver, &context)?;
+        let pairs = self.to_etcd_pairs(&self.rule_prefix, &mut resolver, &context)?;
+
+        let snapshot = client.snapshot(self.rule_prefix.as_str()).await?;
+        let markers = client
+            .snapshot(ownership::markers_prefix(&self.rule_prefix))
+            .await?;
+        let ownership = Ownership::new(&self.rule_prefix, markers.kvs);
+        let live_pairs: Vec<EtcdPair> = snapshot.kvs.into_iter().map(Into::into).collect();
+        let held = ownership.held_by(&live_pairs, self.owner.as_deref());
+        Ok(PrunePlan::new(
+            &self.rule_prefix,
+            snapshot.revision,
+            &pairs,
+            &live_pairs,
+            &held,
+        ))
+    }
+
+    /// Delete the orphan keys of `plan`, provided they are still the orphans
+    ///
+    /// The plan is made again once the lock is held, and the keys are only
+    /// deleted if that finds the same orphans and nothing under the rule
+    /// prefix changed until they are.
+    pub async fn prune<T: StoreClientActor>(
+        &self,
+        client: &StoreClient<T>,
+        plan: &PrunePlan,
+    ) -> TraefikResult<usize> {
+        if plan.orphan_keys().is_empty() {
+            return Ok(0);
+        }
+        let written = async {
+            let current = self.prune_plan(client).await?;
+            let orphans = current.orphan_keys();
+            if orphans != plan.orphan_keys() {
+                return Err(TraefikError::Conflict(format!(
+                    "the orphan keys under '{}' changed since the plan was made, run prune again",
+                    self.rule_prefix
+                )));
+            }
+            let markers = client
+                .snapshot(ownership::markers_prefix(&self.rule_prefix))
+                .await?;
+            let ownership = Ownership::new(&self.rule_prefix, markers.kvs);
+            let diff = etcd::EtcdDiff {
+                removed: orphans,
+                ..Default::default()
+            };
+            let mut ops = Self::txn_ops(&diff, true);
+            ops.extend(ownership.marker_ops(&diff, self.owner.as_deref(), true));
+            client
+                .commit(
+                    &self.rule_prefix,
+                    current.revision,
+                    &meta_key(&self.rule_prefix, APPLY_GUARD_KEY),
+                    ops,
+                )
+                .await?;
+            Ok(diff.removed.len())
+        };
+        client
+            .with_lock(&self.rule_prefix, &self.lock, written)
+            .await
+    }
+
+    pub async fn apply_to_etcd<T: StoreClientActor>(
         &mut self,
-        client: &StoreClient<Etcd>,
+        client: &StoreClient<T>,
         dry_run: bool,
         show_rules: bool,
         should_clean: bool,
@@ -347,113 +637,283 @@ impl TraefikConfig {
             return Ok(());
         }
 
-        if should_clean {
-            self.clean_etcd(client).await?;
-        }
-
-        let mut client = client.actor.client.clone();
-
-        for pair in pairs.iter() {
-            debug!("applying: {:#?}", pair.to_string());
-            match client.put(pair.key(), pair.value(), None).await {
-                Ok(_kv) => {}
-                Err(e) => error!("error: {:?}", e),
-            }
+        let diff = client
+            .with_lock(
+                &self.rule_prefix,
+                &self.lock,
+                Self::write_owned_pairs(
+                    client,
+                    &self.rule_prefix,
+                    self.owner.as_deref(),
+                    &pairs,
+                    should_clean,
+                    false,
+                ),
+            )
+            .await?;
+
+        println!(
+            "Applied '{}': {} added, {} modified, {} removed, {} unchanged",
+            self.rule_prefix,
+            diff.added.len(),
+            diff.modified.len(),
+            if should_clean { diff.removed.len() } else { 0 },
+            diff.unchanged.len()
+        );
+        if !should_clean && !diff.removed.is_empty() {
+            println!(
+                "{} stale keys were left in place, use --clean to remove them",
+                diff.removed.len()
+            );
         }
 
         Ok(())
     }
-}
 
-impl TraefikConfig {
-    pub fn parse_etcd_to_traefik_config(pairs: Vec<EtcdPair>) -> TraefikResult<TraefikConfig> {
-        let mut config_map: HashMap<String, HostConfig> = HashMap::new();
-
-        for pair in pairs {
-            let key = pair.key();
-            let value = pair.value();
+    /// Restore the keys held by the config's owner to how they were at `revision`
+    ///
+    /// Only the keys the owner held at that revision are restored. See
+    /// [`TraefikConfig::replace_prefix`] for how the rollback is written.
+    pub async fn rollback_to_revision<T: StoreClientActor>(
+        &self,
+        client: &StoreClient<T>,
+        revision: i64,
+        dry_run: bool,
+        detailed: bool,
+    ) -> TraefikResult<etcd::EtcdDiff> {
+        let target = client
+            .snapshot_at(self.rule_prefix.as_str(), revision)
+            .await?;
+        let markers = client
+            .snapshot_at(ownership::markers_prefix(&self.rule_prefix), revision)
+            .await?;
+        let target_pairs: Vec<EtcdPair> = target.kvs.into_iter().map(Into::into).collect();
+        let target_pairs = Ownership::new(&self.rule_prefix, markers.kvs)
+            .held_by(&target_pairs, self.owner.as_deref());
+        let diff = Self::replace_prefix(
+            client,
+            &self.rule_prefix,
+            self.owner.as_deref(),
+            &target_pairs,
+            &self.lock,
+            dry_run,
+            detailed,
+        )
+        .await?;
+        if !dry_run {
+            println!(
+                "Rolled back '{}' to revision {}: {} added, {} modified, {} removed, {} unchanged",
+                self.rule_prefix,
+                revision,
+                diff.added.len(),
+                diff.modified.len(),
+                diff.removed.len(),
+                diff.unchanged.len()
+            );
+        }
+        Ok(diff)
+    }
 
-            if !key.starts_with("traefik/http/services/host-") {
-                continue;
+    /// Make the keys `owner` holds under `rule_prefix` exactly match `pairs`
+    ///
+    /// The writes are committed like an `apply --clean`, under the lock and
+    /// only if the prefix did not change since it was read, and they become
+    /// what `owner` last applied. Objects held by other owners are left in
+    /// place and reported. The diff against the state it replaced is printed.
+    pub async fn replace_prefix<T: StoreClientActor>(
+        client: &StoreClient<T>,
+        rule_prefix: &str,
+        owner: Option<&str>,
+        pairs: &[EtcdPair],
+        lock_config: &LockConfig,
+        dry_run: bool,
+        detailed: bool,
+    ) -> TraefikResult<etcd::EtcdDiff> {
+        let written = async {
+            let snapshot = client.snapshot(rule_prefix).await?;
+            let markers = client
+                .snapshot(ownership::markers_prefix(rule_prefix))
+                .await?;
+            let live_pairs: Vec<EtcdPair> = snapshot.kvs.into_iter().map(Into::into).collect();
+            let (pairs, foreign) =
+                Ownership::new(rule_prefix, markers.kvs).without_foreign(pairs, &live_pairs, owner);
+            for conflict in foreign.iter() {
+                println!("Leaving {conflict} in place");
             }
+            Self::write_owned_pairs(client, rule_prefix, owner, &pairs, true, dry_run).await
+        };
+        let diff = match dry_run {
+            true => written.await?,
+            false => client.with_lock(rule_prefix, lock_config, written).await?,
+        };
+        diff.display(detailed);
+        Ok(diff)
+    }
 
-            let service_parts = key.split('/').collect::<Vec<_>>();
-            debug!("Service parts: {:?}", service_parts);
+    /// Diff `pairs` against the keys under `rule_prefix` held by `owner` and,
+    /// unless this is a dry run, commit the writes if the prefix did not
+    /// change since it was read, refusing to write to an object another
+    /// owner holds
+    ///
+    /// The ownership markers of the written and cleaned keys and what
+    /// `owner` last applied are updated in the same transactions.
+    async fn write_owned_pairs<T: StoreClientActor>(
+        client: &StoreClient<T>,
+        rule_prefix: &str,
+        owner: Option<&str>,
+        pairs: &[EtcdPair],
+        should_clean: bool,
+        dry_run: bool,
+    ) -> TraefikResult<etcd::EtcdDiff> {
+        let snapshot = client.snapshot(rule_prefix).await?;
+        let markers = client
+            .snapshot(ownership::markers_prefix(rule_prefix))
+            .await?;
+        let ownership = Ownership::new(rule_prefix, markers.kvs);
+        let live_pairs: Vec<EtcdPair> = snapshot.kvs.into_iter().map(Into::into).collect();
+
+        let conflicts = ownership.conflicts(pairs, &live_pairs, owner);
+        if !conflicts.is_empty() {
+            let report = conflicts
+                .iter()
+                .map(|conflict| format!("  {conflict}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            return Err(TraefikError::Conflict(format!(
+                "{} would overwrite objects of other owners in '{rule_prefix}':\n{report}",
+                owner.map_or_else(
+                    || "a config without an owner".to_string(),
+                    |o| format!("'{o}'")
+                )
+            )));
+        }
 
-            // Extract the service name portion after 'host-'
-            let service_name = match service_parts.get(3) {
-                Some(name) => {
-                    debug!("Found service name: {}", name);
-                    name.strip_prefix("host-").unwrap_or(name)
-                }
-                None => {
-                    debug!("No service name found in key: {}", key);
-                    continue;
-                }
-            };
+        let held = ownership.held_by(&live_pairs, owner);
+        debug!(
+            "{} of {} keys at revision {} are held by {:?}",
+            held.len(),
+            live_pairs.len(),
+            snapshot.revision,
+            owner
+        );
+        let diff = etcd::EtcdDiff::create(pairs, &held);
+        let mut ops = Self::txn_ops(&diff, should_clean);
+        ops.extend(ownership.marker_ops(&diff, owner, should_clean));
+        #[cfg(feature = "drift")]
+        ops.extend(
+            Self::last_applied_op(client, rule_prefix, owner, pairs, &diff, should_clean).await?,
+        );
 
-            debug!("service_name: {}", service_name);
+        if !dry_run && !ops.is_empty() {
+            client
+                .commit(
+                    rule_prefix,
+                    snapshot.revision,
+                    &meta_key(rule_prefix, APPLY_GUARD_KEY),
+                    ops,
+                )
+                .await?;
+        }
+        Ok(diff)
+    }
 
-            // Parse the domain and deployment parts
-            let mut parts = service_name.split('-').collect::<Vec<_>>();
-            if parts.is_empty() {
-                continue;
-            }
+    /// The write recording `pairs` as what `owner` last applied, if it changed
+    #[cfg(feature = "drift")]
+    async fn last_applied_op<T: StoreClientActor>(
+        client: &StoreClient<T>,
+        rule_prefix: &str,
+        owner: Option<&str>,
+        pairs: &[EtcdPair],
+        diff: &etcd::EtcdDiff,
+        should_clean: bool,
+    ) -> TraefikResult<Option<TxnOp>> {
+        let key = drift::last_applied_key(rule_prefix, owner);
+        let stored = match client.get(key.as_str()).await {
+            Ok(kv) => LastApplied::decode(&kv.value).ok(),
+            Err(TraefikError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        // Keys a previous apply wrote and this one leaves in place stay in
+        // the base of `drift`, keys added by hand do not
+        let mut applied = pairs.to_vec();
+        if let (false, Some(stored)) = (should_clean, &stored) {
+            let removed: HashSet<&str> = diff.removed.iter().map(|pair| pair.key()).collect();
+            applied.extend(
+                stored
+                    .pairs
+                    .iter()
+                    .filter(|pair| removed.contains(pair.key()))
+                    .cloned(),
+            );
+        }
+        let last_applied = LastApplied::new(&applied);
+        if stored.map(|stored| stored.hash) == Some(last_applied.hash.clone()) {
+            return Ok(None);
+        }
+        Ok(Some(TxnOp::put(key, last_applied.encode()?)))
+    }
 
-            // Last part should be the deployment index, remove it
-            parts.pop();
-            // Second to last part should be the deployment color
-            let deployment_name = if let Some(color) = parts.pop() {
-                color.to_string()
-            } else {
-                continue;
-            };
+    /// Build the writes that move the live store to the desired config
+    ///
+    /// Only added and modified keys are put, so routers that did not change
+    /// are never touched. Stale keys are deleted one by one rather than
+    /// clearing the whole prefix, which keeps traefik serving throughout.
+    fn txn_ops(diff: &etcd::EtcdDiff, should_clean: bool) -> Vec<TxnOp> {
+        let mut deletes: Vec<&str> = if should_clean {
+            diff.removed.iter().map(|pair| pair.key()).collect()
+        } else {
+            vec![]
+        };
+        deletes.sort();
+
+        let mut puts: Vec<&EtcdPair> = diff
+            .added
+            .iter()
+            .chain(diff.modified.iter().map(|(_old, new)| new))
+            .collect();
+        puts.sort_by(|a, b| a.key().cmp(b.key()));
+
+        deletes
+            .into_iter()
+            .map(TxnOp::delete)
+            .chain(
+                puts.into_iter()
+                    .map(|pair| TxnOp::put(pair.key(), pair.value())),
+            )
+            .collect()
+    }
+}
 
-            // Reconstruct domain from remaining parts
-            let domain = parts.join(".");
-
-            // Get or create host config
-            let host_config = config_map
-                .entry(domain.clone())
-                .or_insert_with(|| HostConfig {
-                    domain: domain.clone(),
-                    deployments: HashMap::new(),
-                    paths: Vec::new(),
-                    middlewares: Vec::new(),
-                    selection: None,
-                    forward_host: true,
-                    variables: None,
-                });
-
-            // Parse deployment if this is a URL entry
-            if key.ends_with("/url") {
-                let url_parts: Vec<&str> = value.split("://").collect();
-                if url_parts.len() == 2 {
-                    let host_port: Vec<&str> = url_parts[1].split(':').collect();
-                    let ip = host_port[0].to_string();
-                    let port = host_port
-                        .get(1)
-                        .and_then(|p| p.trim_end_matches("/").parse().ok())
-                        .unwrap_or(80);
-
-                    let deployment = DeploymentConfig::builder()
-                        .name(deployment_name.clone())
-                        .ip_and_port(ip, port)
-                        .protocol(DeploymentProtocol::Http)
-                        .weight(100)
-                        .build();
-
-                    host_config
-                        .deployments
-                        .insert(deployment_name.clone(), deployment);
-                }
-            }
-        }
+/// Held for the duration of an apply that does not fit in one transaction
+const APPLY_GUARD_KEY: &str = "apply-guard";
 
-        Ok(TraefikConfig {
-            hosts: config_map.into_values().collect(),
-            ..Default::default()
-        })
+impl TraefikConfig {
+    /// Rebuild a config from the keys under the default `traefik` prefix
+    pub fn parse_etcd_to_traefik_config(pairs: Vec<EtcdPair>) -> TraefikResult<TraefikConfig> {
+        Ok(import::import(&default_rule_prefix(), &pairs).config)
+    }
+
+    /// Rebuild a config from the live keys under `self.rule_prefix`
+    ///
+    /// The imported config keeps this config's store settings and owner.
+    pub async fn import<T: StoreClientActor>(
+        &self,
+        client: &StoreClient<T>,
+    ) -> TraefikResult<ImportedConfig> {
+        let pairs = client
+            .get_with_prefix(self.rule_prefix.as_str())
+            .await?
+            .into_iter()
+            .map(EtcdPair::from)
+            .collect::<Vec<_>>();
+        let mut imported = import::import(&self.rule_prefix, &pairs);
+        imported.config = TraefikConfig {
+            hosts: imported.config.hosts,
+            middlewares: imported.config.middlewares,
+            services: imported.config.services,
+            ..self.clone()
+        };
+        Ok(imported)
     }
 }
 
@@ -494,12 +954,19 @@ impl TraefikConfig {
             description: Some("test".to_string()),
             #[cfg(feature = "etcd")]
             etcd: Default::default(),
+            store: None,
+            #[cfg(feature = "consul")]
+            consul: None,
+            #[cfg(feature = "redis")]
+            redis: None,
             middlewares: HashMap::new(),
             hosts: host_configs,
             rule_prefix: "test".to_string(),
+            owner: None,
             services: None,
             entry_points: None,
             variables: None,
+            lock: LockConfig::default(),
         }
     }
 }
@@ -580,6 +1047,7 @@ impl TraefikConfig {
             DeploymentTarget::IpAndPort { ip, port } => {
                 format!("{}:{}", ip, port)
             }
+            DeploymentTarget::Servers { .. } => deployment.target.to_string(),
         };
         let service_node = graph.add_node(service_name.clone());
         graph.add_edge(into_service_node, service_node, "service".to_string());
@@ -654,9 +1122,13 @@ mod tests {
             host::HostConfigBuilder,
         },
         core::templating::TemplateOr,
-        test_helpers::{create_test_resolver, create_test_template_context},
+        features::{memory::MemoryStore, KeyValue},
+        test_helpers::{create_test_config, create_test_resolver, create_test_template_context},
     };
 
+    #[cfg(feature = "drift")]
+    use crate::core::drift::DriftKind;
+
     use super::*;
 
     #[test]
@@ -727,6 +1199,36 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_validate_middleware_protocols_of_tcp_deployments() {
+        let config_str = r#"
+        middlewares:
+          limit:
+            protocol: tcp
+            in_flight_conn:
+              amount: 10
+          compress-all:
+            compress: true
+        hosts:
+          - domain: db.example.com
+            deployments:
+              primary:
+                ip: 10.0.0.5
+                port: 5432
+                protocol: tcp
+                middlewares:
+                  - limit
+        "#;
+        let mut config: TraefikConfig = serde_yaml::from_str(config_str).unwrap();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        assert!(config.validate(&mut resolver, &context).is_ok());
+
+        config.hosts[0].middlewares.push("compress-all".to_string());
+        let err = config.validate(&mut resolver, &context).unwrap_err();
+        assert!(err.to_string().contains("compress"), "{err}");
+    }
+
     #[test]
     fn test_valid_with_complex_config() {
         let config_str = r#"
@@ -888,4 +1390,563 @@ mod tests {
         assert!(host.deployments.contains_key("blue"));
         assert!(host.deployments.contains_key("green"));
     }
+
+    #[tokio::test]
+    async fn test_apply_to_memory_store() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new());
+        config
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+
+        let mut resolver = config.resolver().unwrap();
+        let context = config.context().unwrap();
+        let pairs = config
+            .to_etcd_pairs(&config.rule_prefix, &mut resolver, &context)
+            .unwrap();
+        let stored = client
+            .get_with_prefix(config.rule_prefix.as_str())
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), pairs.len());
+
+        config.clean_etcd(&client).await.unwrap();
+        let stored = client
+            .get_with_prefix(config.rule_prefix.as_str())
+            .await
+            .unwrap();
+        assert!(stored.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_apply_to_memory_store_dry_run_writes_nothing() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new());
+        config
+            .apply_to_etcd(&client, true, false, false)
+            .await
+            .unwrap();
+        let stored = client.get_with_prefix("").await.unwrap();
+        assert!(stored.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_apply_with_clean_removes_only_stale_keys() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::from_pairs(&[
+            EtcdPair::new("test/http/routers/stale/rule", "Host(`stale`)"),
+            EtcdPair::new("other/key", "kept"),
+        ]));
+        config
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+
+        assert!(client.get("test/http/routers/stale/rule").await.is_err());
+        assert!(client.get("other/key").await.is_ok());
+        assert!(client.get(meta_key("test", APPLY_GUARD_KEY)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_without_clean_keeps_existing_keys() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::from_pairs(&[EtcdPair::new(
+            "test/http/routers/stale/rule",
+            "Host(`stale`)",
+        )]));
+        config
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+        assert!(client.get("test/http/routers/stale/rule").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_apply_in_bounded_transactions() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new().with_max_txn_ops(4));
+        config
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+
+        let stored = client.get_with_prefix("test").await.unwrap();
+        assert!(stored.len() > 4);
+        assert!(client.get(meta_key("test", APPLY_GUARD_KEY)).await.is_err());
+    }
+
+    #[test]
+    fn test_txn_ops_only_writes_changes() {
+        let desired = vec![
+            EtcdPair::new("test/added", "1"),
+            EtcdPair::new("test/modified", "new"),
+            EtcdPair::new("test/unchanged", "same"),
+        ];
+        let live = vec![
+            EtcdPair::new("test/modified", "old"),
+            EtcdPair::new("test/unchanged", "same"),
+            EtcdPair::new("test/stale", "gone"),
+        ];
+        let diff = etcd::EtcdDiff::create(&desired, &live);
+        assert_eq!(
+            TraefikConfig::txn_ops(&diff, false),
+            vec![
+                TxnOp::put("test/added", "1"),
+                TxnOp::put("test/modified", "new")
+            ]
+        );
+        assert_eq!(
+            TraefikConfig::txn_ops(&diff, true),
+            vec![
+                TxnOp::delete("test/stale"),
+                TxnOp::put("test/added", "1"),
+                TxnOp::put("test/modified", "new")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reapply_writes_nothing() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new());
+        config
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        let before = client.history("test").await.unwrap();
+        config
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        // Only the lock outside of the prefix was written
+        let after = client.history("test").await.unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_restores_previous_revision() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new());
+        config
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        let good = client.snapshot("test").await.unwrap();
+
+        client
+            .put("test/http/routers/bad/rule", "Host(`bad`)", None)
+            .await
+            .unwrap();
+        client.delete(good.kvs[0].key.clone()).await.unwrap();
+
+        let diff = config
+            .rollback_to_revision(&client, good.revision, false, false)
+            .await
+            .unwrap();
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.removed.len(), 1);
+        let pairs = |kvs: Vec<KeyValue>| kvs.into_iter().map(EtcdPair::from).collect::<Vec<_>>();
+        let restored = client.snapshot("test").await.unwrap();
+        assert_eq!(pairs(restored.kvs), pairs(good.kvs));
+    }
+
+    #[tokio::test]
+    async fn test_rollback_dry_run_writes_nothing() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new());
+        client.put("other/key", "value", None).await.unwrap();
+        let empty = client.snapshot("test").await.unwrap();
+        config
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        let before = client.snapshot("test").await.unwrap();
+
+        assert!(config
+            .rollback_to_revision(&client, 0, true, false)
+            .await
+            .is_err());
+        let diff = config
+            .rollback_to_revision(&client, empty.revision, true, false)
+            .await
+            .unwrap();
+        assert_eq!(diff.removed.len(), before.kvs.len());
+        assert_eq!(
+            client.snapshot("test").await.unwrap().revision,
+            before.revision
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restore_backup_replaces_prefix() {
+        use crate::core::backup::Backup;
+
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new());
+        config
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        let backup = Backup::new("test", client.snapshot("test").await.unwrap());
+
+        client.delete_with_prefix("test").await.unwrap();
+        client
+            .put("test/http/routers/bad/rule", "Host(`bad`)", None)
+            .await
+            .unwrap();
+        let lock = LockConfig::default();
+        let diff = TraefikConfig::replace_prefix(
+            &client,
+            "test",
+            None,
+            &backup.pairs,
+            &lock,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(diff.added.len(), backup.pairs.len());
+        assert_eq!(diff.removed.len(), 1);
+        let restored = Backup::new("test", client.snapshot("test").await.unwrap());
+        assert_eq!(restored.hash, backup.hash);
+    }
+
+    fn owned_config(owner: &str, domain: &str) -> TraefikConfig {
+        let mut config = create_test_config(None);
+        config.owner = Some(owner.to_string());
+        config.hosts.truncate(1);
+        config.hosts[0].domain = domain.to_string();
+        config
+    }
+
+    #[tokio::test]
+    async fn test_owners_apply_and_clean_side_by_side() {
+        let client = StoreClient::new(MemoryStore::new());
+        let mut team_a = owned_config("team-a", "a.example.com");
+        let mut team_b = owned_config("team-b", "b.example.com");
+        // The shared middlewares are team-a's
+        team_b.middlewares.clear();
+        team_b.hosts[0].paths[0].middlewares.clear();
+        team_a
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        let a_keys = client.get_keys("test").await.unwrap().len();
+        // A clean apply of another owner leaves team-a's keys alone
+        team_b
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        let both_keys = client.get_keys("test").await.unwrap().len();
+        assert!(both_keys > a_keys);
+
+        team_b.clean_etcd(&client).await.unwrap();
+        assert_eq!(client.get_keys("test").await.unwrap().len(), a_keys);
+        let markers = client
+            .get_keys(ownership::markers_prefix("test"))
+            .await
+            .unwrap();
+        assert_eq!(markers.len(), a_keys);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_and_restore_respect_owners() {
+        use crate::core::backup::Backup;
+
+        let client = StoreClient::new(MemoryStore::new());
+        client.put("other/key", "value", None).await.unwrap();
+        let empty = client.snapshot("test").await.unwrap();
+        let mut team_a = owned_config("team-a", "a.example.com");
+        let mut team_b = owned_config("team-b", "b.example.com");
+        team_b.middlewares.clear();
+        team_b.hosts[0].paths[0].middlewares.clear();
+        team_a
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        team_b
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        let backup = Backup::new("test", client.snapshot("test").await.unwrap());
+        let b_keys = client.get_keys("test").await.unwrap().len();
+
+        // Rolling team-a back to before its apply removes only its keys,
+        // their markers, and what it last applied
+        team_a
+            .rollback_to_revision(&client, empty.revision, false, false)
+            .await
+            .unwrap();
+        let markers = client
+            .get_with_prefix(ownership::markers_prefix("test"))
+            .await
+            .unwrap();
+        assert!(markers.iter().all(|kv| kv.value == b"team-b"));
+        assert_eq!(client.get_keys("test").await.unwrap().len(), markers.len());
+        #[cfg(feature = "drift")]
+        {
+            let last_applied = client
+                .get(drift::last_applied_key("test", Some("team-a")))
+                .await
+                .unwrap();
+            assert!(LastApplied::decode(&last_applied.value)
+                .unwrap()
+                .pairs
+                .is_empty());
+        }
+
+        // A restore by team-a takes its keys back and leaves team-b's alone
+        let lock = LockConfig::default();
+        let diff = TraefikConfig::replace_prefix(
+            &client,
+            "test",
+            Some("team-a"),
+            &backup.pairs,
+            &lock,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(diff.removed.is_empty());
+        assert_eq!(client.get_keys("test").await.unwrap().len(), b_keys);
+        let markers = client
+            .get_with_prefix(ownership::markers_prefix("test"))
+            .await
+            .unwrap();
+        assert_eq!(markers.len(), b_keys);
+        #[cfg(feature = "drift")]
+        assert!(team_a.drift(&client).await.unwrap().drifts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_apply_refuses_objects_of_another_owner() {
+        let client = StoreClient::new(MemoryStore::new());
+        let mut team_a = owned_config("team-a", "a.example.com");
+        team_a
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+        let before = client.get_keys("").await.unwrap().len();
+
+        let mut team_b = owned_config("team-b", "a.example.com");
+        let err = team_b
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TraefikError::Conflict(_)));
+        assert!(err.to_string().contains("is owned by 'team-a'"), "{err}");
+        assert_eq!(client.get_keys("").await.unwrap().len(), before);
+    }
+
+    #[tokio::test]
+    async fn test_owner_adopts_unowned_keys() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new());
+        config
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+
+        config.owner = Some("team-a".to_string());
+        config
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+        let keys = client.get_keys("test").await.unwrap().len();
+        let markers = client
+            .get_keys(ownership::markers_prefix("test"))
+            .await
+            .unwrap();
+        assert_eq!(markers.len(), keys);
+    }
+
+    #[tokio::test]
+    async fn test_prune_removes_only_orphans() {
+        let config = create_test_config(None);
+        let mut applied = config.clone();
+        applied.hosts[0].domain = "old.example.com".to_string();
+        let client = StoreClient::new(MemoryStore::new());
+        applied
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+        config
+            .clone()
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+
+        let plan = config.prune_plan(&client).await.unwrap();
+        assert!(!plan.orphans.is_empty());
+        assert!(plan
+            .orphans
+            .keys()
+            .all(|object| object.contains("old-example-com")));
+        assert!(plan.dangling.is_empty());
+        let pruned = config.prune(&client, &plan).await.unwrap();
+        assert_eq!(pruned, plan.orphan_keys().len());
+
+        let plan = config.prune_plan(&client).await.unwrap();
+        assert!(plan.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_agent_keys_are_left_alone() {
+        use std::time::Duration;
+
+        use crate::core::agent::{Agent, AgentRouter, HealthProbe};
+
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new());
+        config
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+        let agent = Agent {
+            rule_prefix: "test".to_string(),
+            service: "registered".to_string(),
+            url: "http://10.0.0.1:8080".to_string(),
+            router: Some(AgentRouter {
+                rule: "Host(`registered.example.com`)".to_string(),
+                entry_points: vec!["websecure".to_string()],
+                tls: false,
+            }),
+            ttl: 60,
+            interval: Duration::from_secs(10),
+            probe: HealthProbe::None,
+        };
+        client.register(&agent).await.unwrap();
+
+        let plan = config.prune_plan(&client).await.unwrap();
+        assert!(plan.is_empty());
+        #[cfg(feature = "drift")]
+        assert!(config.drift(&client).await.unwrap().drifts.is_empty());
+        config
+            .apply_to_etcd(&client, false, false, true)
+            .await
+            .unwrap();
+        for pair in agent.pairs() {
+            assert!(client.get(pair.key()).await.is_ok());
+        }
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    async fn test_prune_through_redis() {
+        use crate::features::redis::tests::mock_redis;
+
+        let config = create_test_config(None);
+        let mut applied = config.clone();
+        applied.hosts[0].domain = "old.example.com".to_string();
+        let (redis, _) = mock_redis().await;
+        let client = StoreClient::new(redis);
+        applied
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+        config
+            .clone()
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+
+        let plan = config.prune_plan(&client).await.unwrap();
+        assert!(!plan.orphans.is_empty());
+        let pruned = config.prune(&client, &plan).await.unwrap();
+        assert_eq!(pruned, plan.orphan_keys().len());
+        assert!(config.prune_plan(&client).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_prune_refuses_a_stale_plan() {
+        let config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::from_pairs(&[EtcdPair::new(
+            "test/http/routers/stale/rule",
+            "Host(`stale`)",
+        )]));
+        let plan = config.prune_plan(&client).await.unwrap();
+        client
+            .put("test/http/routers/other/rule", "Host(`other`)", None)
+            .await
+            .unwrap();
+        let result = config.prune(&client, &plan).await;
+        assert!(matches!(result, Err(TraefikError::Conflict(_))));
+        assert!(client.get("test/http/routers/stale/rule").await.is_ok());
+    }
+
+    #[cfg(feature = "drift")]
+    #[tokio::test]
+    async fn test_drift_tells_local_and_live_changes_apart() {
+        let mut config = create_test_config(None);
+        let client = StoreClient::new(MemoryStore::new());
+        assert!(matches!(
+            config.drift(&client).await,
+            Err(TraefikError::NotFound(_))
+        ));
+        config
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+        let report = config.drift(&client).await.unwrap();
+        assert!(report.drifts.is_empty());
+
+        client
+            .put("test/http/routers/manual/rule", "Host(`manual`)", None)
+            .await
+            .unwrap();
+        config.hosts[0].domain = "renamed.example.com".to_string();
+        let report = config.drift(&client).await.unwrap();
+        assert!(report.count(DriftKind::Local) > 0);
+        assert_eq!(report.count(DriftKind::Live), 1);
+        assert!(report.has_live_changes());
+
+        // Applying without --clean keeps the stale keys in the base, the
+        // manual key is still reported
+        config
+            .apply_to_etcd(&client, false, false, false)
+            .await
+            .unwrap();
+        let report = config.drift(&client).await.unwrap();
+        assert_eq!(report.count(DriftKind::Live), 1);
+        assert_eq!(report.count(DriftKind::Conflict), 0);
+    }
+
+    #[test]
+    fn test_diff_of_one_template_with_two_variable_files() {
+        let write = |content: &str| {
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            std::io::Write::write_all(&mut file, content.as_bytes()).unwrap();
+            file
+        };
+        let template = write(
+            r#"
+        rule_prefix: "{{ prefix }}"
+        hosts:
+            - domain: app.example.com
+              deployments:
+                blue:
+                    ip: "{{ ip }}"
+                    port: 80
+        "#,
+        );
+        let staging = write(r#"{"prefix": "staging", "ip": "10.0.0.1"}"#);
+        let prod = write(r#"{"prefix": "prod", "ip": "10.1.0.1"}"#);
+
+        let old = TraefikConfig::from_file(template.path(), &[staging.path()]).unwrap();
+        let new = TraefikConfig::from_file(template.path(), &[prod.path()]).unwrap();
+        assert_eq!(new.rule_prefix, "prod");
+
+        // Both sides are rendered under the new prefix, only the ip differs
+        let diff = new.diff_against(&old).unwrap();
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.modified.len(), 1);
+        let (old_pair, new_pair) = &diff.modified[0];
+        assert!(old_pair.key().starts_with("prod/"));
+        assert_eq!(old_pair.value(), "http://10.0.0.1:80");
+        assert_eq!(new_pair.value(), "http://10.1.0.1:80");
+        assert!(!new.diff_against(&new).unwrap().has_changes());
+    }
 }
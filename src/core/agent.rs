@@ -0,0 +1,323 @@
+use std::{future::Future, time::Duration};
+
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+use crate::error::{TraefikError, TraefikResult};
+
+use super::{
+    client::{StoreClient, StoreClientActor},
+    etcd_trait::EtcdPair,
+    ownership::marker_key,
+};
+
+/// The owner agents mark their keys with, so `apply --clean`, `prune` and
+/// `drift` of a config leave them alone
+pub const AGENT_OWNER: &str = "traefikctl-agent";
+
+/// How a registering agent decides whether its service is healthy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthProbe {
+    /// Always healthy, the registration lives as long as the agent
+    None,
+    /// A shell command that exits successfully while the service is healthy
+    Command(String),
+    /// A url that answers with a 2xx status while the service is healthy
+    #[cfg(feature = "http-probe")]
+    Http(String),
+}
+
+impl HealthProbe {
+    /// Run the probe, counting it as failed when it takes longer than `timeout`
+    pub async fn check(&self, timeout: Duration) -> bool {
+        match self {
+            HealthProbe::None => true,
+            HealthProbe::Command(command) => {
+                let status = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .kill_on_drop(true)
+                    .status();
+                match tokio::time::timeout(timeout, status).await {
+                    Ok(Ok(status)) => status.success(),
+                    Ok(Err(e)) => {
+                        warn!("Health command '{}' failed to run: {}", command, e);
+                        false
+                    }
+                    Err(_) => {
+                        warn!("Health command '{}' timed out", command);
+                        false
+                    }
+                }
+            }
+            #[cfg(feature = "http-probe")]
+            HealthProbe::Http(url) => {
+                let response = match reqwest::Client::builder().timeout(timeout).build() {
+                    Ok(client) => client.get(url).send().await,
+                    Err(e) => Err(e),
+                };
+                match response {
+                    Ok(response) => response.status().is_success(),
+                    Err(e) => {
+                        warn!("Health check of '{}' failed: {}", url, e);
+                        false
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The router an agent registers along with its server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentRouter {
+    pub rule: String,
+    pub entry_points: Vec<String>,
+    pub tls: bool,
+}
+
+/// A server an agent keeps registered under a lease while its probe passes
+#[derive(Debug, Clone)]
+pub struct Agent {
+    pub rule_prefix: String,
+    /// The traefik service the server belongs to, also the router name
+    pub service: String,
+    /// The url traefik forwards to
+    pub url: String,
+    pub router: Option<AgentRouter>,
+    /// How long the registration outlives the agent, in seconds
+    pub ttl: i64,
+    /// How often the probe runs and the lease is renewed
+    pub interval: Duration,
+    pub probe: HealthProbe,
+}
+
+impl Agent {
+    pub fn validate(&self) -> TraefikResult<()> {
+        if self.service.is_empty() || self.service.contains('/') {
+            return Err(TraefikError::ConfigError(format!(
+                "invalid service name '{}'",
+                self.service
+            )));
+        }
+        if self.url.is_empty() {
+            return Err(TraefikError::ConfigError("the server url is empty".into()));
+        }
+        if self.ttl <= 0 {
+            return Err(TraefikError::ConfigError("the ttl must be positive".into()));
+        }
+        if self.interval.is_zero() || self.interval >= Duration::from_secs(self.ttl as u64) {
+            return Err(TraefikError::ConfigError(format!(
+                "the interval must be shorter than the ttl of {}s",
+                self.ttl
+            )));
+        }
+        Ok(())
+    }
+
+    /// The key of this agent's server url
+    ///
+    /// Servers are indexed by a hash of their url, so agents for the same
+    /// service register side by side and a restarted agent reuses its entry.
+    pub fn server_key(&self) -> String {
+        let digest = Sha256::digest(self.url.as_bytes());
+        let index = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+        format!(
+            "{}/http/services/{}/loadBalancer/servers/{}/url",
+            self.rule_prefix, self.service, index
+        )
+    }
+
+    /// The ownership markers of the agent's keys
+    fn markers(&self) -> Vec<EtcdPair> {
+        self.pairs()
+            .iter()
+            .map(|pair| EtcdPair::new(marker_key(&self.rule_prefix, pair.key()), AGENT_OWNER))
+            .collect()
+    }
+
+    /// Every key the agent keeps alive
+    pub fn pairs(&self) -> Vec<EtcdPair> {
+        let mut pairs = vec![EtcdPair::new(self.server_key(), self.url.clone())];
+        if let Some(router) = &self.router {
+            let base = format!("{}/http/routers/{}", self.rule_prefix, self.service);
+            pairs.push(EtcdPair::new(format!("{base}/rule"), router.rule.clone()));
+            pairs.push(EtcdPair::new(
+                format!("{base}/service"),
+                self.service.clone(),
+            ));
+            for (i, entry_point) in router.entry_points.iter().enumerate() {
+                pairs.push(EtcdPair::new(
+                    format!("{base}/entryPoints/{i}"),
+                    entry_point.clone(),
+                ));
+            }
+            if router.tls {
+                pairs.push(EtcdPair::new(format!("{base}/tls"), "true"));
+            }
+        }
+        pairs
+    }
+}
+
+impl<T> StoreClient<T>
+where
+    T: StoreClientActor + Send + Sync,
+{
+    /// Write the agent's keys and their ownership markers, or renew their
+    /// leases when they already exist
+    pub async fn register(&self, agent: &Agent) -> TraefikResult<()> {
+        for pair in agent.markers().into_iter().chain(agent.pairs()) {
+            self.put_or_touch(pair.key(), pair.value(), Some(agent.ttl))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Remove the agent's server, the router is left to expire with its
+    /// lease as other agents of the service may still be renewing it
+    pub async fn deregister(&self, agent: &Agent) -> TraefikResult<()> {
+        self.delete(agent.server_key()).await?;
+        self.delete(marker_key(&agent.rule_prefix, &agent.server_key()))
+            .await?;
+        Ok(())
+    }
+
+    /// Keep the agent registered while its probe passes, until `shutdown`
+    /// completes
+    ///
+    /// A failing probe stops the renewals, so traefik drops the server once
+    /// the ttl runs out. Store errors are logged and retried on the next
+    /// interval rather than ending the agent.
+    pub async fn run_agent(
+        &self,
+        agent: &Agent,
+        shutdown: impl Future<Output = ()>,
+    ) -> TraefikResult<()> {
+        agent.validate()?;
+        tokio::pin!(shutdown);
+        let mut healthy = None;
+        loop {
+            let passed = agent.probe.check(agent.interval).await;
+            if healthy != Some(passed) {
+                match passed {
+                    true => info!("'{}' is healthy, registering {}", agent.service, agent.url),
+                    false => warn!(
+                        "'{}' is unhealthy, {} expires within {}s",
+                        agent.service, agent.url, agent.ttl
+                    ),
+                }
+                healthy = Some(passed);
+            }
+            if passed {
+                match self.register(agent).await {
+                    Ok(()) => debug!("Renewed the registration of {}", agent.url),
+                    Err(e) => warn!("Failed to register {}: {}", agent.url, e),
+                }
+            }
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = tokio::time::sleep(agent.interval) => {}
+            }
+        }
+        info!("Deregistering {}", agent.url);
+        self.deregister(agent).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::memory::MemoryStore;
+
+    use super::*;
+
+    fn agent(probe: HealthProbe) -> Agent {
+        Agent {
+            rule_prefix: "traefik".to_string(),
+            service: "api".to_string(),
+            url: "http://10.0.0.1:8080".to_string(),
+            router: Some(AgentRouter {
+                rule: "Host(`api.example.com`)".to_string(),
+                entry_points: vec!["websecure".to_string()],
+                tls: true,
+            }),
+            ttl: 10,
+            interval: Duration::from_millis(20),
+            probe,
+        }
+    }
+
+    #[test]
+    fn test_server_key_is_stable_per_url() {
+        let a = agent(HealthProbe::None);
+        let mut b = a.clone();
+        assert_eq!(a.server_key(), b.server_key());
+        b.url = "http://10.0.0.2:8080".to_string();
+        assert_ne!(a.server_key(), b.server_key());
+        assert!(a
+            .server_key()
+            .starts_with("traefik/http/services/api/loadBalancer/servers/"));
+    }
+
+    #[test]
+    fn test_validate_requires_interval_below_ttl() {
+        let mut a = agent(HealthProbe::None);
+        assert!(a.validate().is_ok());
+        a.interval = Duration::from_secs(10);
+        assert!(a.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_agent_registers_until_shutdown() {
+        let client = StoreClient::new(MemoryStore::new());
+        let a = agent(HealthProbe::Command("true".to_string()));
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let running = {
+            let client = client.clone();
+            let a = a.clone();
+            tokio::spawn(async move {
+                client
+                    .run_agent(&a, async {
+                        let _ = stopped.await;
+                    })
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let kv = client.get(a.server_key()).await.unwrap();
+        assert_eq!(kv.value, a.url.as_bytes());
+        let kv = client.get("traefik/http/routers/api/rule").await.unwrap();
+        assert_eq!(kv.value, b"Host(`api.example.com`)");
+
+        let marker = client
+            .get(marker_key("traefik", &a.server_key()))
+            .await
+            .unwrap();
+        assert_eq!(marker.value, AGENT_OWNER.as_bytes());
+
+        stop.send(()).unwrap();
+        running.await.unwrap().unwrap();
+        assert!(client.get(a.server_key()).await.is_err());
+        assert!(client
+            .get(marker_key("traefik", &a.server_key()))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slow_probe_fails() {
+        let probe = HealthProbe::Command("sleep 5".to_string());
+        assert!(!probe.check(Duration::from_millis(50)).await);
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_agent_does_not_register() {
+        let client = StoreClient::new(MemoryStore::new());
+        let a = agent(HealthProbe::Command("false".to_string()));
+        client
+            .run_agent(&a, tokio::time::sleep(Duration::from_millis(60)))
+            .await
+            .unwrap();
+        assert!(client.get_keys("traefik").await.unwrap().is_empty());
+    }
+}
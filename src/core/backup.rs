@@ -0,0 +1,160 @@
+use std::{collections::HashSet, path::Path};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{TraefikError, TraefikResult};
+
+use super::{client::StoreSnapshot, etcd_trait::EtcdPair};
+
+/// The version of the backup file format written by this build
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// Every key and value under a rule prefix at a single store revision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub rule_prefix: String,
+    /// The store revision the keys were read at
+    pub revision: i64,
+    pub created_at: DateTime<Utc>,
+    /// See [`content_hash`]
+    pub hash: String,
+    /// Sorted by key
+    pub pairs: Vec<EtcdPair>,
+}
+
+impl Backup {
+    pub fn new(rule_prefix: &str, snapshot: StoreSnapshot) -> Self {
+        let mut pairs: Vec<EtcdPair> = snapshot.kvs.into_iter().map(Into::into).collect();
+        pairs.sort_by(|a, b| a.key().cmp(b.key()));
+        Self {
+            version: BACKUP_FORMAT_VERSION,
+            rule_prefix: rule_prefix.to_string(),
+            revision: snapshot.revision,
+            created_at: Utc::now(),
+            hash: content_hash(&pairs),
+            pairs,
+        }
+    }
+
+    /// Read and validate a backup file
+    pub fn read(path: &Path) -> TraefikResult<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let backup: Backup = serde_json::from_str(&content)?;
+        backup.validate()?;
+        Ok(backup)
+    }
+
+    pub fn write(&self, path: &Path) -> TraefikResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Check the backup was written by a compatible version, was not
+    /// modified since, and only holds keys under its rule prefix
+    pub fn validate(&self) -> TraefikResult<()> {
+        if self.version != BACKUP_FORMAT_VERSION {
+            return Err(TraefikError::ConfigError(format!(
+                "unsupported backup version {}, expected {}",
+                self.version, BACKUP_FORMAT_VERSION
+            )));
+        }
+        let hash = content_hash(&self.pairs);
+        if hash != self.hash {
+            return Err(TraefikError::ConfigError(format!(
+                "backup hash mismatch, expected {} but the content hashes to {}",
+                self.hash, hash
+            )));
+        }
+        let mut keys = HashSet::new();
+        for pair in self.pairs.iter() {
+            if !pair.key().starts_with(&self.rule_prefix) {
+                return Err(TraefikError::ConfigError(format!(
+                    "backup key '{}' is not under '{}'",
+                    pair.key(),
+                    self.rule_prefix
+                )));
+            }
+            if !keys.insert(pair.key()) {
+                return Err(TraefikError::ConfigError(format!(
+                    "backup key '{}' appears more than once",
+                    pair.key()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A `sha256:` digest of the pairs, in the order given
+pub fn content_hash(pairs: &[EtcdPair]) -> String {
+    let mut hasher = Sha256::new();
+    for pair in pairs {
+        // Length prefixes keep `a` + `bc` and `ab` + `c` apart
+        for part in [pair.key(), pair.value()] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part.as_bytes());
+        }
+    }
+    format!("sha256:{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::KeyValue;
+
+    use super::*;
+
+    fn snapshot() -> StoreSnapshot {
+        let kv = |key: &str, value: &str| KeyValue {
+            key: key.as_bytes().to_vec(),
+            value: value.as_bytes().to_vec(),
+        };
+        StoreSnapshot {
+            revision: 42,
+            kvs: vec![
+                kv("traefik/http/routers/b/rule", "Host(`b`) && Path(`/a b`)"),
+                kv("traefik/http/routers/a/rule", "Host(`a`)\n"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_backup_round_trip() {
+        let backup = Backup::new("traefik", snapshot());
+        assert_eq!(backup.revision, 42);
+        assert_eq!(backup.pairs[0].key(), "traefik/http/routers/a/rule");
+
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        backup.write(tmpfile.path()).unwrap();
+        let read = Backup::read(tmpfile.path()).unwrap();
+        assert_eq!(read, backup);
+        assert_eq!(read.pairs[0].value(), "Host(`a`)\n");
+    }
+
+    #[test]
+    fn test_validate_rejects_modified_content() {
+        let mut backup = Backup::new("traefik", snapshot());
+        backup.pairs[0] = EtcdPair::new("traefik/http/routers/a/rule", "Host(`evil`)");
+        assert!(backup.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_foreign_keys_and_versions() {
+        let mut backup = Backup::new("other", snapshot());
+        assert!(backup.validate().is_err());
+        backup = Backup::new("traefik", snapshot());
+        backup.version = BACKUP_FORMAT_VERSION + 1;
+        assert!(backup.validate().is_err());
+    }
+
+    #[test]
+    fn test_content_hash_separates_fields() {
+        let a = content_hash(&[EtcdPair::new("a", "bc")]);
+        let b = content_hash(&[EtcdPair::new("ab", "c")]);
+        assert_ne!(a, b);
+        assert!(a.starts_with("sha256:"));
+    }
+}
@@ -1,6 +1,218 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
 use async_trait::async_trait;
+use export_type::ExportType;
+use futures_util::Stream;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use tracing::{debug, warn};
+
+use crate::{
+    error::{TraefikError, TraefikResult},
+    features::KeyValue,
+};
+
+/// The key-value store backend used to publish the traefik configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::Type))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// A live etcd cluster
+    #[default]
+    Etcd,
+    /// An in-memory store, optionally seeded from a file
+    Memory,
+    /// A consul agent's KV store
+    Consul,
+    /// A redis server
+    Redis,
+}
+
+/// A consistent read of every key under a prefix, along with the store
+/// revision the read was served at
+#[derive(Debug, Clone, Default)]
+pub struct StoreSnapshot {
+    pub revision: i64,
+    pub kvs: Vec<KeyValue>,
+}
+
+/// A single write within a store transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxnOp {
+    /// A put with a ttl expires like one made by [`StoreClientActor::put`]
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl: Option<i64>,
+    },
+    Delete {
+        key: Vec<u8>,
+    },
+}
+
+impl TxnOp {
+    pub fn put(key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Self {
+        Self::Put {
+            key: key.into(),
+            value: value.into(),
+            ttl: None,
+        }
+    }
+
+    pub fn put_with_ttl(key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>, ttl: i64) -> Self {
+        Self::Put {
+            key: key.into(),
+            value: value.into(),
+            ttl: Some(ttl),
+        }
+    }
+
+    pub fn delete(key: impl Into<Vec<u8>>) -> Self {
+        Self::Delete { key: key.into() }
+    }
+
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Self::Put { key, .. } | Self::Delete { key } => key,
+        }
+    }
+}
+
+/// A condition that must hold for a store transaction to be committed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxnCompare {
+    /// No key under the prefix has been modified after the revision
+    UnchangedSince { prefix: Vec<u8>, revision: i64 },
+    /// The key does not exist
+    Absent { key: Vec<u8> },
+    /// The key exists and holds the value
+    ValueEquals { key: Vec<u8>, value: Vec<u8> },
+}
+
+/// The kind of change reported by a watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchEventKind {
+    Put,
+    Delete,
+}
+
+/// A single change to a watched key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchEvent {
+    pub kind: WatchEventKind,
+    pub key: String,
+    /// Empty for deletes
+    pub value: String,
+    /// The store revision the change was made at
+    pub revision: i64,
+}
+
+/// A store revision that changed keys under a prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevisionSummary {
+    pub revision: i64,
+    pub puts: usize,
+    pub deletes: usize,
+}
+
+impl RevisionSummary {
+    /// The number of keys changed at this revision
+    pub fn changed(&self) -> usize {
+        self.puts + self.deletes
+    }
+
+    /// Group changes by the revision they were made at, oldest first
+    pub fn from_events<'a>(events: impl IntoIterator<Item = &'a WatchEvent>) -> Vec<Self> {
+        let mut summaries: Vec<Self> = Vec::new();
+        for event in events {
+            let summary = match summaries.last_mut() {
+                Some(summary) if summary.revision == event.revision => summary,
+                _ => {
+                    summaries.push(Self {
+                        revision: event.revision,
+                        puts: 0,
+                        deletes: 0,
+                    });
+                    summaries.last_mut().unwrap()
+                }
+            };
+            match event.kind {
+                WatchEventKind::Put => summary.puts += 1,
+                WatchEventKind::Delete => summary.deletes += 1,
+            }
+        }
+        summaries
+    }
+}
+
+/// How [`StoreClient`] retries calls that failed with a transient error, such
+/// as an etcd leader election
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct RetryConfig {
+    /// How many times a call is attempted before giving up, including the first
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled after every attempt, in milliseconds
+    pub initial_backoff: u64,
+    /// The longest delay between two attempts, in milliseconds
+    pub max_backoff: u64,
+    /// How long a call may take across all of its attempts, in milliseconds,
+    /// `0` for no limit
+    pub deadline: u64,
+}
 
-use crate::{error::TraefikResult, features::KeyValue};
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: 100,
+            max_backoff: 2000,
+            deadline: 15000,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// A single attempt without a deadline
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: 0,
+            max_backoff: 0,
+            deadline: 0,
+        }
+    }
+
+    /// The delay after the given failed attempt, counting from 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Duration::from_millis(
+            self.initial_backoff
+                .saturating_mul(factor)
+                .min(self.max_backoff),
+        )
+    }
+}
+
+/// The stream of changes returned by [`StoreClientActor::watch`]
+pub type WatchStream = Pin<Box<dyn Stream<Item = TraefikResult<WatchEvent>> + Send>>;
+
+/// The default upper bound on the number of operations in a single transaction,
+/// matching etcd's default `--max-txn-ops`
+pub const DEFAULT_MAX_TXN_OPS: usize = 128;
 
 /// A trait defining the core operations for interacting with a key-value store.
 ///
@@ -30,12 +242,20 @@ use crate::{error::TraefikResult, features::KeyValue};
 ///
 /// - `get`: Retrieves a single key-value pair
 /// - `get_with_prefix`: Retrieves all key-value pairs with a given prefix
-/// - `get_keys`: Retrieves keys matching a pattern
+/// - `get_keys`: Retrieves the keys (without values) with a given prefix
 /// - `put`: Stores a key-value pair with optional TTL
 /// - `delete`: Removes a single key-value pair
 /// - `delete_with_prefix`: Removes all key-value pairs with a given prefix
 /// - `touch`: Updates the TTL of an existing key
 /// - `put_or_touch`: Creates or updates a key-value pair with TTL
+/// - `put_if_absent`: Creates a key-value pair with optional TTL only if the key does not exist
+/// - `snapshot`: Retrieves all key-value pairs with a given prefix and the revision they were read at
+/// - `txn`: Applies a set of writes atomically if every comparison holds
+/// - `max_txn_ops`: The maximum number of writes the store accepts in a single transaction
+/// - `watch`: Streams the changes made to keys with a given prefix
+/// - `history`: Lists the revisions that changed keys with a given prefix
+/// - `snapshot_at`: Retrieves all key-value pairs with a given prefix as they were at a past revision
+/// - `retry_config`: How [`StoreClient`] retries calls that failed with [`TraefikError::Unavailable`]
 #[async_trait]
 pub trait StoreClientActor: Send + Sync {
     async fn get(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<KeyValue>;
@@ -58,6 +278,44 @@ pub trait StoreClientActor: Send + Sync {
         value: impl Into<Vec<u8>> + Send,
         ttl: Option<i64>,
     ) -> TraefikResult<()>;
+    /// Returns `false`, without writing anything, when the key already exists
+    async fn put_if_absent(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<bool>;
+
+    async fn snapshot(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<StoreSnapshot>;
+    /// Returns `false`, without writing anything, when a comparison fails
+    async fn txn(&self, compares: Vec<TxnCompare>, ops: Vec<TxnOp>) -> TraefikResult<bool>;
+    fn max_txn_ops(&self) -> usize {
+        DEFAULT_MAX_TXN_OPS
+    }
+    async fn watch(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<WatchStream>;
+
+    /// Only stores that keep past revisions of the keyspace support history
+    async fn history(
+        &self,
+        _prefix: impl Into<Vec<u8>> + Send,
+    ) -> TraefikResult<Vec<RevisionSummary>> {
+        Err(TraefikError::ConfigError(
+            "this store does not keep revision history".to_string(),
+        ))
+    }
+    async fn snapshot_at(
+        &self,
+        _prefix: impl Into<Vec<u8>> + Send,
+        _revision: i64,
+    ) -> TraefikResult<StoreSnapshot> {
+        Err(TraefikError::ConfigError(
+            "this store does not keep revision history".to_string(),
+        ))
+    }
+
+    fn retry_config(&self) -> RetryConfig {
+        RetryConfig::none()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -77,17 +335,21 @@ where
     }
 
     pub async fn get(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<KeyValue> {
-        self.actor.get(key).await
+        let key = key.into();
+        self.call("get", || self.actor.get(key.clone())).await
     }
 
     pub async fn get_with_prefix(
         &self,
         key: impl Into<Vec<u8>> + Send,
     ) -> TraefikResult<Vec<KeyValue>> {
-        self.actor.get_with_prefix(key).await
+        let key = key.into();
+        self.call("get", || self.actor.get_with_prefix(key.clone()))
+            .await
     }
     pub async fn get_keys(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<Vec<KeyValue>> {
-        self.actor.get_keys(key).await
+        let key = key.into();
+        self.call("get", || self.actor.get_keys(key.clone())).await
     }
     pub async fn put(
         &self,
@@ -95,16 +357,22 @@ where
         value: impl Into<Vec<u8>> + Send,
         ttl: Option<i64>,
     ) -> TraefikResult<Option<KeyValue>> {
-        self.actor.put(key, value, ttl).await
+        let (key, value) = (key.into(), value.into());
+        self.call("put", || self.actor.put(key.clone(), value.clone(), ttl))
+            .await
     }
     pub async fn delete(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
-        self.actor.delete(key).await
+        let key = key.into();
+        self.call("delete", || self.actor.delete(key.clone())).await
     }
     pub async fn delete_with_prefix(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
-        self.actor.delete_with_prefix(key).await
+        let key = key.into();
+        self.call("delete", || self.actor.delete_with_prefix(key.clone()))
+            .await
     }
     pub async fn touch(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<()> {
-        self.actor.touch(key).await
+        let key = key.into();
+        self.call("touch", || self.actor.touch(key.clone())).await
     }
     pub async fn put_or_touch(
         &self,
@@ -112,6 +380,574 @@ where
         value: impl Into<Vec<u8>> + Send,
         ttl: Option<i64>,
     ) -> TraefikResult<()> {
-        self.actor.put_or_touch(key, value, ttl).await
+        let (key, value) = (key.into(), value.into());
+        self.call("put", || {
+            self.actor.put_or_touch(key.clone(), value.clone(), ttl)
+        })
+        .await
+    }
+
+    /// The first attempt may have created the key before its response was
+    /// lost, so a retry reads the key first and reports it as ours when it
+    /// holds `value`
+    pub async fn put_if_absent(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<bool> {
+        let (key, value) = (key.into(), value.into());
+        let attempted = AtomicBool::new(false);
+        self.call("put", || async {
+            if attempted.swap(true, Ordering::SeqCst) {
+                match self.actor.get(key.clone()).await {
+                    Ok(kv) => return Ok(kv.value == value),
+                    Err(TraefikError::NotFound(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            self.actor
+                .put_if_absent(key.clone(), value.clone(), ttl)
+                .await
+        })
+        .await
+    }
+
+    pub async fn snapshot(
+        &self,
+        prefix: impl Into<Vec<u8>> + Send,
+    ) -> TraefikResult<StoreSnapshot> {
+        let prefix = prefix.into();
+        self.call("snapshot", || self.actor.snapshot(prefix.clone()))
+            .await
+    }
+    /// The first attempt may have committed before its response was lost, so
+    /// a retry first checks whether every operation is already visible and
+    /// reports the transaction as committed when it is, rather than sending
+    /// it again to fail its comparisons
+    pub async fn txn(&self, compares: Vec<TxnCompare>, ops: Vec<TxnOp>) -> TraefikResult<bool> {
+        let attempted = AtomicBool::new(false);
+        self.call("txn", || async {
+            if attempted.swap(true, Ordering::SeqCst) && self.applied(&ops).await? {
+                return Ok(true);
+            }
+            self.actor.txn(compares.clone(), ops.clone()).await
+        })
+        .await
+    }
+
+    /// Whether the store already holds the result of every operation
+    async fn applied(&self, ops: &[TxnOp]) -> TraefikResult<bool> {
+        for op in ops {
+            let (key, expected) = match op {
+                TxnOp::Put { key, value, .. } => (key, Some(value)),
+                TxnOp::Delete { key } => (key, None),
+            };
+            let current = match self.actor.get(key.clone()).await {
+                Ok(kv) => Some(kv.value),
+                Err(TraefikError::NotFound(_)) => None,
+                Err(e) => return Err(e),
+            };
+            if current.as_ref() != expected {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+    pub async fn watch(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<WatchStream> {
+        let prefix = prefix.into();
+        self.call("watch", || self.actor.watch(prefix.clone()))
+            .await
+    }
+
+    pub async fn history(
+        &self,
+        prefix: impl Into<Vec<u8>> + Send,
+    ) -> TraefikResult<Vec<RevisionSummary>> {
+        let prefix = prefix.into();
+        // Replaying a long history can take well over the deadline of a call
+        let retry = RetryConfig {
+            deadline: 0,
+            ..self.actor.retry_config()
+        };
+        retrying(&retry, "history", || self.actor.history(prefix.clone())).await
+    }
+
+    pub async fn snapshot_at(
+        &self,
+        prefix: impl Into<Vec<u8>> + Send,
+        revision: i64,
+    ) -> TraefikResult<StoreSnapshot> {
+        let prefix = prefix.into();
+        self.call("snapshot", || {
+            self.actor.snapshot_at(prefix.clone(), revision)
+        })
+        .await
+    }
+
+    async fn call<R, F, Fut>(&self, action: &str, attempt: F) -> TraefikResult<R>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = TraefikResult<R>>,
+    {
+        retrying(&self.actor.retry_config(), action, attempt).await
+    }
+
+    /// Commit `ops` provided nothing under `prefix` changed after `revision`
+    ///
+    /// When the operations fit in a single transaction they are committed
+    /// atomically. Otherwise they are split into bounded transactions: the
+    /// first one claims `guard_key`, and every following one only commits
+    /// while the guard is still ours, so concurrent writers are refused until
+    /// the last transaction releases it. Either way, a conflict detected
+    /// before the first transaction commits leaves the store untouched.
+    pub async fn commit(
+        &self,
+        prefix: &str,
+        revision: i64,
+        guard_key: &str,
+        ops: Vec<TxnOp>,
+    ) -> TraefikResult<()> {
+        let unchanged = TxnCompare::UnchangedSince {
+            prefix: prefix.into(),
+            revision,
+        };
+        let guard_absent = TxnCompare::Absent {
+            key: guard_key.into(),
+        };
+        let conflict = || {
+            TraefikError::Conflict(format!(
+                "'{prefix}' was modified after revision {revision} or another apply is in progress"
+            ))
+        };
+
+        let max_ops = self.actor.max_txn_ops();
+        if ops.len() <= max_ops {
+            debug!(
+                "Committing {} operations in a single transaction",
+                ops.len()
+            );
+            return match self.txn(vec![unchanged, guard_absent], ops).await? {
+                true => Ok(()),
+                false => Err(conflict()),
+            };
+        }
+        if max_ops < 2 {
+            return Err(TraefikError::ConfigError(format!(
+                "max_txn_ops must be at least 2 to split {} operations, got {max_ops}",
+                ops.len()
+            )));
+        }
+
+        let token = guard_token(revision);
+        let holds_guard = TxnCompare::ValueEquals {
+            key: guard_key.into(),
+            value: token.clone().into(),
+        };
+        let chunks = ops.chunks(max_ops - 1).collect::<Vec<_>>();
+        let total = chunks.len();
+        debug!(
+            "Committing {} operations in {total} transactions",
+            ops.len()
+        );
+
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let mut chunk_ops = chunk.to_vec();
+            let compares = if index == 0 {
+                chunk_ops.insert(0, TxnOp::put(guard_key, token.clone()));
+                vec![unchanged.clone(), guard_absent.clone()]
+            } else {
+                vec![holds_guard.clone()]
+            };
+            if index + 1 == total {
+                chunk_ops.push(TxnOp::delete(guard_key));
+            }
+
+            match self.txn(compares, chunk_ops).await {
+                Ok(true) => {}
+                Ok(false) if index == 0 => return Err(conflict()),
+                Ok(false) => {
+                    return Err(TraefikError::Conflict(format!(
+                        "guard key '{guard_key}' was lost after {index} of {total} transactions were committed"
+                    )))
+                }
+                Err(e) => {
+                    if index > 0 {
+                        self.release_guard(guard_key, &token).await;
+                        warn!("apply interrupted after {index} of {total} transactions were committed");
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn release_guard(&self, guard_key: &str, token: &str) {
+        let compares = vec![TxnCompare::ValueEquals {
+            key: guard_key.into(),
+            value: token.into(),
+        }];
+        if let Err(e) = self.txn(compares, vec![TxnOp::delete(guard_key)]).await {
+            warn!("failed to release guard key '{guard_key}': {e}");
+        }
+    }
+}
+
+/// Run `attempt`, retrying it with exponential backoff while it fails with a
+/// retryable error, all within the deadline of `retry`
+async fn retrying<R, F, Fut>(retry: &RetryConfig, action: &str, attempt: F) -> TraefikResult<R>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = TraefikResult<R>>,
+{
+    let attempts = async {
+        let mut tries = 1;
+        loop {
+            match attempt().await {
+                Err(e) if e.is_retryable() && tries < retry.max_attempts => {
+                    let backoff = retry.backoff(tries);
+                    warn!(
+                        "{action} failed (attempt {tries} of {}), retrying in {backoff:?}: {e}",
+                        retry.max_attempts
+                    );
+                    tokio::time::sleep(backoff).await;
+                    tries += 1;
+                }
+                result => return result,
+            }
+        }
+    };
+    if retry.deadline == 0 {
+        return attempts.await;
+    }
+    tokio::time::timeout(Duration::from_millis(retry.deadline), attempts)
+        .await
+        .unwrap_or_else(|_| {
+            Err(TraefikError::Unavailable(format!(
+                "{action} did not complete within {}ms",
+                retry.deadline
+            )))
+        })
+}
+
+/// A value identifying this process as the holder of a guard key
+fn guard_token(revision: i64) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("{}-{revision}-{nanos}", std::process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+
+    use crate::{core::etcd_trait::EtcdPair, features::memory::MemoryStore};
+
+    use super::*;
+
+    fn puts(count: usize) -> Vec<TxnOp> {
+        (0..count)
+            .map(|i| TxnOp::put(format!("traefik/{i}"), i.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_up_to_the_max() {
+        let retry = RetryConfig {
+            initial_backoff: 100,
+            max_backoff: 1000,
+            ..Default::default()
+        };
+        assert_eq!(retry.backoff(1), Duration::from_millis(100));
+        assert_eq!(retry.backoff(2), Duration::from_millis(200));
+        assert_eq!(retry.backoff(4), Duration::from_millis(800));
+        assert_eq!(retry.backoff(5), Duration::from_millis(1000));
+        assert_eq!(retry.backoff(64), Duration::from_millis(1000));
+    }
+
+    #[tokio::test]
+    async fn test_retrying_only_retries_transient_errors() {
+        let retry = RetryConfig {
+            max_attempts: 3,
+            initial_backoff: 1,
+            max_backoff: 1,
+            deadline: 0,
+        };
+        let tries = AtomicU32::new(0);
+        let result = retrying(&retry, "get", || async {
+            match tries.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(TraefikError::Unavailable("leader changed".into())),
+                _ => Ok(42),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(tries.load(Ordering::SeqCst), 2);
+
+        tries.store(0, Ordering::SeqCst);
+        let result: TraefikResult<()> = retrying(&retry, "get", || async {
+            tries.fetch_add(1, Ordering::SeqCst);
+            Err(TraefikError::NotFound("missing".into()))
+        })
+        .await;
+        assert!(matches!(result, Err(TraefikError::NotFound(_))));
+        assert_eq!(tries.load(Ordering::SeqCst), 1);
+
+        let result: TraefikResult<()> = retrying(&retry, "get", || async {
+            tries.fetch_add(1, Ordering::SeqCst);
+            Err(TraefikError::Unavailable("no leader".into()))
+        })
+        .await;
+        assert!(matches!(result, Err(TraefikError::Unavailable(_))));
+        assert_eq!(tries.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_retrying_gives_up_at_the_deadline() {
+        let retry = RetryConfig {
+            max_attempts: 100,
+            initial_backoff: 20,
+            max_backoff: 20,
+            deadline: 50,
+        };
+        let result: TraefikResult<()> = retrying(&retry, "txn", || async {
+            Err(TraefikError::Unavailable("no leader".into()))
+        })
+        .await;
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("txn did not complete within 50ms"), "{err}");
+    }
+
+    /// Applies every call, but loses the response of the first write
+    struct LosesFirstResponse {
+        store: MemoryStore,
+        lost: AtomicBool,
+    }
+
+    impl LosesFirstResponse {
+        fn new(store: MemoryStore) -> Self {
+            Self {
+                store,
+                lost: AtomicBool::new(false),
+            }
+        }
+
+        fn respond<R>(&self, result: TraefikResult<R>) -> TraefikResult<R> {
+            match self.lost.swap(true, Ordering::SeqCst) {
+                false => Err(TraefikError::Unavailable("connection reset".into())),
+                true => result,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl StoreClientActor for LosesFirstResponse {
+        async fn get(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<KeyValue> {
+            self.store.get(key).await
+        }
+        async fn get_with_prefix(
+            &self,
+            key: impl Into<Vec<u8>> + Send,
+        ) -> TraefikResult<Vec<KeyValue>> {
+            self.store.get_with_prefix(key).await
+        }
+        async fn get_keys(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<Vec<KeyValue>> {
+            self.store.get_keys(key).await
+        }
+        async fn put(
+            &self,
+            key: impl Into<Vec<u8>> + Send,
+            value: impl Into<Vec<u8>> + Send,
+            ttl: Option<i64>,
+        ) -> TraefikResult<Option<KeyValue>> {
+            self.store.put(key, value, ttl).await
+        }
+        async fn delete(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
+            self.store.delete(key).await
+        }
+        async fn delete_with_prefix(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
+            self.store.delete_with_prefix(key).await
+        }
+        async fn touch(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<()> {
+            self.store.touch(key).await
+        }
+        async fn put_or_touch(
+            &self,
+            key: impl Into<Vec<u8>> + Send,
+            value: impl Into<Vec<u8>> + Send,
+            ttl: Option<i64>,
+        ) -> TraefikResult<()> {
+            self.store.put_or_touch(key, value, ttl).await
+        }
+        async fn put_if_absent(
+            &self,
+            key: impl Into<Vec<u8>> + Send,
+            value: impl Into<Vec<u8>> + Send,
+            ttl: Option<i64>,
+        ) -> TraefikResult<bool> {
+            let result = self.store.put_if_absent(key, value, ttl).await;
+            self.respond(result)
+        }
+        async fn snapshot(
+            &self,
+            prefix: impl Into<Vec<u8>> + Send,
+        ) -> TraefikResult<StoreSnapshot> {
+            self.store.snapshot(prefix).await
+        }
+        async fn txn(&self, compares: Vec<TxnCompare>, ops: Vec<TxnOp>) -> TraefikResult<bool> {
+            let result = self.store.txn(compares, ops).await;
+            self.respond(result)
+        }
+        fn max_txn_ops(&self) -> usize {
+            self.store.max_txn_ops()
+        }
+        async fn watch(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<WatchStream> {
+            self.store.watch(prefix).await
+        }
+        fn retry_config(&self) -> RetryConfig {
+            RetryConfig {
+                max_attempts: 3,
+                initial_backoff: 1,
+                max_backoff: 1,
+                deadline: 0,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_put_if_absent_recognises_its_own_lost_write() {
+        let client = StoreClient::new(LosesFirstResponse::new(MemoryStore::new()));
+        assert!(client.put_if_absent("lock", "me", None).await.unwrap());
+
+        let client = StoreClient::new(LosesFirstResponse::new(MemoryStore::from_pairs(&[
+            EtcdPair::new("lock", "someone-else"),
+        ])));
+        assert!(!client.put_if_absent("lock", "me", None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_commit_survives_a_lost_txn_response() {
+        let client = StoreClient::new(LosesFirstResponse::new(MemoryStore::new()));
+        let snapshot = client.snapshot("traefik").await.unwrap();
+        client
+            .commit("traefik", snapshot.revision, "guard", puts(3))
+            .await
+            .unwrap();
+        assert_eq!(client.get_with_prefix("traefik").await.unwrap().len(), 3);
+
+        // The lost response belongs to the transaction claiming the guard
+        let client = StoreClient::new(LosesFirstResponse::new(
+            MemoryStore::new().with_max_txn_ops(3),
+        ));
+        let snapshot = client.snapshot("traefik").await.unwrap();
+        client
+            .commit("traefik", snapshot.revision, "guard", puts(7))
+            .await
+            .unwrap();
+        assert_eq!(client.get_with_prefix("traefik").await.unwrap().len(), 7);
+        assert!(client.get("guard").await.is_err());
+    }
+
+    #[test]
+    fn test_revision_summary_groups_events() {
+        let event = |kind, revision| WatchEvent {
+            kind,
+            key: "traefik/a".to_string(),
+            value: String::new(),
+            revision,
+        };
+        let events = [
+            event(WatchEventKind::Put, 2),
+            event(WatchEventKind::Put, 2),
+            event(WatchEventKind::Delete, 2),
+            event(WatchEventKind::Delete, 5),
+        ];
+        let summaries = RevisionSummary::from_events(&events);
+        assert_eq!(summaries.len(), 2);
+        assert_eq!((summaries[0].puts, summaries[0].deletes), (2, 1));
+        assert_eq!(summaries[0].changed(), 3);
+        assert_eq!(summaries[1].revision, 5);
+    }
+
+    #[tokio::test]
+    async fn test_commit_in_single_transaction() {
+        let client = StoreClient::new(MemoryStore::new());
+        let snapshot = client.snapshot("traefik").await.unwrap();
+        client
+            .commit("traefik", snapshot.revision, "guard", puts(3))
+            .await
+            .unwrap();
+        assert_eq!(client.get_with_prefix("traefik").await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_commit_refuses_stale_revision() {
+        let client = StoreClient::new(MemoryStore::from_pairs(&[EtcdPair::new(
+            "traefik/0",
+            "old",
+        )]));
+        let snapshot = client.snapshot("traefik").await.unwrap();
+        client.put("traefik/other", "racing", None).await.unwrap();
+        let result = client
+            .commit("traefik", snapshot.revision, "guard", puts(3))
+            .await;
+        assert!(matches!(result, Err(TraefikError::Conflict(_))));
+        assert_eq!(
+            client.get("traefik/0").await.unwrap().value_str(),
+            Some("old")
+        );
+        assert!(client.get("traefik/1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_commit_refuses_a_delete_after_the_revision() {
+        let client = StoreClient::new(MemoryStore::from_pairs(&[
+            EtcdPair::new("traefik/0", "old"),
+            EtcdPair::new("traefik/gone", "old"),
+        ]));
+        let snapshot = client.snapshot("traefik").await.unwrap();
+        client.delete("traefik/gone").await.unwrap();
+        let result = client
+            .commit("traefik", snapshot.revision, "guard", puts(3))
+            .await;
+        assert!(matches!(result, Err(TraefikError::Conflict(_))));
+        assert_eq!(
+            client.get("traefik/0").await.unwrap().value_str(),
+            Some("old")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit_splits_into_bounded_transactions() {
+        let client = StoreClient::new(MemoryStore::new().with_max_txn_ops(3));
+        let snapshot = client.snapshot("traefik").await.unwrap();
+        client
+            .commit("traefik", snapshot.revision, "guard", puts(7))
+            .await
+            .unwrap();
+        assert_eq!(client.get_with_prefix("traefik").await.unwrap().len(), 7);
+        assert!(client.get("guard").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_commit_refuses_while_guard_is_held() {
+        let client = StoreClient::new(MemoryStore::new().with_max_txn_ops(3));
+        client.put("guard", "someone-else", None).await.unwrap();
+        let snapshot = client.snapshot("traefik").await.unwrap();
+        let result = client
+            .commit("traefik", snapshot.revision, "guard", puts(7))
+            .await;
+        assert!(matches!(result, Err(TraefikError::Conflict(_))));
+        assert!(client.get_with_prefix("traefik").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_commit_requires_room_for_the_guard() {
+        let client = StoreClient::new(MemoryStore::new().with_max_txn_ops(1));
+        let result = client.commit("traefik", 0, "guard", puts(2)).await;
+        assert!(matches!(result, Err(TraefikError::ConfigError(_))));
     }
 }
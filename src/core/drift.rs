@@ -0,0 +1,243 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{Read, Write},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{TraefikError, TraefikResult};
+
+use super::{backup::content_hash, etcd_trait::EtcdPair, meta_key};
+
+/// The key holding what `owner` last applied under `rule_prefix`
+pub fn last_applied_key(rule_prefix: &str, owner: Option<&str>) -> String {
+    match owner {
+        Some(owner) => meta_key(rule_prefix, &format!("last-applied/{owner}")),
+        None => meta_key(rule_prefix, "last-applied"),
+    }
+}
+
+/// The pairs written by the last apply, the base of a three-way comparison
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastApplied {
+    pub applied_at: DateTime<Utc>,
+    /// See [`content_hash`], of the pairs sorted by key
+    pub hash: String,
+    /// Sorted by key
+    pub pairs: Vec<EtcdPair>,
+}
+
+/// How [`LastApplied`] is stored, the pairs are gzipped JSON in base64
+#[derive(Serialize, Deserialize)]
+struct StoredLastApplied {
+    applied_at: DateTime<Utc>,
+    hash: String,
+    pairs: String,
+}
+
+impl LastApplied {
+    pub fn new(pairs: &[EtcdPair]) -> Self {
+        let mut pairs = pairs.to_vec();
+        pairs.sort_by(|a, b| a.key().cmp(b.key()));
+        Self {
+            applied_at: Utc::now(),
+            hash: content_hash(&pairs),
+            pairs,
+        }
+    }
+
+    pub fn encode(&self) -> TraefikResult<String> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&serde_json::to_vec(&self.pairs)?)?;
+        let stored = StoredLastApplied {
+            applied_at: self.applied_at,
+            hash: self.hash.clone(),
+            pairs: STANDARD.encode(encoder.finish()?),
+        };
+        Ok(serde_json::to_string(&stored)?)
+    }
+
+    /// Decode a stored snapshot, checking its content against its hash
+    pub fn decode(value: &[u8]) -> TraefikResult<Self> {
+        let stored: StoredLastApplied = serde_json::from_slice(value)?;
+        let compressed = STANDARD.decode(&stored.pairs).map_err(|e| {
+            TraefikError::ConfigError(format!("last-applied snapshot is not base64: {e}"))
+        })?;
+        let mut json = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut json)?;
+        let pairs: Vec<EtcdPair> = serde_json::from_slice(&json)?;
+        if content_hash(&pairs) != stored.hash {
+            return Err(TraefikError::ConfigError(format!(
+                "last-applied snapshot does not match its hash {}",
+                stored.hash
+            )));
+        }
+        Ok(Self {
+            applied_at: stored.applied_at,
+            hash: stored.hash,
+            pairs,
+        })
+    }
+}
+
+/// Which side a difference comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DriftKind {
+    /// The config changed since the last apply, applying it resolves this
+    Local,
+    /// The store was changed after the last apply by something else
+    Live,
+    /// Both the config and the store changed, differently
+    Conflict,
+}
+
+impl std::fmt::Display for DriftKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DriftKind::Local => write!(f, "local"),
+            DriftKind::Live => write!(f, "live"),
+            DriftKind::Conflict => write!(f, "conflict"),
+        }
+    }
+}
+
+/// A key whose desired, last applied and live values are not all equal,
+/// `None` when the key is absent on that side
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Drift {
+    pub key: String,
+    pub kind: DriftKind,
+    pub desired: Option<String>,
+    pub last_applied: Option<String>,
+    pub live: Option<String>,
+}
+
+/// The differences found by [`detect`], along with when the base was applied
+#[derive(Debug, Clone, Serialize)]
+pub struct DriftReport {
+    pub applied_at: DateTime<Utc>,
+    pub drifts: Vec<Drift>,
+}
+
+impl DriftReport {
+    pub fn count(&self, kind: DriftKind) -> usize {
+        self.drifts
+            .iter()
+            .filter(|drift| drift.kind == kind)
+            .count()
+    }
+
+    /// Whether the store was changed by something other than an apply
+    pub fn has_live_changes(&self) -> bool {
+        self.drifts
+            .iter()
+            .any(|drift| drift.kind != DriftKind::Local)
+    }
+}
+
+/// Classify every key that differs between the three states
+///
+/// A key changed on one side only is a local or live change, a key changed
+/// on both sides to different values is a conflict. Keys changed the same
+/// way on both sides are in sync.
+pub fn detect(desired: &[EtcdPair], last_applied: &[EtcdPair], live: &[EtcdPair]) -> Vec<Drift> {
+    let map = |pairs: &[EtcdPair]| -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|pair| (pair.key().to_string(), pair.value().to_string()))
+            .collect()
+    };
+    let (desired, base, live) = (map(desired), map(last_applied), map(live));
+    let keys: BTreeSet<&String> = desired
+        .keys()
+        .chain(base.keys())
+        .chain(live.keys())
+        .collect();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let (d, b, l) = (desired.get(key), base.get(key), live.get(key));
+            let kind = match (d == b, l == b) {
+                _ if d == l => return None,
+                (false, true) => DriftKind::Local,
+                (true, false) => DriftKind::Live,
+                _ => DriftKind::Conflict,
+            };
+            Some(Drift {
+                key: key.clone(),
+                kind,
+                desired: d.cloned(),
+                last_applied: b.cloned(),
+                live: l.cloned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::pairs;
+
+    use super::*;
+
+    #[test]
+    fn test_last_applied_round_trip() {
+        let applied = LastApplied::new(&pairs(&[("t/b", "2"), ("t/a", "1")]));
+        assert_eq!(applied.pairs[0].key(), "t/a");
+        let encoded = applied.encode().unwrap();
+        assert_eq!(LastApplied::decode(encoded.as_bytes()).unwrap(), applied);
+    }
+
+    #[test]
+    fn test_decode_rejects_a_wrong_hash() {
+        let mut applied = LastApplied::new(&pairs(&[("t/a", "1")]));
+        applied.hash = content_hash(&pairs(&[("t/a", "2")]));
+        let encoded = applied.encode().unwrap();
+        assert!(LastApplied::decode(encoded.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_detect_classifies_each_side() {
+        let base = pairs(&[
+            ("t/local", "old"),
+            ("t/live", "old"),
+            ("t/both", "old"),
+            ("t/same", "old"),
+            ("t/deleted-live", "old"),
+        ]);
+        let desired = pairs(&[
+            ("t/local", "new"),
+            ("t/live", "old"),
+            ("t/both", "yaml"),
+            ("t/same", "new"),
+            ("t/deleted-live", "old"),
+            ("t/added", "new"),
+        ]);
+        let live = pairs(&[
+            ("t/local", "old"),
+            ("t/live", "edited"),
+            ("t/both", "edited"),
+            ("t/same", "new"),
+            ("t/manual", "edited"),
+        ]);
+        let drifts = detect(&desired, &base, &live)
+            .into_iter()
+            .map(|drift| (drift.key, drift.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            drifts,
+            vec![
+                ("t/added".to_string(), DriftKind::Local),
+                ("t/both".to_string(), DriftKind::Conflict),
+                ("t/deleted-live".to_string(), DriftKind::Live),
+                ("t/live".to_string(), DriftKind::Live),
+                ("t/local".to_string(), DriftKind::Local),
+                ("t/manual".to_string(), DriftKind::Live),
+            ]
+        );
+    }
+}
@@ -1,11 +1,17 @@
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::error::TraefikResult;
+use crate::{error::TraefikResult, features::KeyValue};
 
-use super::templating::{TemplateContext, TemplateResolver};
+use super::{
+    backup::Backup,
+    templating::{TemplateContext, TemplateResolver},
+};
 
 pub trait ToEtcdPairs {
     fn to_etcd_pairs(
@@ -36,8 +42,20 @@ impl EtcdPair {
 
 // Add these utility implementations
 impl EtcdPair {
+    /// Read pairs from a file
+    ///
+    /// Backups and JSON lists of `[key, value]` pairs (as written by
+    /// [`EtcdPair::to_file`]) are read as is. Anything else is read as one
+    /// `key value` pair per line, which cannot hold values with newlines.
     pub fn from_file(path: &PathBuf) -> TraefikResult<Vec<EtcdPair>> {
         let content = std::fs::read_to_string(path)?;
+        if let Ok(backup) = serde_json::from_str::<Backup>(&content) {
+            backup.validate()?;
+            return Ok(backup.pairs);
+        }
+        if let Ok(pairs) = serde_json::from_str::<Vec<EtcdPair>>(&content) {
+            return Ok(pairs);
+        }
         Ok(content
             .lines()
             .filter(|line| !line.is_empty())
@@ -50,6 +68,21 @@ impl EtcdPair {
             })
             .collect())
     }
+
+    /// Write pairs as a JSON list that [`EtcdPair::from_file`] reads back unchanged
+    pub fn to_file(pairs: &[EtcdPair], path: &Path) -> TraefikResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(pairs)?)?;
+        Ok(())
+    }
+}
+
+impl From<KeyValue> for EtcdPair {
+    fn from(kv: KeyValue) -> Self {
+        EtcdPair::new(
+            String::from_utf8_lossy(&kv.key),
+            String::from_utf8_lossy(&kv.value),
+        )
+    }
 }
 
 impl Display for EtcdPair {
@@ -73,4 +106,17 @@ mod tests {
         assert_eq!(pairs[1].key(), "key2");
         assert_eq!(pairs[1].value(), "value2");
     }
+
+    #[test]
+    fn test_to_file_round_trips_spaces_and_newlines() {
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        let pairs = vec![
+            EtcdPair::new("traefik/a", "Host(`a`) && Path(`/b c`)"),
+            EtcdPair::new("traefik/b", "first\nsecond\n"),
+            EtcdPair::new("traefik/c", ""),
+        ];
+        EtcdPair::to_file(&pairs, tmpfile.path()).unwrap();
+        let read = EtcdPair::from_file(&tmpfile.path().to_path_buf()).unwrap();
+        assert_eq!(read, pairs);
+    }
 }
@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Value};
+
+use super::etcd_trait::EtcdPair;
+
+/// Keys whose values are written to the store as comma-joined lists, which
+/// the file provider expects as arrays
+static LIST_KEYS: &[&str] = &[
+    "accessControlAllowHeaders",
+    "accessControlAllowMethods",
+    "accessControlAllowOriginList",
+    "accessControlExposeHeaders",
+    "authRequestHeaders",
+    "authResponseHeaders",
+    "sans",
+];
+
+/// Maps whose keys are user supplied names, so their values are never coerced
+static STRING_MAPS: &[&str] = &["customRequestHeaders", "customResponseHeaders"];
+
+/// Options that traefik reads as booleans, every other value stays a string
+static BOOL_KEYS: &[&str] = &[
+    "accessControlAllowCredentials",
+    "addVaryHeader",
+    "browserXssFilter",
+    "contentTypeNosniff",
+    "followRedirects",
+    "forceSTSHeader",
+    "forceSlash",
+    "frameDeny",
+    "httpOnly",
+    "insecureSkipVerify",
+    "isDevelopment",
+    "passHostHeader",
+    "permanent",
+    "secure",
+    "stsIncludeSubdomains",
+    "stsPreload",
+    "trustForwardHeader",
+];
+
+/// Options that traefik reads as integers, every other value stays a string
+static NUMBER_KEYS: &[&str] = &[
+    "accessControlMaxAge",
+    "amount",
+    "attempts",
+    "average",
+    "burst",
+    "depth",
+    "maxAge",
+    "maxRequestBodyBytes",
+    "maxResponseBodyBytes",
+    "memRequestBodyBytes",
+    "memResponseBodyBytes",
+    "minResponseBodyBytes",
+    "port",
+    "priority",
+    "status",
+    "stsSeconds",
+    "weight",
+];
+
+/// Options that are enabled by writing `true` in the store, but are empty
+/// sections in the file provider
+static FLAG_SECTIONS: &[&str] = &["tls"];
+
+#[derive(Debug)]
+enum Node {
+    Leaf(String),
+    Branch(BTreeMap<String, Node>),
+}
+
+impl Node {
+    fn insert(&mut self, path: &[&str], value: &str) {
+        let Some((segment, rest)) = path.split_first() else {
+            return;
+        };
+        if let Node::Leaf(_) = self {
+            // A section with options replaces a `true` flag
+            *self = Node::Branch(BTreeMap::new());
+        }
+        let Node::Branch(children) = self else {
+            unreachable!()
+        };
+        if rest.is_empty() {
+            // A later value for the same key wins, as it does in the store,
+            // but a section with options is never replaced by a flag
+            match children.get(*segment) {
+                Some(Node::Branch(_)) => {}
+                _ => {
+                    children.insert(segment.to_string(), Node::Leaf(value.to_string()));
+                }
+            }
+        } else {
+            children
+                .entry(segment.to_string())
+                .or_insert_with(|| Node::Branch(BTreeMap::new()))
+                .insert(rest, value);
+        }
+    }
+
+    fn into_value(self, key: &str, parent: &str) -> Value {
+        match self {
+            Node::Leaf(value) => leaf_value(key, parent, value),
+            Node::Branch(children) => {
+                let indexed = children
+                    .keys()
+                    .map(|k| k.parse::<usize>().ok())
+                    .collect::<Option<Vec<_>>>();
+                match indexed {
+                    Some(indices) if !indices.is_empty() => {
+                        let mut items = indices
+                            .into_iter()
+                            .zip(children.into_values())
+                            .collect::<Vec<_>>();
+                        items.sort_by_key(|(index, _)| *index);
+                        Value::Array(
+                            items
+                                .into_iter()
+                                .map(|(_, child)| child.into_value(key, parent))
+                                .collect(),
+                        )
+                    }
+                    _ => Value::Object(
+                        children
+                            .into_iter()
+                            .map(|(k, child)| {
+                                let value = child.into_value(&k, key);
+                                (k, value)
+                            })
+                            .collect::<Map<_, _>>(),
+                    ),
+                }
+            }
+        }
+    }
+}
+
+fn leaf_value(key: &str, parent: &str, value: String) -> Value {
+    if LIST_KEYS.contains(&key) {
+        return Value::Array(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        );
+    }
+    if STRING_MAPS.contains(&parent) {
+        return Value::String(value);
+    }
+    if FLAG_SECTIONS.contains(&key) && value == "true" {
+        return Value::Object(Map::new());
+    }
+    if BOOL_KEYS.contains(&key) {
+        if let Ok(flag) = value.parse::<bool>() {
+            return Value::Bool(flag);
+        }
+    }
+    if NUMBER_KEYS.contains(&key) {
+        if let Ok(number) = value.parse::<i64>() {
+            return Value::from(number);
+        }
+    }
+    Value::String(value)
+}
+
+/// Build a traefik file provider dynamic configuration from the pairs that
+/// would be written to the store under `rule_prefix`.
+///
+/// Indexed keys (`entryPoints/0`, `servers/1/url`) become arrays in index
+/// order and comma-joined list values are split into arrays.
+pub fn to_file_provider(pairs: &[EtcdPair], rule_prefix: &str) -> Value {
+    let mut root = Node::Branch(BTreeMap::new());
+    let prefix = format!("{}/", rule_prefix.trim_end_matches('/'));
+    for pair in pairs {
+        let Some(path) = pair.key().strip_prefix(&prefix) else {
+            continue;
+        };
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        root.insert(&segments, pair.value());
+    }
+    root.into_value("", "")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_helpers::pairs;
+
+    use super::*;
+
+    #[test]
+    fn test_nests_keys_under_the_rule_prefix() {
+        let doc = to_file_provider(
+            &pairs(&[
+                ("traefik/http/routers/web/rule", "Host(`example.com`)"),
+                ("traefik/http/routers/web/service", "web"),
+                ("other/http/routers/skip/rule", "Host(`skip.com`)"),
+            ]),
+            "traefik",
+        );
+        assert_eq!(
+            doc,
+            json!({"http": {"routers": {"web": {
+                "rule": "Host(`example.com`)",
+                "service": "web"
+            }}}})
+        );
+    }
+
+    #[test]
+    fn test_indexed_keys_become_ordered_arrays() {
+        let doc = to_file_provider(
+            &pairs(&[
+                ("traefik/http/routers/web/middlewares/10", "k"),
+                ("traefik/http/routers/web/middlewares/2", "c"),
+                ("traefik/http/routers/web/middlewares/0", "a"),
+                (
+                    "traefik/http/services/web/loadBalancer/servers/0/url",
+                    "http://a:80",
+                ),
+                (
+                    "traefik/http/services/web/loadBalancer/servers/1/url",
+                    "http://b:80",
+                ),
+            ]),
+            "traefik",
+        );
+        assert_eq!(
+            doc["http"]["routers"]["web"]["middlewares"],
+            json!(["a", "c", "k"])
+        );
+        assert_eq!(
+            doc["http"]["services"]["web"]["loadBalancer"]["servers"],
+            json!([{"url": "http://a:80"}, {"url": "http://b:80"}])
+        );
+    }
+
+    #[test]
+    fn test_comma_joined_lists_become_arrays() {
+        let doc = to_file_provider(
+            &pairs(&[(
+                "traefik/http/middlewares/cors/headers/accessControlAllowMethods",
+                "GET, POST,PUT",
+            )]),
+            "traefik",
+        );
+        assert_eq!(
+            doc["http"]["middlewares"]["cors"]["headers"]["accessControlAllowMethods"],
+            json!(["GET", "POST", "PUT"])
+        );
+    }
+
+    #[test]
+    fn test_scalars_are_typed_except_header_values() {
+        let doc = to_file_provider(
+            &pairs(&[
+                ("traefik/http/routers/web/priority", "1010"),
+                ("traefik/http/routers/web/tls", "true"),
+                (
+                    "traefik/http/services/web/loadBalancer/passHostHeader",
+                    "false",
+                ),
+                (
+                    "traefik/http/middlewares/h/headers/customRequestHeaders/X-Forwarded-Port",
+                    "443",
+                ),
+            ]),
+            "traefik",
+        );
+        let router = &doc["http"]["routers"]["web"];
+        assert_eq!(router["priority"], json!(1010));
+        assert_eq!(router["tls"], json!({}));
+        assert_eq!(
+            doc["http"]["services"]["web"]["loadBalancer"]["passHostHeader"],
+            json!(false)
+        );
+        assert_eq!(
+            doc["http"]["middlewares"]["h"]["headers"]["customRequestHeaders"]["X-Forwarded-Port"],
+            json!("443")
+        );
+    }
+
+    #[test]
+    fn test_unknown_options_stay_strings() {
+        let doc = to_file_provider(
+            &pairs(&[
+                ("traefik/http/middlewares/auth/basicAuth/realm", "true"),
+                (
+                    "traefik/http/middlewares/strip/stripPrefix/prefixes/0",
+                    "2024",
+                ),
+                (
+                    "traefik/http/services/web/loadBalancer/sticky/cookie/name",
+                    "1",
+                ),
+            ]),
+            "traefik",
+        );
+        assert_eq!(
+            doc["http"]["middlewares"]["auth"]["basicAuth"]["realm"],
+            json!("true")
+        );
+        assert_eq!(
+            doc["http"]["middlewares"]["strip"]["stripPrefix"]["prefixes"],
+            json!(["2024"])
+        );
+        assert_eq!(
+            doc["http"]["services"]["web"]["loadBalancer"]["sticky"]["cookie"]["name"],
+            json!("1")
+        );
+    }
+
+    #[test]
+    fn test_duplicate_keys_keep_the_last_value() {
+        let doc = to_file_provider(
+            &pairs(&[
+                ("traefik/http/routers/web/priority", "10"),
+                ("traefik/http/routers/web/priority", "20"),
+                ("traefik/http/routers/web/tls/certResolver", "le"),
+                ("traefik/http/routers/web/tls", "true"),
+            ]),
+            "traefik",
+        );
+        let router = &doc["http"]["routers"]["web"];
+        assert_eq!(router["priority"], json!(20));
+        assert_eq!(router["tls"], json!({"certResolver": "le"}));
+    }
+
+    #[test]
+    fn test_tcp_section_and_toml_output() {
+        let doc = to_file_provider(
+            &pairs(&[
+                ("traefik/http/routers/web/entryPoints/0", "websecure"),
+                ("traefik/tcp/routers/db/rule", "HostSNI(`*`)"),
+                (
+                    "traefik/tcp/services/db/loadBalancer/servers/0/address",
+                    "10.0.0.1:5432",
+                ),
+            ]),
+            "traefik",
+        );
+        assert_eq!(
+            doc["tcp"]["services"]["db"]["loadBalancer"]["servers"][0]["address"],
+            json!("10.0.0.1:5432")
+        );
+        let rendered = toml::to_string(&doc).unwrap();
+        assert!(rendered.contains("entryPoints = [\"websecure\"]"));
+        assert!(rendered.contains("[[tcp.services.db.loadBalancer.servers]]"));
+    }
+}
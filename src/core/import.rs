@@ -0,0 +1,1214 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use tracing::debug;
+
+use crate::{
+    config::{
+        deployment::{
+            DeploymentConfig, DeploymentProtocol, DeploymentTarget, ServerConfig, TcpConfig,
+        },
+        headers::HeadersConfig,
+        health_check::HealthCheckConfig,
+        host::{HostConfig, PathConfig},
+        middleware::{
+            BasicAuthConfig, CircuitBreakerConfig, ForwardAuthConfig, InFlightConnConfig,
+            IpAllowListConfig, MiddlewareConfig, RateLimitConfig, RedirectRegexConfig,
+            RedirectSchemeConfig, StripPrefixConfig,
+        },
+        selections::{FromClientIpConfig, SelectionConfig, WithCookieConfig},
+        services::{ServiceConfig, WeightedServiceConfig},
+        sticky::StickyConfig,
+    },
+    TraefikConfig,
+};
+
+use super::{etcd_trait::EtcdPair, templating::TemplateOr, util::get_safe_key};
+
+/// A config rebuilt from the keys under a rule prefix, along with what could
+/// not be carried over
+#[derive(Debug, Clone, Default)]
+pub struct ImportedConfig {
+    pub config: TraefikConfig,
+    /// One line per router, service, middleware or key that was skipped
+    pub warnings: Vec<String>,
+}
+
+/// One matcher of a router rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Matcher {
+    Host(String),
+    HostSni(String),
+    PathPrefix(String),
+    Header(String, String),
+    ClientIp(String),
+}
+
+/// Parse a rule made of matchers joined by `&&`, as traefikctl writes them
+///
+/// Returns `None` for rules using `||`, `!`, grouping or other matchers,
+/// which have no equivalent in a host config.
+pub fn parse_rule(rule: &str) -> Option<Vec<Matcher>> {
+    let mut matchers = vec![];
+    let mut rest = rule.trim();
+    loop {
+        let open = rest.find('(')?;
+        let name = rest[..open].trim();
+        let (args, after) = parse_args(&rest[open + 1..])?;
+        let matcher = match (name, args.as_slice()) {
+            ("Host", [domain]) => Matcher::Host(domain.clone()),
+            ("HostSNI", [domain]) => Matcher::HostSni(domain.clone()),
+            ("PathPrefix", [path]) => Matcher::PathPrefix(path.clone()),
+            ("HeaderRegexp", [header, value]) => Matcher::Header(header.clone(), value.clone()),
+            ("ClientIP", [ip]) => Matcher::ClientIp(ip.clone()),
+            _ => return None,
+        };
+        matchers.push(matcher);
+        rest = after.trim_start();
+        if rest.is_empty() {
+            return Some(matchers);
+        }
+        rest = rest.strip_prefix("&&")?.trim_start();
+    }
+}
+
+/// The backtick quoted arguments of a matcher, and what follows its `)`
+fn parse_args(input: &str) -> Option<(Vec<String>, &str)> {
+    let mut args = vec![];
+    let mut rest = input.trim_start();
+    loop {
+        if let Some(after) = rest.strip_prefix(')') {
+            return Some((args, after));
+        }
+        rest = rest.strip_prefix('`')?;
+        let end = rest.find('`')?;
+        args.push(rest[..end].to_string());
+        rest = rest[end + 1..].trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+        }
+    }
+}
+
+/// The keys of one router, service or middleware, by path relative to it
+type Fields = BTreeMap<String, String>;
+
+/// The values of an indexed list like `middlewares/0`, in index order
+fn list(fields: &Fields, prefix: &str) -> Vec<String> {
+    let mut items = fields
+        .iter()
+        .filter_map(|(path, value)| {
+            let index = path.strip_prefix(prefix)?.strip_prefix('/')?;
+            Some((index.parse::<usize>().ok()?, value.clone()))
+        })
+        .collect::<Vec<_>>();
+    items.sort();
+    items.into_iter().map(|(_, value)| value).collect()
+}
+
+/// `scheme://host:port` as a protocol and target, the port defaults to 80
+fn parse_url(url: &str) -> Option<(DeploymentProtocol, DeploymentTarget)> {
+    let (scheme, address) = url.split_once("://")?;
+    let address = address.trim_end_matches('/');
+    let (ip, port) = match address.rsplit_once(':') {
+        Some((ip, port)) => (ip, port.parse().ok()?),
+        None => (address, 80),
+    };
+    Some((
+        DeploymentProtocol::from(scheme),
+        DeploymentTarget::IpAndPort {
+            ip: ip.to_string(),
+            port,
+        },
+    ))
+}
+
+/// The servers of a load balancer, an `address` for tcp and udp and a `url`
+/// otherwise, with the protocol of the first one. A single server without a
+/// weight stays a plain ip and port
+fn load_balancer_target(fields: &Fields) -> Option<(DeploymentProtocol, DeploymentTarget)> {
+    let mut protocol = None;
+    let mut servers = vec![];
+    for idx in 0.. {
+        let server_key = format!("loadBalancer/servers/{idx}");
+        let parsed = match (
+            fields.get(&format!("{server_key}/address")),
+            fields.get(&format!("{server_key}/url")),
+        ) {
+            (Some(address), _) => parse_url(&format!("tcp://{address}")),
+            (None, Some(url)) => parse_url(url),
+            (None, None) => break,
+        };
+        let (server_protocol, DeploymentTarget::IpAndPort { ip, port }) = parsed? else {
+            return None;
+        };
+        protocol.get_or_insert(server_protocol);
+        servers.push(ServerConfig {
+            ip,
+            port,
+            weight: fields
+                .get(&format!("{server_key}/weight"))
+                .and_then(|weight| weight.parse().ok()),
+        });
+    }
+    let target = match servers.as_slice() {
+        [] => return None,
+        [ServerConfig {
+            ip,
+            port,
+            weight: None,
+        }] => DeploymentTarget::IpAndPort {
+            ip: ip.clone(),
+            port: *port,
+        },
+        _ => DeploymentTarget::Servers { servers },
+    };
+    Some((protocol?, target))
+}
+
+/// The `loadBalancer/healthCheck` subtree of a service, when it has one
+fn import_health_check(fields: &Fields) -> Option<HealthCheckConfig> {
+    let check = fields
+        .iter()
+        .filter_map(|(path, value)| Some((path.strip_prefix("loadBalancer/healthCheck/")?, value)))
+        .collect::<BTreeMap<_, _>>();
+    if check.is_empty() {
+        return None;
+    }
+    let mut health_check = HealthCheckConfig::default();
+    for (path, value) in check {
+        match path {
+            "path" => health_check.path = value.clone(),
+            "interval" => health_check.interval = value.clone(),
+            "timeout" => health_check.timeout = value.clone(),
+            "scheme" => health_check.scheme = Some(value.clone()),
+            "hostname" => health_check.hostname = Some(value.clone()),
+            "port" => health_check.port = value.parse().ok(),
+            "status" => health_check.expected_status = value.parse().ok(),
+            "followRedirects" => health_check.follow_redirects = value.parse().ok(),
+            _ => {
+                if let Some(name) = path.strip_prefix("headers/") {
+                    health_check
+                        .headers
+                        .get_or_insert_with(HashMap::new)
+                        .insert(name.to_string(), value.clone());
+                }
+            }
+        }
+    }
+    Some(health_check)
+}
+
+/// The `loadBalancer/sticky/cookie` subtree of a service, when it has one
+/// A weighted service, the services it splits between are imported on
+/// their own
+fn import_weighted_service(fields: &Fields, name: &str) -> ServiceConfig {
+    let mut services = BTreeMap::<usize, WeightedServiceConfig>::new();
+    for (path, value) in fields {
+        let Some((index, field)) = path
+            .strip_prefix("weighted/services/")
+            .and_then(|rest| rest.split_once('/'))
+        else {
+            continue;
+        };
+        let Ok(index) = index.parse() else {
+            continue;
+        };
+        let service = services.entry(index).or_insert(WeightedServiceConfig {
+            name: String::new(),
+            weight: 1,
+        });
+        match field {
+            "name" => service.name = value.clone(),
+            "weight" => service.weight = value.parse().unwrap_or(1),
+            _ => {}
+        }
+    }
+    ServiceConfig {
+        name: name.to_string(),
+        deployment: DeploymentConfig {
+            name: name.to_string(),
+            sticky: import_sticky(fields, "weighted"),
+            ..Default::default()
+        },
+        pass_host_header: false,
+        weighted: Some(services.into_values().collect()),
+    }
+}
+
+fn import_sticky(fields: &Fields, base: &str) -> Option<StickyConfig> {
+    let base = format!("{base}/sticky/cookie");
+    if fields.contains_key(&base) {
+        return Some(StickyConfig::default());
+    }
+    let cookie = fields
+        .iter()
+        .filter_map(|(path, value)| Some((path.strip_prefix(&base)?.strip_prefix('/')?, value)))
+        .collect::<BTreeMap<_, _>>();
+    if cookie.is_empty() {
+        return None;
+    }
+    let mut sticky = StickyConfig::default();
+    for (path, value) in cookie {
+        match path {
+            "name" => sticky.name = Some(value.clone()),
+            "secure" => sticky.secure = value.parse().ok(),
+            "httpOnly" => sticky.http_only = value.parse().ok(),
+            "sameSite" => sticky.same_site = Some(value.clone()),
+            "maxAge" => sticky.max_age = value.parse().ok(),
+            "path" => sticky.path = Some(value.clone()),
+            _ => {}
+        }
+    }
+    Some(sticky)
+}
+
+/// A router reduced to the deployment it was generated from
+#[derive(Debug, Clone)]
+struct ImportedRouter {
+    protocol: String,
+    router: String,
+    domain: String,
+    path: Option<String>,
+    name: String,
+    deployment: DeploymentConfig,
+    middlewares: Vec<String>,
+    strip_prefix: bool,
+    pass_through: bool,
+    forward_host: bool,
+}
+
+#[derive(Debug, Default)]
+struct Importer {
+    rule_prefix: String,
+    /// Objects by protocol, kind and name
+    objects: BTreeMap<(String, String, String), Fields>,
+    warnings: Vec<String>,
+}
+
+impl Importer {
+    fn new(rule_prefix: &str, pairs: &[EtcdPair]) -> Self {
+        let mut importer = Self {
+            rule_prefix: rule_prefix.to_string(),
+            ..Default::default()
+        };
+        for pair in pairs {
+            let Some(relative) = pair
+                .key()
+                .strip_prefix(rule_prefix)
+                .and_then(|key| key.strip_prefix('/'))
+            else {
+                continue;
+            };
+            match relative.splitn(4, '/').collect::<Vec<_>>()[..] {
+                [protocol, kind, name, path] => {
+                    importer
+                        .objects
+                        .entry((protocol.to_string(), kind.to_string(), name.to_string()))
+                        .or_default()
+                        .insert(path.to_string(), pair.value().to_string());
+                }
+                _ => importer
+                    .warnings
+                    .push(format!("key {} is not part of an object", pair.key())),
+            }
+        }
+        importer
+    }
+
+    fn object(&self, protocol: &str, kind: &str, name: &str) -> Option<&Fields> {
+        self.objects
+            .get(&(protocol.to_string(), kind.to_string(), name.to_string()))
+    }
+
+    fn import(mut self) -> ImportedConfig {
+        let mut used: BTreeSet<(String, String, String)> = BTreeSet::new();
+        let mut routers = vec![];
+        let router_keys = self
+            .objects
+            .keys()
+            .filter(|(_, kind, _)| kind == "routers")
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in router_keys {
+            if let Some(router) = self.import_router(&key, &mut used) {
+                routers.push(router);
+            }
+            used.insert(key);
+        }
+
+        let mut middlewares = HashMap::new();
+        for router in routers.iter() {
+            for name in router.middlewares.iter() {
+                if middlewares.contains_key(name) {
+                    continue;
+                }
+                // Prefer the shared definition over the copy made for the router
+                let copy = format!("{}-{}", router.router, name);
+                let source = [name.as_str(), copy.as_str()].into_iter().find(|source| {
+                    self.object(&router.protocol, "middlewares", source)
+                        .is_some()
+                });
+                if let Some(source) = source {
+                    if let Some(middleware) = self.import_middleware(&router.protocol, source, name)
+                    {
+                        middlewares.insert(name.clone(), middleware);
+                    }
+                } else {
+                    self.warnings
+                        .push(format!("middleware '{name}' has no definition"));
+                }
+            }
+        }
+        let unused = self
+            .objects
+            .keys()
+            .filter(|key| !used.contains(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut services = HashMap::new();
+        let mut legacy = vec![];
+        for (protocol, kind, name) in unused {
+            match (protocol.as_str(), kind.as_str()) {
+                ("http" | "tcp", "middlewares") => {
+                    let generated = routers
+                        .iter()
+                        .any(|router| name.starts_with(&format!("{}-", router.router)));
+                    if !generated && !middlewares.contains_key(&name) {
+                        if let Some(middleware) = self.import_middleware(&protocol, &name, &name) {
+                            middlewares.insert(name.clone(), middleware);
+                        }
+                    }
+                }
+                ("http", "services") if name.starts_with("host-") => legacy.push(name),
+                ("http" | "tcp" | "udp", "services") => {
+                    if let Some(service) = self.import_service(&protocol, &name) {
+                        services.insert(name.clone(), service);
+                    }
+                }
+                _ => self
+                    .warnings
+                    .push(format!("{protocol} {kind} '{name}' is not supported")),
+            }
+        }
+
+        let mut hosts = build_hosts(routers);
+        for name in legacy {
+            self.import_legacy_service(&name, &mut hosts);
+        }
+
+        ImportedConfig {
+            config: TraefikConfig {
+                rule_prefix: self.rule_prefix.clone(),
+                hosts: hosts.into_values().collect(),
+                middlewares,
+                services: (!services.is_empty()).then_some(services),
+                ..Default::default()
+            },
+            warnings: self.warnings,
+        }
+    }
+
+    fn import_router(
+        &mut self,
+        key: &(String, String, String),
+        used: &mut BTreeSet<(String, String, String)>,
+    ) -> Option<ImportedRouter> {
+        let (protocol, _, router) = key;
+        let fields = self.objects.get(key)?.clone();
+        let Some(rule) = fields.get("rule") else {
+            let reason = match protocol.as_str() {
+                // The domain is only in the router name, and not recoverable
+                "udp" => "udp routers cannot be matched to a host",
+                _ => "has no rule",
+            };
+            self.warnings
+                .push(format!("{protocol} router '{router}' {reason}"));
+            return None;
+        };
+        let Some(matchers) = parse_rule(rule) else {
+            self.warnings.push(format!(
+                "{protocol} router '{router}': rule {rule} cannot be expressed as a host config"
+            ));
+            return None;
+        };
+
+        let mut domain = None;
+        let mut path = None;
+        let mut selection = SelectionConfig::default();
+        for matcher in matchers {
+            match matcher {
+                Matcher::Host(host) | Matcher::HostSni(host) => domain = Some(host),
+                Matcher::PathPrefix(prefix) => path = Some(prefix),
+                Matcher::Header(header, value) if header == "Cookie" => {
+                    let (name, value) = value.split_once('=').unwrap_or((&value, "true"));
+                    selection.with_cookie = Some(WithCookieConfig {
+                        name: name.to_string(),
+                        value: (value != "true").then(|| value.to_string()),
+                    });
+                }
+                Matcher::ClientIp(ip) => {
+                    let from_client_ip = selection
+                        .from_client_ip
+                        .get_or_insert_with(FromClientIpConfig::default);
+                    match ip.contains('/') {
+                        true => from_client_ip.range = Some(ip),
+                        false => from_client_ip.ip = Some(ip),
+                    }
+                }
+                Matcher::Header(header, _) => {
+                    self.warnings.push(format!(
+                        "{protocol} router '{router}': header {header} cannot be selected on"
+                    ));
+                    return None;
+                }
+            }
+        }
+        let Some(domain) = domain else {
+            self.warnings.push(format!(
+                "{protocol} router '{router}' does not match a host"
+            ));
+            return None;
+        };
+
+        let safe_domain = get_safe_key(&domain);
+        let suffix = match path.is_some() {
+            true => "-path-router",
+            false => "-router",
+        };
+        let name = router
+            .strip_prefix(&format!("{safe_domain}-"))
+            .and_then(|name| name.strip_suffix(suffix))
+            .unwrap_or(router)
+            .to_string();
+
+        let service = fields.get("service").cloned().unwrap_or_default();
+        let generated_service = format!(
+            "{}-{}-{}service",
+            safe_domain,
+            name,
+            if path.is_some() { "path-" } else { "" }
+        );
+        let service_fields = self.object(protocol, "services", &service).cloned();
+        let server = service_fields.as_ref().and_then(load_balancer_target);
+        let (target, health_check, sticky) = match (service == generated_service, server) {
+            (true, Some((_, target))) => {
+                used.insert((protocol.clone(), "services".to_string(), service));
+                let fields = service_fields.as_ref();
+                (
+                    target,
+                    fields.and_then(import_health_check),
+                    fields.and_then(|fields| import_sticky(fields, "loadBalancer")),
+                )
+            }
+            _ => (
+                DeploymentTarget::Service {
+                    service_name: service,
+                },
+                None,
+                None,
+            ),
+        };
+
+        let passthrough = fields
+            .get("tls/passthrough")
+            .is_some_and(|value| value == "true");
+        let mut imported = ImportedRouter {
+            protocol: protocol.clone(),
+            router: router.clone(),
+            domain,
+            path,
+            name: name.clone(),
+            deployment: DeploymentConfig {
+                name,
+                target,
+                selection: (selection != SelectionConfig::default()).then_some(selection),
+                protocol: DeploymentProtocol::from(protocol.as_str()),
+                tcp: passthrough.then(|| TcpConfig {
+                    passthrough,
+                    ..Default::default()
+                }),
+                health_check,
+                sticky,
+                ..Default::default()
+            },
+            middlewares: vec![],
+            strip_prefix: false,
+            pass_through: false,
+            forward_host: false,
+        };
+        // The strip and headers middlewares are generated from the host and
+        // path flags, and are not always listed on the router
+        let strip = format!("{router}-strip");
+        let headers = format!("{router}-headers");
+        if self.object(protocol, "middlewares", &strip).is_some() {
+            imported.strip_prefix = true;
+            used.insert((protocol.clone(), "middlewares".into(), strip.clone()));
+        }
+        if let Some(fields) = self.object(protocol, "middlewares", &headers) {
+            let header =
+                |name: &str| fields.contains_key(&format!("headers/customRequestHeaders/{name}"));
+            imported.forward_host = header("X-Forwarded-Host");
+            imported.pass_through = header("X-Pass-Through");
+            used.insert((protocol.clone(), "middlewares".into(), headers.clone()));
+        }
+        for middleware in list(&fields, "middlewares") {
+            if middleware == strip || middleware == headers {
+                continue;
+            }
+            let name = middleware
+                .strip_prefix(&format!("{router}-"))
+                .unwrap_or(&middleware);
+            imported.middlewares.push(name.to_string());
+        }
+        debug!("Imported router {} as {:?}", router, imported.deployment);
+        Some(imported)
+    }
+
+    fn import_service(&mut self, protocol: &str, name: &str) -> Option<ServiceConfig> {
+        let fields = self.object(protocol, "services", name)?.clone();
+        if protocol == "http" && fields.keys().any(|path| path.starts_with("weighted/")) {
+            return Some(import_weighted_service(&fields, name));
+        }
+        let Some((server_protocol, target)) = load_balancer_target(&fields) else {
+            self.warnings.push(format!(
+                "{protocol} service '{name}' is not a load balancer with a server"
+            ));
+            return None;
+        };
+        Some(ServiceConfig {
+            name: name.to_string(),
+            deployment: DeploymentConfig {
+                name: name.to_string(),
+                target,
+                // Udp servers are written as an address, like tcp ones
+                protocol: match protocol {
+                    "udp" => DeploymentProtocol::Udp,
+                    _ => server_protocol,
+                },
+                health_check: import_health_check(&fields),
+                sticky: import_sticky(&fields, "loadBalancer"),
+                ..Default::default()
+            },
+            pass_host_header: fields
+                .get("loadBalancer/passHostHeader")
+                .is_some_and(|value| value == "true"),
+            weighted: None,
+        })
+    }
+
+    /// Services named `host-{domain}-{deployment}-{index}`, as written before
+    /// routers were generated
+    fn import_legacy_service(&mut self, name: &str, hosts: &mut BTreeMap<String, HostConfig>) {
+        let mut parts = name
+            .strip_prefix("host-")
+            .unwrap_or(name)
+            .split('-')
+            .collect::<Vec<_>>();
+        parts.pop();
+        let (Some(deployment_name), false) = (parts.pop(), parts.is_empty()) else {
+            self.warnings
+                .push(format!("http service '{name}' is not supported"));
+            return;
+        };
+        let domain = parts.join(".");
+        let url = self
+            .object("http", "services", name)
+            .and_then(|fields| fields.get("loadBalancer/servers/0/url"))
+            .cloned();
+        let Some((_, target)) = url.as_deref().and_then(parse_url) else {
+            return;
+        };
+        let host = hosts.entry(domain.clone()).or_insert_with(|| HostConfig {
+            domain,
+            forward_host: true,
+            ..Default::default()
+        });
+        host.deployments.insert(
+            deployment_name.to_string(),
+            DeploymentConfig {
+                name: deployment_name.to_string(),
+                target,
+                protocol: DeploymentProtocol::Http,
+                ..Default::default()
+            },
+        );
+    }
+
+    fn import_middleware(
+        &mut self,
+        protocol: &str,
+        source: &str,
+        name: &str,
+    ) -> Option<MiddlewareConfig> {
+        let fields = self.object(protocol, "middlewares", source)?.clone();
+        let mut middleware = MiddlewareConfig {
+            name: name.to_string(),
+            protocol: match protocol {
+                "tcp" => "tcp".to_string(),
+                _ => "http".to_string(),
+            },
+            ..Default::default()
+        };
+        let value = |path: &str| {
+            fields
+                .get(path)
+                .map(|value| TemplateOr::Static(value.clone()))
+        };
+        let flag = |path: &str| {
+            fields
+                .get(path)
+                .and_then(|value| value.parse::<bool>().ok())
+                .map(TemplateOr::Static)
+        };
+        let number = |path: &str| {
+            fields
+                .get(path)
+                .and_then(|value| value.parse::<u32>().ok())
+                .map(TemplateOr::Static)
+        };
+        let split = |path: &str| {
+            fields.get(path).map(|value| {
+                value
+                    .split(',')
+                    .map(|item| TemplateOr::Static(item.trim().to_string()))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let statics = |items: Vec<String>| {
+            items
+                .into_iter()
+                .map(TemplateOr::Static)
+                .collect::<Vec<_>>()
+        };
+
+        let sections: BTreeSet<&str> = fields
+            .keys()
+            .map(|path| path.split('/').next().unwrap_or_default())
+            .collect();
+        for section in sections {
+            match section {
+                "headers" => middleware.headers = Some(import_headers(&fields)),
+                "forwardAuth" => {
+                    middleware.forward_auth = Some(ForwardAuthConfig {
+                        address: value("forwardAuth/address"),
+                        trust_forward_header: flag("forwardAuth/trustForwardHeader"),
+                        auth_response_headers: split("forwardAuth/authResponseHeaders"),
+                        auth_request_headers: split("forwardAuth/authRequestHeaders"),
+                        auth_response_headers_regex: value("forwardAuth/authResponseHeadersRegex"),
+                    })
+                }
+                "redirectRegex" => {
+                    middleware.redirect_regex = Some(RedirectRegexConfig {
+                        permanent: flag("redirectRegex/permanent"),
+                        regex: value("redirectRegex/regex"),
+                        replacement: value("redirectRegex/replacement"),
+                    })
+                }
+                "redirectScheme" => {
+                    middleware.redirect_scheme = Some(RedirectSchemeConfig {
+                        scheme: value("redirectScheme/scheme"),
+                        permanent: flag("redirectScheme/permanent"),
+                        port: value("redirectScheme/port"),
+                    })
+                }
+                "stripPrefix" => {
+                    middleware.strip_prefix = Some(StripPrefixConfig {
+                        prefixes: Some(statics(list(&fields, "stripPrefix/prefixes"))),
+                        force_slash: flag("stripPrefix/forceSlash"),
+                    })
+                }
+                "rateLimit" => {
+                    middleware.rate_limit = Some(RateLimitConfig {
+                        average: number("rateLimit/average"),
+                        burst: number("rateLimit/burst"),
+                        period: value("rateLimit/period"),
+                    })
+                }
+                "basicAuth" => {
+                    middleware.basic_auth = Some(BasicAuthConfig {
+                        users: statics(list(&fields, "basicAuth/users")),
+                        realm: value("basicAuth/realm"),
+                        header_field: value("basicAuth/headerField"),
+                    })
+                }
+                "compress" => middleware.compress = true,
+                "circuitBreaker" => {
+                    if let Some(expression) = value("circuitBreaker/expression") {
+                        middleware.circuit_breaker = Some(CircuitBreakerConfig { expression });
+                    }
+                }
+                "inFlightConn" => {
+                    if let Some(amount) = number("inFlightConn/amount") {
+                        middleware.in_flight_conn = Some(InFlightConnConfig { amount });
+                    }
+                }
+                "ipAllowList" => {
+                    middleware.ip_allow_list = Some(IpAllowListConfig {
+                        source_range: statics(list(&fields, "ipAllowList/sourceRange")),
+                    })
+                }
+                other => self.warnings.push(format!(
+                    "{protocol} middleware '{source}': {other} is not supported"
+                )),
+            }
+        }
+        Some(middleware)
+    }
+}
+
+fn import_headers(fields: &Fields) -> HeadersConfig {
+    let mut headers = HeadersConfig::default();
+    let split = |value: &str| {
+        value
+            .split(',')
+            .map(|item| TemplateOr::Static(item.trim().to_string()))
+            .collect::<Vec<_>>()
+    };
+    for (path, value) in fields.iter() {
+        let Some(path) = path.strip_prefix("headers/") else {
+            continue;
+        };
+        let static_value = TemplateOr::Static(value.clone());
+        match path.split_once('/') {
+            Some(("customRequestHeaders", header)) => {
+                headers
+                    .custom_request_headers
+                    .insert(header.to_string(), static_value);
+            }
+            Some(("customResponseHeaders", header)) => {
+                headers
+                    .custom_response_headers
+                    .insert(header.to_string(), static_value);
+            }
+            _ => match path {
+                "accessControlAllowMethods" => headers.access_control_allow_methods = split(value),
+                "accessControlAllowHeaders" => headers.access_control_allow_headers = split(value),
+                "accessControlExposeHeaders" => {
+                    headers.access_control_expose_headers = split(value)
+                }
+                "accessControlAllowOriginList" => {
+                    headers.access_control_allow_origin_list = split(value)
+                }
+                "addVaryHeader" => headers.add_vary_header = value == "true",
+                _ => {
+                    headers
+                        .additional_headers
+                        .insert(path.to_string(), static_value);
+                }
+            },
+        }
+    }
+    headers
+}
+
+/// Group the routers into hosts and paths
+///
+/// Middlewares every router of a host uses go on the host, those every
+/// router of a path uses go on the path and the rest on the deployments.
+fn build_hosts(routers: Vec<ImportedRouter>) -> BTreeMap<String, HostConfig> {
+    let mut by_domain: BTreeMap<String, Vec<ImportedRouter>> = BTreeMap::new();
+    for router in routers {
+        by_domain
+            .entry(router.domain.clone())
+            .or_default()
+            .push(router);
+    }
+
+    let common = |routers: &[&ImportedRouter]| -> Vec<String> {
+        let Some(first) = routers.first() else {
+            return vec![];
+        };
+        first
+            .middlewares
+            .iter()
+            .filter(|name| {
+                routers
+                    .iter()
+                    .all(|router| router.middlewares.contains(name))
+            })
+            .cloned()
+            .collect()
+    };
+
+    let mut hosts = BTreeMap::new();
+    for (domain, routers) in by_domain {
+        let all = routers.iter().collect::<Vec<_>>();
+        let host_middlewares = common(&all);
+        let mut host = HostConfig {
+            domain: domain.clone(),
+            middlewares: host_middlewares.clone(),
+            forward_host: routers.iter().any(|router| router.forward_host),
+            ..Default::default()
+        };
+
+        let mut paths: BTreeMap<String, Vec<&ImportedRouter>> = BTreeMap::new();
+        for router in routers.iter() {
+            match &router.path {
+                Some(path) => paths.entry(path.clone()).or_default().push(router),
+                None => {
+                    let mut deployment = router.deployment.clone();
+                    let own = router
+                        .middlewares
+                        .iter()
+                        .filter(|name| !host_middlewares.contains(name))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    deployment.middlewares = (!own.is_empty()).then_some(own);
+                    host.deployments.insert(router.name.clone(), deployment);
+                }
+            }
+        }
+        for (path, routers) in paths {
+            let path_middlewares = common(&routers)
+                .into_iter()
+                .filter(|name| !host_middlewares.contains(name))
+                .collect::<Vec<_>>();
+            let mut path_config = PathConfig {
+                path,
+                middlewares: path_middlewares.clone(),
+                strip_prefix: routers.iter().any(|router| router.strip_prefix),
+                pass_through: routers.iter().any(|router| router.pass_through),
+                ..Default::default()
+            };
+            for router in routers {
+                let mut deployment = router.deployment.clone();
+                let own = router
+                    .middlewares
+                    .iter()
+                    .filter(|name| {
+                        !host_middlewares.contains(name) && !path_middlewares.contains(name)
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                deployment.middlewares = (!own.is_empty()).then_some(own);
+                path_config
+                    .deployments
+                    .insert(router.name.clone(), deployment);
+            }
+            host.paths.push(path_config);
+        }
+        hosts.insert(domain, host);
+    }
+    hosts
+}
+
+/// Rebuild a config from the live keys under `rule_prefix`
+///
+/// Routers are parsed back into hosts, paths, deployments and selections,
+/// and the middlewares and services they use become shared definitions.
+/// Anything without an equivalent in the config is listed in the warnings.
+pub fn import(rule_prefix: &str, pairs: &[EtcdPair]) -> ImportedConfig {
+    Importer::new(rule_prefix, pairs).import()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        core::etcd_trait::ToEtcdPairs,
+        test_helpers::{create_complex_test_config, create_test_config},
+    };
+
+    fn pairs(config: &TraefikConfig) -> HashSet<EtcdPair> {
+        let mut resolver = config.resolver().unwrap();
+        let context = config.context().unwrap();
+        config
+            .to_etcd_pairs(&config.rule_prefix, &mut resolver, &context)
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!(
+            parse_rule(
+                "Host(`a.com`) && HeaderRegexp(`Cookie`, `beta=1`) && ClientIP(`10.0.0.0/8`) && PathPrefix(`/api`)"
+            ),
+            Some(vec![
+                Matcher::Host("a.com".into()),
+                Matcher::Header("Cookie".into(), "beta=1".into()),
+                Matcher::ClientIp("10.0.0.0/8".into()),
+                Matcher::PathPrefix("/api".into()),
+            ])
+        );
+        assert_eq!(parse_rule("Host(`a.com`) || Host(`b.com`)"), None);
+        assert_eq!(parse_rule("Path(`/a`)"), None);
+    }
+
+    #[test]
+    fn test_generated_configs_round_trip() {
+        for config in [create_test_config(None), create_complex_test_config()] {
+            let original = pairs(&config);
+            let prefix = config.rule_prefix.clone();
+            let imported = import(&prefix, &original.iter().cloned().collect::<Vec<_>>());
+            assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+            assert_eq!(pairs(&imported.config), original);
+        }
+    }
+
+    #[test]
+    fn test_tcp_config_round_trips() {
+        let mut config = create_test_config(None);
+        let mut host = HostConfig {
+            domain: "db.example.com".to_string(),
+            ..Default::default()
+        };
+        host.deployments.insert(
+            "primary".to_string(),
+            DeploymentConfig::builder()
+                .name("primary".to_string())
+                .ip_and_port("10.0.0.5".to_string(), 5432)
+                .protocol(DeploymentProtocol::Tcp)
+                .middlewares(vec!["limit".to_string()])
+                .tcp(TcpConfig {
+                    passthrough: true,
+                    ..Default::default()
+                })
+                .build(),
+        );
+        config.hosts.push(host);
+        config.middlewares.insert(
+            "limit".to_string(),
+            MiddlewareConfig {
+                protocol: "tcp".to_string(),
+                ip_allow_list: Some(IpAllowListConfig {
+                    source_range: vec![TemplateOr::Static("10.0.0.0/8".to_string())],
+                }),
+                ..Default::default()
+            },
+        );
+
+        let original = pairs(&config);
+        let imported = import(
+            &config.rule_prefix,
+            &original.iter().cloned().collect::<Vec<_>>(),
+        );
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+        assert_eq!(imported.config.middlewares["limit"].protocol, "tcp");
+        assert_eq!(pairs(&imported.config), original);
+    }
+
+    #[test]
+    fn test_several_servers_round_trip() {
+        let mut config = create_test_config(None);
+        let servers = vec![
+            ServerConfig {
+                ip: "10.0.0.7".to_string(),
+                port: 8080,
+                weight: Some(2),
+            },
+            ServerConfig {
+                ip: "10.0.0.8".to_string(),
+                port: 8080,
+                weight: None,
+            },
+        ];
+        let mut host = HostConfig {
+            domain: "api.example.com".to_string(),
+            ..Default::default()
+        };
+        host.deployments.insert(
+            "blue".to_string(),
+            DeploymentConfig::builder()
+                .name("blue".to_string())
+                .servers(servers.clone())
+                .build(),
+        );
+        config.hosts.push(host);
+        config.services = Some(HashMap::from([(
+            "replicas".to_string(),
+            ServiceConfig {
+                name: "replicas".to_string(),
+                deployment: DeploymentConfig::builder().servers(servers.clone()).build(),
+                pass_host_header: true,
+                weighted: None,
+            },
+        )]));
+
+        let original = pairs(&config);
+        let imported = import(
+            &config.rule_prefix,
+            &original.iter().cloned().collect::<Vec<_>>(),
+        );
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+        let host = imported
+            .config
+            .hosts
+            .iter()
+            .find(|host| host.domain == "api.example.com")
+            .unwrap();
+        assert_eq!(
+            host.deployments["blue"].target,
+            DeploymentTarget::Servers { servers }
+        );
+        assert_eq!(pairs(&imported.config), original);
+    }
+
+    #[test]
+    fn test_sticky_round_trips() {
+        let mut config = create_test_config(None);
+        let mut host = HostConfig {
+            domain: "app.example.com".to_string(),
+            ..Default::default()
+        };
+        host.deployments.insert(
+            "blue".to_string(),
+            DeploymentConfig::builder()
+                .name("blue".to_string())
+                .ip_and_port("10.0.0.7".to_string(), 8080)
+                .sticky(StickyConfig {
+                    name: Some("app_affinity".to_string()),
+                    secure: Some(true),
+                    same_site: Some("lax".to_string()),
+                    ..Default::default()
+                })
+                .build(),
+        );
+        config.hosts.push(host);
+        config.services = Some(HashMap::from([
+            (
+                "sessions".to_string(),
+                ServiceConfig {
+                    name: "sessions".to_string(),
+                    deployment: DeploymentConfig::builder()
+                        .ip_and_port("10.0.0.8".to_string(), 80)
+                        .sticky(StickyConfig::default())
+                        .build(),
+                    pass_host_header: true,
+                    weighted: None,
+                },
+            ),
+            (
+                "split".to_string(),
+                ServiceConfig::builder()
+                    .name("split".to_string())
+                    .deployment(
+                        DeploymentConfig::builder()
+                            .sticky(StickyConfig {
+                                name: Some("split_affinity".to_string()),
+                                http_only: Some(true),
+                                ..Default::default()
+                            })
+                            .build(),
+                    )
+                    .weighted(vec![
+                        WeightedServiceConfig {
+                            name: "sessions".to_string(),
+                            weight: 3,
+                        },
+                        WeightedServiceConfig {
+                            name: "app-example-com-blue-service".to_string(),
+                            weight: 1,
+                        },
+                    ])
+                    .build(),
+            ),
+        ]));
+
+        let original = pairs(&config);
+        let imported = import(
+            &config.rule_prefix,
+            &original.iter().cloned().collect::<Vec<_>>(),
+        );
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+        assert_eq!(pairs(&imported.config), original);
+    }
+
+    #[test]
+    fn test_health_checks_round_trip() {
+        let mut config = create_test_config(None);
+        let mut host = HostConfig {
+            domain: "api.example.com".to_string(),
+            ..Default::default()
+        };
+        host.deployments.insert(
+            "blue".to_string(),
+            DeploymentConfig::builder()
+                .name("blue".to_string())
+                .ip_and_port("10.0.0.7".to_string(), 8080)
+                .health_check(HealthCheckConfig {
+                    path: "/ready".to_string(),
+                    expected_status: Some(204),
+                    headers: Some(HashMap::from([(
+                        "X-Check".to_string(),
+                        "traefik".to_string(),
+                    )])),
+                    ..Default::default()
+                })
+                .build(),
+        );
+        config.hosts.push(host);
+        config.services = Some(HashMap::from([(
+            "checked".to_string(),
+            ServiceConfig {
+                name: "checked".to_string(),
+                deployment: DeploymentConfig::builder()
+                    .ip_and_port("10.0.0.8".to_string(), 80)
+                    .health_check(HealthCheckConfig {
+                        follow_redirects: Some(false),
+                        ..Default::default()
+                    })
+                    .build(),
+                pass_host_header: true,
+                weighted: None,
+            },
+        )]));
+
+        let original = pairs(&config);
+        let imported = import(
+            &config.rule_prefix,
+            &original.iter().cloned().collect::<Vec<_>>(),
+        );
+        assert!(imported.warnings.is_empty(), "{:?}", imported.warnings);
+        assert_eq!(pairs(&imported.config), original);
+    }
+
+    #[test]
+    fn test_hand_written_keys_are_adopted() {
+        let pairs = [
+            ("http/routers/web/rule", "Host(`web.example.com`)"),
+            ("http/routers/web/service", "web"),
+            ("http/routers/web/middlewares/0", "secure"),
+            (
+                "http/services/web/loadBalancer/servers/0/url",
+                "http://10.0.0.5:8080",
+            ),
+            (
+                "http/middlewares/secure/headers/customResponseHeaders/X-Frame-Options",
+                "DENY",
+            ),
+            ("http/routers/legacy/rule", "Host(`a.com`) || Host(`b.com`)"),
+        ]
+        .map(|(key, value)| EtcdPair::new(format!("edge/{key}"), value));
+        let imported = import("edge", &pairs);
+        assert_eq!(imported.warnings.len(), 1, "{:?}", imported.warnings);
+        assert!(imported.warnings[0].contains("'legacy'"));
+
+        let config = imported.config;
+        assert_eq!(config.hosts.len(), 1);
+        let deployment = &config.hosts[0].deployments["web"];
+        assert_eq!(
+            deployment.target,
+            DeploymentTarget::Service {
+                service_name: "web".to_string()
+            }
+        );
+        assert_eq!(config.hosts[0].middlewares, vec!["secure".to_string()]);
+        let service = &config.services.as_ref().unwrap()["web"];
+        assert_eq!(
+            service.deployment.target,
+            DeploymentTarget::IpAndPort {
+                ip: "10.0.0.5".to_string(),
+                port: 8080
+            }
+        );
+        let headers = config.middlewares["secure"].headers.as_ref().unwrap();
+        assert_eq!(
+            headers.custom_response_headers["X-Frame-Options"],
+            TemplateOr::Static("DENY".to_string())
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use export_type::ExportType;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::error::{TraefikError, TraefikResult};
+
+use super::{
+    client::{StoreClient, StoreClientActor, TxnCompare, TxnOp},
+    meta_key,
+};
+
+/// How long to wait between attempts to take a held lock
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The configuration for the lock taken by commands that write the rule prefix
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct LockConfig {
+    /// How long to wait for another writer to release the lock, in seconds
+    pub timeout: u64,
+    /// How long the lock outlives a writer that crashed, in seconds
+    pub ttl: i64,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            ttl: 60,
+        }
+    }
+}
+
+/// Who holds the lock on a rule prefix, stored as the JSON value of the lock key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    /// `user@host`
+    pub identity: String,
+    pub pid: u32,
+    /// The command line the lock was taken by
+    pub command: String,
+    pub acquired_at: DateTime<Utc>,
+}
+
+impl LockHolder {
+    pub fn current() -> Self {
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        let host = std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|host| host.trim().to_string())
+            .filter(|host| !host.is_empty())
+            .unwrap_or_else(|| "unknown".to_string());
+        Self {
+            identity: format!("{user}@{host}"),
+            pid: std::process::id(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+            acquired_at: Utc::now(),
+        }
+    }
+}
+
+impl std::fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (pid {}) since {}: {}",
+            self.identity, self.pid, self.acquired_at, self.command
+        )
+    }
+}
+
+/// A lock taken with [`StoreClient::lock`], release it with [`StoreClient::unlock`]
+#[derive(Debug)]
+#[must_use = "the lock is held until it is released or its ttl runs out"]
+pub struct PrefixLock {
+    key: String,
+    value: String,
+}
+
+/// The key holding the lock on `rule_prefix`
+pub fn lock_key(rule_prefix: &str) -> String {
+    meta_key(rule_prefix, "lock")
+}
+
+impl<T> StoreClient<T>
+where
+    T: StoreClientActor + Send + Sync,
+{
+    /// Take the lock on `rule_prefix`, waiting up to the configured timeout
+    /// for another holder to release it
+    pub async fn lock(&self, rule_prefix: &str, config: &LockConfig) -> TraefikResult<PrefixLock> {
+        let key = lock_key(rule_prefix);
+        let value = serde_json::to_string(&LockHolder::current())?;
+        let deadline = Instant::now() + Duration::from_secs(config.timeout);
+        loop {
+            if self
+                .put_if_absent(key.as_str(), value.as_str(), Some(config.ttl))
+                .await?
+            {
+                debug!("Locked '{}'", rule_prefix);
+                return Ok(PrefixLock { key, value });
+            }
+            if Instant::now() >= deadline {
+                let holder = self
+                    .lock_holder(rule_prefix)
+                    .await?
+                    .map_or_else(|| "another writer".to_string(), |h| h.to_string());
+                return Err(TraefikError::Conflict(format!(
+                    "'{rule_prefix}' is locked by {holder}, run `lock break` if it is stuck"
+                )));
+            }
+            tokio::time::sleep(LOCK_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Run `work` while holding the lock on `rule_prefix`, then release it
+    ///
+    /// The lock's ttl is renewed until the work finishes, so work that takes
+    /// longer than the ttl is not left unprotected. If the lock is broken or
+    /// expires meanwhile the work is dropped unfinished and this fails with
+    /// [`TraefikError::Conflict`].
+    pub async fn with_lock<R>(
+        &self,
+        rule_prefix: &str,
+        config: &LockConfig,
+        work: impl Future<Output = TraefikResult<R>>,
+    ) -> TraefikResult<R> {
+        let lock = self.lock(rule_prefix, config).await?;
+        tokio::pin!(work);
+        let result = tokio::select! {
+            result = &mut work => result,
+            () = self.renew(&lock, config.ttl) => {
+                return Err(TraefikError::Conflict(format!(
+                    "lock '{}' was lost before the work holding it finished",
+                    lock.key
+                )));
+            }
+        };
+        let unlocked = self.unlock(lock).await;
+        let result = result?;
+        unlocked?;
+        Ok(result)
+    }
+
+    /// Renew the lock three times per ttl, returning once it was lost
+    ///
+    /// The lock is rewritten with a fresh ttl only while it still holds our
+    /// value, so a lock broken and taken by someone else is never extended.
+    async fn renew(&self, lock: &PrefixLock, ttl: i64) {
+        let interval = Duration::from_millis(ttl.max(1) as u64 * 1000 / 3);
+        loop {
+            tokio::time::sleep(interval).await;
+            let renewed = self
+                .actor
+                .txn(
+                    vec![TxnCompare::ValueEquals {
+                        key: lock.key.clone().into_bytes(),
+                        value: lock.value.clone().into_bytes(),
+                    }],
+                    vec![TxnOp::put_with_ttl(
+                        lock.key.as_str(),
+                        lock.value.as_str(),
+                        ttl,
+                    )],
+                )
+                .await;
+            match renewed {
+                Ok(true) => {}
+                Ok(false) => {
+                    warn!("Lock '{}' was broken while it was held", lock.key);
+                    return;
+                }
+                Err(e) => warn!("Failed to renew lock '{}': {e}", lock.key),
+            }
+        }
+    }
+
+    /// Release a lock, unless it was broken and taken by someone else since
+    pub async fn unlock(&self, lock: PrefixLock) -> TraefikResult<()> {
+        let released = self
+            .actor
+            .txn(
+                vec![TxnCompare::ValueEquals {
+                    key: lock.key.clone().into_bytes(),
+                    value: lock.value.into_bytes(),
+                }],
+                vec![TxnOp::delete(lock.key.as_str())],
+            )
+            .await?;
+        if !released {
+            debug!("Lock '{}' was broken before it was released", lock.key);
+        }
+        Ok(())
+    }
+
+    /// The current holder of the lock on `rule_prefix`, if any
+    pub async fn lock_holder(&self, rule_prefix: &str) -> TraefikResult<Option<LockHolder>> {
+        match self.get(lock_key(rule_prefix)).await {
+            Ok(kv) => Ok(Some(serde_json::from_slice(&kv.value)?)),
+            Err(TraefikError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Forcibly release the lock on `rule_prefix`, returning who held it
+    pub async fn break_lock(&self, rule_prefix: &str) -> TraefikResult<Option<LockHolder>> {
+        let holder = self.lock_holder(rule_prefix).await?;
+        self.delete(lock_key(rule_prefix)).await?;
+        Ok(holder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::features::memory::MemoryStore;
+
+    use super::*;
+
+    fn no_wait() -> LockConfig {
+        LockConfig {
+            timeout: 0,
+            ttl: 60,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lock_is_exclusive() {
+        let client = StoreClient::new(MemoryStore::new());
+        let lock = client.lock("traefik", &no_wait()).await.unwrap();
+        let holder = client.lock_holder("traefik").await.unwrap().unwrap();
+        assert_eq!(holder.pid, std::process::id());
+
+        let result = client.lock("traefik", &no_wait()).await;
+        assert!(matches!(result, Err(TraefikError::Conflict(_))));
+        // Other prefixes are not affected
+        let other = client.lock("other", &no_wait()).await.unwrap();
+
+        client.unlock(lock).await.unwrap();
+        client.unlock(other).await.unwrap();
+        assert!(client.lock_holder("traefik").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_break_lock() {
+        let client = StoreClient::new(MemoryStore::new());
+        let stuck = client.lock("traefik", &no_wait()).await.unwrap();
+        let holder = client.break_lock("traefik").await.unwrap();
+        assert!(holder.is_some());
+
+        let lock = client.lock("traefik", &no_wait()).await.unwrap();
+        // Releasing the broken lock leaves the new holder alone
+        client.unlock(stuck).await.unwrap();
+        assert!(client.lock_holder("traefik").await.unwrap().is_some());
+        client.unlock(lock).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lock_is_renewed_while_held() {
+        let client = StoreClient::new(MemoryStore::new());
+        let config = LockConfig { timeout: 0, ttl: 1 };
+        let holder = client
+            .with_lock("traefik", &config, async {
+                tokio::time::sleep(Duration::from_millis(2500)).await;
+                client.lock_holder("traefik").await
+            })
+            .await
+            .unwrap();
+        assert!(holder.is_some());
+        assert!(client.lock_holder("traefik").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_work_is_dropped_when_the_lock_is_broken() {
+        let client = StoreClient::new(MemoryStore::new());
+        let config = LockConfig { timeout: 0, ttl: 1 };
+        let finished = AtomicBool::new(false);
+        let result = client
+            .with_lock("traefik", &config, async {
+                client.break_lock("traefik").await?;
+                let taken = client.lock("traefik", &no_wait()).await?;
+                tokio::time::sleep(Duration::from_millis(1500)).await;
+                finished.store(true, Ordering::SeqCst);
+                Ok(taken)
+            })
+            .await;
+        match result {
+            Err(TraefikError::Conflict(message)) => {
+                assert!(message.contains("_traefikctl/traefik/lock"))
+            }
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+        assert!(!finished.load(Ordering::SeqCst));
+        // The new holder's lock was neither renewed over nor released
+        let holder = client.lock_holder("traefik").await.unwrap();
+        assert!(holder.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_lock_waits_for_release() {
+        let client = StoreClient::new(MemoryStore::new());
+        let lock = client.lock("traefik", &no_wait()).await.unwrap();
+        let waiter = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                let config = LockConfig {
+                    timeout: 5,
+                    ttl: 60,
+                };
+                client.lock("traefik", &config).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        client.unlock(lock).await.unwrap();
+        assert!(waiter.await.unwrap().is_ok());
+    }
+}
@@ -2,13 +2,30 @@ use templating::{TemplateContext, TemplateResolver};
 
 use crate::error::TraefikResult;
 
+pub mod agent;
+pub mod backup;
 pub mod client;
+#[cfg(feature = "drift")]
+pub mod drift;
+pub mod file_provider;
+pub mod import;
+pub mod lock;
+pub mod ownership;
+pub mod prune;
 pub mod rules;
 pub mod templating;
 pub mod util;
 
 pub static TCP_BASE_KEY: &str = "traefik/tcp";
 pub static HTTP_BASE_KEY: &str = "traefik/http";
+/// Bookkeeping keys written by traefikctl live under this prefix, outside of
+/// any `rule_prefix` so traefik never reads them
+pub static META_BASE_KEY: &str = "_traefikctl";
+
+/// The bookkeeping key `name` for the configuration under `rule_prefix`
+pub fn meta_key(rule_prefix: &str, name: &str) -> String {
+    format!("{META_BASE_KEY}/{rule_prefix}/{name}")
+}
 
 #[cfg(feature = "etcd")]
 pub mod etcd_trait;
@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::features::{etcd::EtcdDiff, KeyValue};
+
+use super::{client::TxnOp, etcd_trait::EtcdPair, meta_key};
+
+/// The prefix holding the ownership markers of the keys under `rule_prefix`
+///
+/// Every key written by a config with an `owner` has a marker at
+/// `{markers_prefix}{key relative to the rule prefix}` holding the owner.
+/// Keys without a marker are unowned.
+pub fn markers_prefix(rule_prefix: &str) -> String {
+    format!("{}/", meta_key(rule_prefix, "owners"))
+}
+
+/// The ownership marker of `key`, a key under `rule_prefix`
+pub fn marker_key(rule_prefix: &str, key: &str) -> String {
+    let relative = key
+        .strip_prefix(rule_prefix)
+        .unwrap_or(key)
+        .trim_start_matches('/');
+    format!("{}{}", markers_prefix(rule_prefix), relative)
+}
+
+/// The router, service or middleware a key belongs to, as
+/// `{protocol}/{kind}/{name}` relative to the rule prefix
+pub(crate) fn object_of(relative_key: &str) -> String {
+    let segments = relative_key.splitn(4, '/').collect::<Vec<_>>();
+    match segments.len() {
+        0..=3 => relative_key.to_string(),
+        _ => segments[..3].join("/"),
+    }
+}
+
+/// An object another owner holds keys of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnershipConflict {
+    /// `{protocol}/{kind}/{name}` relative to the rule prefix
+    pub object: String,
+    pub owner: String,
+}
+
+/// An object as `{protocol} {kind} '{name}'`, like `http router 'web'`
+pub(crate) fn describe_object(object: &str) -> String {
+    match object.splitn(3, '/').collect::<Vec<_>>()[..] {
+        [protocol, kind, name] => format!("{} {} '{}'", protocol, kind.trim_end_matches('s'), name),
+        _ => format!("'{}'", object),
+    }
+}
+
+impl std::fmt::Display for OwnershipConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is owned by '{}'",
+            describe_object(&self.object),
+            self.owner
+        )
+    }
+}
+
+/// Who owns the keys under a rule prefix, read from the ownership markers
+#[derive(Debug, Clone, Default)]
+pub struct Ownership {
+    rule_prefix: String,
+    /// Owners by key
+    owners: HashMap<String, String>,
+}
+
+impl Ownership {
+    pub fn new(rule_prefix: &str, markers: Vec<KeyValue>) -> Self {
+        let prefix = markers_prefix(rule_prefix);
+        let owners = markers
+            .into_iter()
+            .map(EtcdPair::from)
+            .filter_map(|pair| {
+                let relative = pair.key().strip_prefix(&prefix)?;
+                Some((
+                    format!("{}/{}", rule_prefix, relative),
+                    pair.value().to_string(),
+                ))
+            })
+            .collect();
+        Self {
+            rule_prefix: rule_prefix.to_string(),
+            owners,
+        }
+    }
+
+    pub fn owner_of(&self, key: &str) -> Option<&str> {
+        self.owners.get(key).map(String::as_str)
+    }
+
+    fn marker_key(&self, key: &str) -> String {
+        marker_key(&self.rule_prefix, key)
+    }
+
+    fn relative<'a>(&self, key: &'a str) -> &'a str {
+        key.strip_prefix(&self.rule_prefix)
+            .unwrap_or(key)
+            .trim_start_matches('/')
+    }
+
+    /// The objects `pairs` would write to that hold live keys of another
+    /// owner, unowned keys are free to be taken
+    pub fn conflicts(
+        &self,
+        pairs: &[EtcdPair],
+        live: &[EtcdPair],
+        owner: Option<&str>,
+    ) -> Vec<OwnershipConflict> {
+        let mut live_owners: HashMap<String, &str> = HashMap::new();
+        for pair in live {
+            if let Some(live_owner) = self.owner_of(pair.key()) {
+                if Some(live_owner) != owner {
+                    live_owners.insert(object_of(self.relative(pair.key())), live_owner);
+                }
+            }
+        }
+        let mut conflicts = BTreeMap::new();
+        for pair in pairs {
+            let object = object_of(self.relative(pair.key()));
+            if let Some(live_owner) = live_owners.get(&object) {
+                conflicts.insert(object, live_owner.to_string());
+            }
+        }
+        conflicts
+            .into_iter()
+            .map(|(object, owner)| OwnershipConflict { object, owner })
+            .collect()
+    }
+
+    /// Split `pairs` into the pairs `owner` may write and the objects of
+    /// other owners they would overwrite
+    pub fn without_foreign(
+        &self,
+        pairs: &[EtcdPair],
+        live: &[EtcdPair],
+        owner: Option<&str>,
+    ) -> (Vec<EtcdPair>, Vec<OwnershipConflict>) {
+        let conflicts = self.conflicts(pairs, live, owner);
+        let foreign: HashSet<&str> = conflicts
+            .iter()
+            .map(|conflict| conflict.object.as_str())
+            .collect();
+        let writable = pairs
+            .iter()
+            .filter(|pair| !foreign.contains(object_of(self.relative(pair.key())).as_str()))
+            .cloned()
+            .collect();
+        (writable, conflicts)
+    }
+
+    /// The live keys `owner` holds, the unowned keys when `owner` is `None`
+    pub fn held_by(&self, live: &[EtcdPair], owner: Option<&str>) -> Vec<EtcdPair> {
+        live.iter()
+            .filter(|pair| self.owner_of(pair.key()) == owner)
+            .cloned()
+            .collect()
+    }
+
+    /// The marker writes that follow an apply of `diff` by `owner`
+    ///
+    /// Written keys are marked as held by `owner`, and the markers of keys
+    /// removed by a clean are deleted with them.
+    pub fn marker_ops(
+        &self,
+        diff: &EtcdDiff,
+        owner: Option<&str>,
+        should_clean: bool,
+    ) -> Vec<TxnOp> {
+        let mut ops = vec![];
+        let written = diff
+            .added
+            .iter()
+            .chain(diff.modified.iter().map(|(_old, new)| new))
+            .chain(diff.unchanged.iter());
+        for pair in written {
+            let marked = self.owner_of(pair.key());
+            match owner {
+                Some(owner) if marked != Some(owner) => {
+                    ops.push(TxnOp::put(self.marker_key(pair.key()), owner))
+                }
+                None if marked.is_some() => ops.push(TxnOp::delete(self.marker_key(pair.key()))),
+                _ => {}
+            }
+        }
+        if should_clean {
+            for pair in diff.removed.iter() {
+                if self.owner_of(pair.key()).is_some() {
+                    ops.push(TxnOp::delete(self.marker_key(pair.key())));
+                }
+            }
+        }
+        ops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ownership(markers: &[(&str, &str)]) -> Ownership {
+        let kvs = markers
+            .iter()
+            .map(|(key, owner)| KeyValue {
+                key: format!("{}{}", markers_prefix("traefik"), key).into_bytes(),
+                value: owner.as_bytes().to_vec(),
+            })
+            .collect();
+        Ownership::new("traefik", kvs)
+    }
+
+    fn pairs(keys: &[&str]) -> Vec<EtcdPair> {
+        keys.iter()
+            .map(|key| EtcdPair::new(format!("traefik/{key}"), "v"))
+            .collect()
+    }
+
+    #[test]
+    fn test_conflicts_are_reported_per_object() {
+        let ownership = ownership(&[
+            ("http/routers/web/rule", "team-a"),
+            ("http/routers/web/service", "team-a"),
+            ("http/services/api/loadBalancer/servers/0/url", "team-b"),
+        ]);
+        let live = pairs(&[
+            "http/routers/web/rule",
+            "http/routers/web/service",
+            "http/services/api/loadBalancer/servers/0/url",
+        ]);
+        let desired = pairs(&["http/routers/web/priority", "http/routers/web/rule"]);
+
+        let conflicts = ownership.conflicts(&desired, &live, Some("team-b"));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].to_string(),
+            "http router 'web' is owned by 'team-a'"
+        );
+        assert!(ownership
+            .conflicts(&desired, &live, Some("team-a"))
+            .is_empty());
+        // A config without an owner may not take owned objects either
+        assert_eq!(ownership.conflicts(&desired, &live, None).len(), 1);
+    }
+
+    #[test]
+    fn test_unowned_and_stale_markers_do_not_conflict() {
+        // The marker of a key that no longer exists is ignored
+        let ownership = ownership(&[("http/routers/gone/rule", "team-a")]);
+        let live = pairs(&["http/routers/legacy/rule"]);
+        let desired = pairs(&["http/routers/legacy/rule", "http/routers/gone/rule"]);
+        assert!(ownership
+            .conflicts(&desired, &live, Some("team-b"))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_held_by_and_marker_ops() {
+        let ownership = ownership(&[
+            ("http/routers/a/rule", "team-a"),
+            ("http/routers/b/rule", "team-b"),
+        ]);
+        let live = pairs(&[
+            "http/routers/a/rule",
+            "http/routers/b/rule",
+            "http/routers/c/rule",
+        ]);
+        let held = ownership.held_by(&live, Some("team-a"));
+        assert_eq!(held, pairs(&["http/routers/a/rule"]));
+        assert_eq!(
+            ownership.held_by(&live, None),
+            pairs(&["http/routers/c/rule"])
+        );
+
+        let diff = EtcdDiff::create(&pairs(&["http/routers/d/rule"]), &held);
+        let ops = ownership.marker_ops(&diff, Some("team-a"), true);
+        assert_eq!(
+            ops,
+            vec![
+                TxnOp::put(
+                    format!("{}http/routers/d/rule", markers_prefix("traefik")),
+                    "team-a"
+                ),
+                TxnOp::delete(format!("{}http/routers/a/rule", markers_prefix("traefik"))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use super::{etcd_trait::EtcdPair, ownership::object_of};
+
+/// A key whose value names a router, service or middleware that does not exist
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DanglingReference {
+    pub key: String,
+    /// The object the key refers to, as `{protocol}/{kind}/{name}`
+    pub target: String,
+}
+
+impl std::fmt::Display for DanglingReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {} (missing)", self.key, self.target)
+    }
+}
+
+/// The keys left behind by renamed or deleted hosts, and the references
+/// that would still dangle once they are gone
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PrunePlan {
+    pub rule_prefix: String,
+    /// The store revision the live keys were read at
+    pub revision: i64,
+    /// Orphan keys grouped by the router, service or middleware they belong to
+    pub orphans: BTreeMap<String, Vec<EtcdPair>>,
+    pub dangling: Vec<DanglingReference>,
+}
+
+impl PrunePlan {
+    /// Compare the live keys with the pairs the config generates
+    ///
+    /// Only keys in `held`, the live keys the config may write, are orphans.
+    /// References are checked against every live key that is kept.
+    pub fn new(
+        rule_prefix: &str,
+        revision: i64,
+        desired: &[EtcdPair],
+        live: &[EtcdPair],
+        held: &[EtcdPair],
+    ) -> Self {
+        let desired_keys: HashSet<&str> = desired.iter().map(|pair| pair.key()).collect();
+        let mut orphans: BTreeMap<String, Vec<EtcdPair>> = BTreeMap::new();
+        for pair in held {
+            if !desired_keys.contains(pair.key()) {
+                let object = object_of(relative(rule_prefix, pair.key()));
+                orphans.entry(object).or_default().push(pair.clone());
+            }
+        }
+        for pairs in orphans.values_mut() {
+            pairs.sort_by(|a, b| a.key().cmp(b.key()));
+        }
+
+        let pruned: HashSet<&str> = orphans.values().flatten().map(|pair| pair.key()).collect();
+        let kept = live
+            .iter()
+            .filter(|pair| !pruned.contains(pair.key()))
+            .collect::<Vec<_>>();
+        let objects: HashSet<String> = kept
+            .iter()
+            .map(|pair| object_of(relative(rule_prefix, pair.key())))
+            .collect();
+        let mut dangling = kept
+            .iter()
+            .filter_map(|pair| {
+                let target = reference(relative(rule_prefix, pair.key()), pair.value())?;
+                (!objects.contains(&target)).then(|| DanglingReference {
+                    key: pair.key().to_string(),
+                    target,
+                })
+            })
+            .collect::<Vec<_>>();
+        dangling.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Self {
+            rule_prefix: rule_prefix.to_string(),
+            revision,
+            orphans,
+            dangling,
+        }
+    }
+
+    pub fn orphan_keys(&self) -> Vec<EtcdPair> {
+        self.orphans.values().flatten().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty() && self.dangling.is_empty()
+    }
+
+    pub fn display(&self) {
+        if self.orphans.is_empty() {
+            println!("No orphan keys under '{}'", self.rule_prefix);
+        } else {
+            println!(
+                "{} orphan keys under '{}' in {} objects:",
+                self.orphans.values().map(Vec::len).sum::<usize>(),
+                self.rule_prefix,
+                self.orphans.len()
+            );
+            for (object, pairs) in self.orphans.iter() {
+                println!("  {} ({} keys)", object, pairs.len());
+                for pair in pairs {
+                    println!("    {}", pair.key());
+                }
+            }
+        }
+        if !self.dangling.is_empty() {
+            println!("{} dangling references:", self.dangling.len());
+            for reference in self.dangling.iter() {
+                println!("  {}", reference);
+            }
+        }
+    }
+}
+
+fn relative<'a>(rule_prefix: &str, key: &'a str) -> &'a str {
+    key.strip_prefix(rule_prefix)
+        .unwrap_or(key)
+        .trim_start_matches('/')
+}
+
+/// The object a key refers to by name, if any
+///
+/// Names qualified with a provider, like `api@internal`, live outside of the
+/// store and are not checked.
+fn reference(relative_key: &str, value: &str) -> Option<String> {
+    if value.is_empty() || value.contains('@') {
+        return None;
+    }
+    let segments = relative_key.split('/').collect::<Vec<_>>();
+    let (protocol, kind, rest) = match segments.as_slice() {
+        [protocol, kind, _name, rest @ ..] => (*protocol, *kind, rest),
+        _ => return None,
+    };
+    let target_kind = match (kind, rest) {
+        ("routers", ["service"]) => "services",
+        ("routers", ["middlewares", _]) => "middlewares",
+        ("middlewares", ["chain", "middlewares", _]) => "middlewares",
+        ("services", ["weighted", "services", _, "name"]) => "services",
+        ("services", ["mirroring", "service"]) => "services",
+        ("services", ["mirroring", "mirrors", _, "name"]) => "services",
+        _ => return None,
+    };
+    Some(format!("{protocol}/{target_kind}/{value}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::pairs;
+
+    use super::*;
+
+    #[test]
+    fn test_orphans_are_grouped_by_object() {
+        let desired = pairs(&[
+            ("traefik/http/routers/new/rule", "Host(`new`)"),
+            ("traefik/http/routers/new/service", "new"),
+            (
+                "traefik/http/services/new/loadBalancer/servers/0/url",
+                "http://a",
+            ),
+        ]);
+        let mut live = desired.clone();
+        live.extend(pairs(&[
+            ("traefik/http/routers/old/rule", "Host(`old`)"),
+            ("traefik/http/routers/old/service", "old"),
+            (
+                "traefik/http/services/old/loadBalancer/servers/0/url",
+                "http://b",
+            ),
+        ]));
+        let plan = PrunePlan::new("traefik", 7, &desired, &live, &live);
+        assert_eq!(
+            plan.orphans.keys().collect::<Vec<_>>(),
+            vec!["http/routers/old", "http/services/old"]
+        );
+        assert_eq!(plan.orphans["http/routers/old"].len(), 2);
+        assert_eq!(plan.orphan_keys().len(), 3);
+        // Pruning the router and its service together leaves nothing dangling
+        assert!(plan.dangling.is_empty());
+    }
+
+    #[test]
+    fn test_dangling_references_are_flagged() {
+        let live = pairs(&[
+            ("traefik/http/routers/web/rule", "Host(`web`)"),
+            ("traefik/http/routers/web/service", "gone"),
+            ("traefik/http/routers/web/middlewares/0", "headers"),
+            ("traefik/http/routers/web/middlewares/1", "auth@file"),
+            ("traefik/http/routers/dash/service", "api@internal"),
+            (
+                "traefik/http/services/split/weighted/services/0/name",
+                "gone",
+            ),
+        ]);
+        // `held` is empty, so nothing is pruned and only references are checked
+        let plan = PrunePlan::new("traefik", 7, &live, &live, &[]);
+        assert!(plan.orphans.is_empty());
+        let targets = plan
+            .dangling
+            .iter()
+            .map(|reference| reference.target.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            vec![
+                "http/middlewares/headers",
+                "http/services/gone",
+                "http/services/gone"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pruning_a_service_in_use_is_flagged() {
+        let desired = pairs(&[("traefik/http/routers/web/service", "api")]);
+        let live = pairs(&[
+            ("traefik/http/routers/web/service", "api"),
+            (
+                "traefik/http/services/api/loadBalancer/servers/0/url",
+                "http://a",
+            ),
+        ]);
+        let plan = PrunePlan::new("traefik", 7, &desired, &live, &live);
+        assert_eq!(plan.dangling.len(), 1);
+        assert_eq!(plan.dangling[0].key, "traefik/http/routers/web/service");
+    }
+}
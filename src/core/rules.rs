@@ -58,6 +58,7 @@ impl Display for Rule {
             RuleType::Host => format!("Host(`{}`)", self.value),
             RuleType::ClientIp => format!("ClientIP(`{}`)", self.value),
             RuleType::TcpHost => format!("HostSNI(`{}`)", self.value),
+            RuleType::TcpHostRegexp => format!("HostSNIRegexp(`{}`)", self.value),
         };
         write!(f, "{}", rule_str)
     }
@@ -212,16 +213,21 @@ impl RuleConfig {
         self
     }
 
+    pub fn add_tcp_regexp_rule(&mut self, pattern: &str) -> &mut Self {
+        self.add_rule("HostSNIRegexp", pattern, RuleType::TcpHostRegexp);
+        self
+    }
+
     pub fn rule_str(&self) -> String {
         // Sort rules to ensure consistent ordering
         let mut rules: Vec<_> = self.rules.iter().collect();
         rules.sort_by_key(|rule| {
             match rule.rule_type {
-                RuleType::Host => 0,     // Host rules first
-                RuleType::Header => 1,   // Then Header rules
-                RuleType::ClientIp => 2, // Then ClientIP rules
-                RuleType::TcpHost => 3,  // TCP Host rules
-                RuleType::Other => 4,    // Other rules last
+                RuleType::Host => 0,                              // Host rules first
+                RuleType::Header => 1,                            // Then Header rules
+                RuleType::ClientIp => 2,                          // Then ClientIP rules
+                RuleType::TcpHost | RuleType::TcpHostRegexp => 3, // TCP Host rules
+                RuleType::Other => 4,                             // Other rules last
             }
         });
 
@@ -243,6 +249,7 @@ impl RuleConfig {
 /// Header rules are used to match the headers of the request
 /// ClientIP rules are used to match the client IP of the request
 /// TcpHost rules are used to match the SNI of the request
+/// TcpHostRegexp rules are used to match the SNI against a regular expression
 /// Other rules are used to match other types of rules
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub enum RuleType {
@@ -250,6 +257,7 @@ pub enum RuleType {
     Header,
     ClientIp,
     TcpHost,
+    TcpHostRegexp,
     Other,
 }
 
@@ -445,7 +453,7 @@ impl InternalDeploymentConfig {
     fn add_service_configuration(&mut self, base_key: &str) -> TraefikResult<Vec<EtcdPair>> {
         let mut pairs = Vec::new();
         match &self.deployment.target {
-            DeploymentTarget::IpAndPort { .. } => {
+            DeploymentTarget::IpAndPort { .. } | DeploymentTarget::Servers { .. } => {
                 pairs.push(EtcdPair::new(
                     format!("{}/routers/{}/service", base_key, self.get_router_name()),
                     self.get_service_name().clone(),
@@ -471,6 +479,21 @@ impl InternalDeploymentConfig {
         debug!("Adding root router for {}", router_name);
 
         let router_key = format!("{}/routers/{}", base_key, router_name);
+        if let Some(udp) = self
+            .deployment
+            .udp
+            .as_ref()
+            .filter(|_| self.deployment.is_udp())
+        {
+            for (idx, entry_point) in udp.entry_points.iter().enumerate() {
+                pairs.push(EtcdPair::new(
+                    format!("{}/entryPoints/{}", router_key, idx),
+                    entry_point,
+                ));
+            }
+            debug!("Added udp entrypoints: {:?}", udp.entry_points);
+            return Ok(pairs);
+        }
         pairs.push(EtcdPair::new(
             format!("{}/rule", router_key),
             rule.rule_str(),
@@ -486,8 +509,21 @@ impl InternalDeploymentConfig {
             "websecure",
         ));
         debug!("Added entrypoint: websecure");
-        pairs.push(EtcdPair::new(format!("{}/tls", router_key), "true"));
-        debug!("Added tls: true");
+        if self
+            .deployment
+            .tcp
+            .as_ref()
+            .is_some_and(|tcp| tcp.passthrough)
+        {
+            pairs.push(EtcdPair::new(
+                format!("{}/tls/passthrough", router_key),
+                "true",
+            ));
+            debug!("Added tls passthrough: true");
+        } else {
+            pairs.push(EtcdPair::new(format!("{}/tls", router_key), "true"));
+            debug!("Added tls: true");
+        }
 
         // Set priority based on rule complexity
         pairs.push(EtcdPair::new(
@@ -582,7 +618,8 @@ impl InternalDeploymentConfig {
         _context: &TemplateContext,
     ) -> TraefikResult<Vec<EtcdPair>> {
         let mut pairs = Vec::new();
-        if self.host_config.forward_host {
+        // Headers cannot be set on tcp or udp connections
+        if self.host_config.forward_host && self.deployment.router_protocol() == "http" {
             let forward_host_pairs =
                 self.add_forward_host_middleware(collected_middlewares, base_key)?;
             pairs.extend(forward_host_pairs);
@@ -799,16 +836,39 @@ impl InternalDeploymentConfig {
         );
 
         match &self.deployment.target {
-            DeploymentTarget::IpAndPort { ip, port } => {
+            DeploymentTarget::IpAndPort { .. } | DeploymentTarget::Servers { .. }
+                if self.deployment.is_tcp() || self.deployment.is_udp() =>
+            {
                 let base_key = format!("{}/services/{}", base_key, deployment_service_name);
                 debug!(
-                    "Adding service {} pointing to http://{}:{}",
-                    deployment_service_name, ip, port
+                    "Adding {} service {} pointing to {}",
+                    self.deployment.protocol, deployment_service_name, self.deployment.target
                 );
-                pairs.push(EtcdPair::new(
-                    format!("{}/loadBalancer/servers/0/url", base_key),
-                    format!("http://{}:{}", ip, port),
-                ));
+                for (idx, server) in self.deployment.target.servers().iter().enumerate() {
+                    pairs.push(EtcdPair::new(
+                        format!("{}/loadBalancer/servers/{}/address", base_key, idx),
+                        server.to_string(),
+                    ));
+                }
+            }
+            DeploymentTarget::IpAndPort { .. } | DeploymentTarget::Servers { .. } => {
+                let base_key = format!("{}/services/{}", base_key, deployment_service_name);
+                debug!(
+                    "Adding service {} pointing to {}",
+                    deployment_service_name, self.deployment.target
+                );
+                for (idx, server) in self.deployment.target.servers().iter().enumerate() {
+                    pairs.push(EtcdPair::new(
+                        format!("{}/loadBalancer/servers/{}/url", base_key, idx),
+                        format!("http://{}", server),
+                    ));
+                    if let Some(weight) = server.weight {
+                        pairs.push(EtcdPair::new(
+                            format!("{}/loadBalancer/servers/{}/weight", base_key, idx),
+                            weight.to_string(),
+                        ));
+                    }
+                }
                 pairs.push(EtcdPair::new(
                     format!("{}/loadBalancer/passHostHeader", base_key),
                     "true".to_string(),
@@ -817,6 +877,26 @@ impl InternalDeploymentConfig {
                     format!("{}/loadBalancer/responseForwarding/flushInterval", base_key),
                     "100ms".to_string(),
                 ));
+                // The deployment's own health check wins over the host default
+                if let Some(health_check) = self
+                    .deployment
+                    .health_check
+                    .as_ref()
+                    .or(self.host_config.health_check.as_ref())
+                {
+                    pairs.extend(health_check.to_etcd_pairs(
+                        &format!("{}/loadBalancer", base_key),
+                        resolver,
+                        context,
+                    )?);
+                }
+                if let Some(sticky) = &self.deployment.sticky {
+                    pairs.extend(sticky.to_etcd_pairs(
+                        &format!("{}/loadBalancer", base_key),
+                        resolver,
+                        context,
+                    )?);
+                }
             }
             DeploymentTarget::Service { service_name } => {
                 // let base_key = format!("{}/services/{}", base_key, service_name);
@@ -892,8 +972,18 @@ impl InternalDeploymentConfig {
                 rules.add_host_rule(&self.host_config.domain);
             }
             DeploymentProtocol::Tcp => {
-                rules.add_tcp_rule(&self.host_config.domain);
+                match self
+                    .deployment
+                    .tcp
+                    .as_ref()
+                    .and_then(|tcp| tcp.sni_regexp.as_deref())
+                {
+                    Some(pattern) => rules.add_tcp_regexp_rule(pattern),
+                    None => rules.add_tcp_rule(&self.host_config.domain),
+                };
             }
+            // Udp routers only have entry points, there is nothing to match on
+            DeploymentProtocol::Udp => {}
             DeploymentProtocol::Invalid => {
                 error!("Invalid deployment protocol for {}", self.name);
             }
@@ -1026,7 +1116,14 @@ mod tests {
     #[allow(unused_imports)]
     use crate::test_helpers::init_test_tracing;
     use crate::{
-        config::{headers::HeadersConfig, middleware::MiddlewareConfig, services::ServiceConfig},
+        config::{
+            deployment::{ServerConfig, TcpConfig, UdpConfig},
+            headers::HeadersConfig,
+            health_check::HealthCheckConfig,
+            middleware::{InFlightConnConfig, MiddlewareConfig},
+            services::ServiceConfig,
+            sticky::StickyConfig,
+        },
         test_helpers::{
             assert_contains_pair, assert_does_not_contain_pair, create_complex_test_config,
             create_test_config, create_test_deployment, create_test_host, create_test_resolver,
@@ -1563,6 +1660,312 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_deployment_with_several_servers() {
+        let mut host = create_test_host();
+        host.domain = "api.example.com".to_string();
+        host.paths.clear();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let servers = vec![
+            ServerConfig {
+                ip: "10.0.0.1".to_string(),
+                port: 8080,
+                weight: Some(3),
+            },
+            ServerConfig {
+                ip: "10.0.0.2".to_string(),
+                port: 8080,
+                weight: None,
+            },
+        ];
+
+        let mut deployments = vec![
+            InternalDeploymentConfig {
+                name: "blue".to_string(),
+                deployment: DeploymentConfig::builder()
+                    .name("blue".to_string())
+                    .servers(servers.clone())
+                    .build(),
+                host_config: host.clone(),
+                ..Default::default()
+            },
+            InternalDeploymentConfig {
+                name: "db".to_string(),
+                deployment: DeploymentConfig::builder()
+                    .name("db".to_string())
+                    .servers(
+                        servers
+                            .iter()
+                            .map(|server| ServerConfig {
+                                weight: None,
+                                ..server.clone()
+                            })
+                            .collect(),
+                    )
+                    .protocol(DeploymentProtocol::Tcp)
+                    .build(),
+                host_config: host.clone(),
+                ..Default::default()
+            },
+        ];
+        let pairs =
+            add_deployment_rules(&mut deployments, "test", &mut resolver, &context).unwrap();
+
+        let blue = "test/http/services/api-example-com-blue-service/loadBalancer/servers";
+        assert_contains_pair(&pairs, &format!("{blue}/0/url http://10.0.0.1:8080"));
+        assert_contains_pair(&pairs, &format!("{blue}/0/weight 3"));
+        assert_contains_pair(&pairs, &format!("{blue}/1/url http://10.0.0.2:8080"));
+        assert!(!pairs
+            .iter()
+            .any(|pair| pair.key() == format!("{blue}/1/weight")));
+        let db = "test/tcp/services/api-example-com-db-service/loadBalancer/servers";
+        assert_contains_pair(&pairs, &format!("{db}/0/address 10.0.0.1:8080"));
+        assert_contains_pair(&pairs, &format!("{db}/1/address 10.0.0.2:8080"));
+        assert_contains_pair(
+            &pairs,
+            "test/http/routers/api-example-com-blue-router/service api-example-com-blue-service",
+        );
+    }
+
+    #[test]
+    fn test_sticky_deployment_with_weighted_servers() {
+        let mut host = create_test_host();
+        host.domain = "app.example.com".to_string();
+        host.paths.clear();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+
+        let mut deployments = vec![InternalDeploymentConfig {
+            name: "blue".to_string(),
+            deployment: DeploymentConfig::builder()
+                .name("blue".to_string())
+                .servers(vec![
+                    ServerConfig {
+                        ip: "10.0.0.1".to_string(),
+                        port: 8080,
+                        weight: Some(3),
+                    },
+                    ServerConfig {
+                        ip: "10.0.0.2".to_string(),
+                        port: 8080,
+                        weight: Some(1),
+                    },
+                ])
+                .sticky(StickyConfig {
+                    name: Some("app_affinity".to_string()),
+                    http_only: Some(true),
+                    max_age: Some(600),
+                    ..Default::default()
+                })
+                .build(),
+            host_config: host.clone(),
+            ..Default::default()
+        }];
+        let pairs =
+            add_deployment_rules(&mut deployments, "test", &mut resolver, &context).unwrap();
+
+        let lb = "test/http/services/app-example-com-blue-service/loadBalancer";
+        assert_contains_pair(&pairs, &format!("{lb}/servers/0/weight 3"));
+        assert_contains_pair(&pairs, &format!("{lb}/servers/1/weight 1"));
+        assert_contains_pair(&pairs, &format!("{lb}/sticky/cookie/name app_affinity"));
+        assert_contains_pair(&pairs, &format!("{lb}/sticky/cookie/httpOnly true"));
+        assert_contains_pair(&pairs, &format!("{lb}/sticky/cookie/maxAge 600"));
+    }
+
+    #[test]
+    fn test_health_check_defaults_to_the_host() {
+        let mut host = create_test_host();
+        host.domain = "api.example.com".to_string();
+        host.paths.clear();
+        host.health_check = Some(HealthCheckConfig::default());
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+
+        let mut deployments = vec![
+            InternalDeploymentConfig {
+                name: "blue".to_string(),
+                deployment: DeploymentConfig::builder()
+                    .name("blue".to_string())
+                    .ip_and_port("10.0.0.7".to_string(), 8080)
+                    .build(),
+                host_config: host.clone(),
+                ..Default::default()
+            },
+            InternalDeploymentConfig {
+                name: "green".to_string(),
+                deployment: DeploymentConfig::builder()
+                    .name("green".to_string())
+                    .ip_and_port("10.0.0.8".to_string(), 8080)
+                    .health_check(HealthCheckConfig {
+                        path: "/ready".to_string(),
+                        interval: "2s".to_string(),
+                        ..Default::default()
+                    })
+                    .build(),
+                host_config: host.clone(),
+                ..Default::default()
+            },
+            InternalDeploymentConfig {
+                name: "db".to_string(),
+                deployment: DeploymentConfig::builder()
+                    .name("db".to_string())
+                    .ip_and_port("10.0.0.9".to_string(), 5432)
+                    .protocol(DeploymentProtocol::Tcp)
+                    .build(),
+                host_config: host.clone(),
+                ..Default::default()
+            },
+        ];
+        let pairs =
+            add_deployment_rules(&mut deployments, "test", &mut resolver, &context).unwrap();
+
+        let blue = "test/http/services/api-example-com-blue-service/loadBalancer/healthCheck";
+        assert_contains_pair(&pairs, &format!("{blue}/path /health"));
+        assert_contains_pair(&pairs, &format!("{blue}/interval 10s"));
+        let green = "test/http/services/api-example-com-green-service/loadBalancer/healthCheck";
+        assert_contains_pair(&pairs, &format!("{green}/path /ready"));
+        assert_contains_pair(&pairs, &format!("{green}/interval 2s"));
+        assert!(
+            pairs
+                .iter()
+                .all(|pair| !pair.key().starts_with("test/tcp")
+                    || !pair.key().contains("healthCheck"))
+        );
+    }
+
+    #[test]
+    fn test_udp_deployment_rules() {
+        let mut host = create_test_host();
+        host.domain = "dns.example.com".to_string();
+        host.forward_host = true;
+        host.middlewares.clear();
+        host.paths.clear();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+
+        let mut deployment = InternalDeploymentConfig {
+            name: "resolver".to_string(),
+            deployment: DeploymentConfig::builder()
+                .ip_and_port("10.0.0.53".to_string(), 53)
+                .protocol(DeploymentProtocol::Udp)
+                .udp(UdpConfig {
+                    entry_points: vec!["dns".to_string(), "dns-alt".to_string()],
+                })
+                .build(),
+            host_config: host.clone(),
+            ..Default::default()
+        };
+        deployment.init(&mut resolver, &context);
+        let mut deployments = vec![deployment];
+        let pairs =
+            add_deployment_rules(&mut deployments, "test", &mut resolver, &context).unwrap();
+
+        let router = "test/udp/routers/dns-example-com-resolver-router";
+        assert_contains_pair(&pairs, &format!("{router}/entryPoints/0 dns"));
+        assert_contains_pair(&pairs, &format!("{router}/entryPoints/1 dns-alt"));
+        assert_contains_pair(
+            &pairs,
+            &format!("{router}/service dns-example-com-resolver-service"),
+        );
+        assert_contains_pair(
+            &pairs,
+            "test/udp/services/dns-example-com-resolver-service/loadBalancer/servers/0/address 10.0.0.53:53",
+        );
+        assert!(pairs.iter().all(|pair| {
+            let key = pair.key();
+            !key.ends_with("/rule")
+                && !key.ends_with("/tls")
+                && !key.ends_with("/priority")
+                && !key.contains("middlewares")
+        }));
+    }
+
+    #[test]
+    fn test_tcp_deployment_rules() {
+        let mut host = create_test_host();
+        host.domain = "db.example.com".to_string();
+        host.forward_host = true;
+        host.paths.clear();
+        let mut traefik_config = TraefikConfig::default();
+        traefik_config.middlewares.insert(
+            "limit".to_string(),
+            MiddlewareConfig {
+                protocol: "tcp".to_string(),
+                in_flight_conn: Some(InFlightConnConfig {
+                    amount: TemplateOr::Static(5),
+                }),
+                ..Default::default()
+            },
+        );
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+
+        let mut deployment = InternalDeploymentConfig {
+            name: "primary".to_string(),
+            deployment: DeploymentConfig::builder()
+                .ip_and_port("10.0.0.5".to_string(), 5432)
+                .protocol(DeploymentProtocol::Tcp)
+                .middlewares(vec!["limit".to_string()])
+                .tcp(TcpConfig {
+                    passthrough: true,
+                    ..Default::default()
+                })
+                .build(),
+            host_config: host.clone(),
+            traefik_config,
+            ..Default::default()
+        };
+        deployment.init(&mut resolver, &context);
+        let mut deployments = vec![deployment];
+        let pairs =
+            add_deployment_rules(&mut deployments, "test", &mut resolver, &context).unwrap();
+
+        let router = "test/tcp/routers/db-example-com-primary-router";
+        assert_contains_pair(&pairs, &format!("{router}/rule HostSNI(`db.example.com`)"));
+        assert_contains_pair(&pairs, &format!("{router}/tls/passthrough true"));
+        assert_does_not_contain_pair(&pairs, &format!("{router}/tls true"));
+        assert_contains_pair(
+            &pairs,
+            &format!("{router}/middlewares/0 db-example-com-primary-router-limit"),
+        );
+        assert_contains_pair(
+            &pairs,
+            "test/tcp/middlewares/db-example-com-primary-router-limit/inFlightConn/amount 5",
+        );
+        assert_contains_pair(
+            &pairs,
+            "test/tcp/services/db-example-com-primary-service/loadBalancer/servers/0/address 10.0.0.5:5432",
+        );
+        assert!(pairs
+            .iter()
+            .all(|pair| !pair.key().contains("passHostHeader") && !pair.key().contains("headers")));
+    }
+
+    #[test]
+    fn test_tcp_deployment_with_sni_regexp() {
+        let host = create_test_host();
+        let mut resolver = create_test_resolver();
+        let context = create_test_template_context();
+        let mut deployment = InternalDeploymentConfig {
+            name: "primary".to_string(),
+            deployment: DeploymentConfig::builder()
+                .protocol(DeploymentProtocol::Tcp)
+                .tcp(TcpConfig {
+                    sni_regexp: Some("^db-[0-9]+\\.example\\.com$".to_string()),
+                    ..Default::default()
+                })
+                .build(),
+            host_config: host,
+            ..Default::default()
+        };
+        deployment.init(&mut resolver, &context);
+        assert_eq!(
+            deployment.rules.rule_str(),
+            "HostSNIRegexp(`^db-[0-9]+\\.example\\.com$`)"
+        );
+    }
+
     #[test]
     fn test_add_deployment_rules_empty_deployments() {
         let mut resolver = create_test_resolver();
@@ -250,6 +250,13 @@ impl TemplateContext {
                 deployment_json["ip"] = serde_json::to_value(ip).unwrap();
                 deployment_json["port"] = serde_json::to_value(port).unwrap();
             }
+            // Templates see the first server as the deployment's ip and port
+            DeploymentTarget::Servers { servers } => {
+                if let Some(server) = servers.first() {
+                    deployment_json["ip"] = serde_json::to_value(&server.ip).unwrap();
+                    deployment_json["port"] = serde_json::to_value(server.port).unwrap();
+                }
+            }
             DeploymentTarget::Service { service_name } => {
                 match traefik_config.get_service(&service_name) {
                     Some(service) => match &service.deployment.target {
@@ -257,6 +264,13 @@ impl TemplateContext {
                             deployment_json["ip"] = serde_json::to_value(ip).unwrap();
                             deployment_json["port"] = serde_json::to_value(port).unwrap();
                         }
+                        DeploymentTarget::Servers { servers } => {
+                            if let Some(server) = servers.first() {
+                                deployment_json["ip"] = serde_json::to_value(&server.ip).unwrap();
+                                deployment_json["port"] =
+                                    serde_json::to_value(server.port).unwrap();
+                            }
+                        }
                         DeploymentTarget::Service { service_name } => {
                             deployment_json["service_name"] =
                                 serde_json::to_value(service_name).unwrap();
@@ -99,6 +99,35 @@ pub fn get_safe_key(key: &str) -> String {
     key.replace(".", "-").replace("/", "-")
 }
 
+/// Whether the value is a duration traefik can parse, like `10s` or `1m30s`
+pub fn is_duration(value: &str) -> bool {
+    if value == "0" {
+        return true;
+    }
+    let mut rest = value;
+    if rest.is_empty() {
+        return false;
+    }
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number = &rest[..number_len];
+        if number.is_empty() || number == "." || number.matches('.').count() > 1 {
+            return false;
+        }
+        rest = &rest[number_len..];
+        let Some(unit) = ["ns", "us", "µs", "ms", "s", "m", "h"]
+            .iter()
+            .find(|unit| rest.starts_with(*unit))
+        else {
+            return false;
+        };
+        rest = &rest[unit.len()..];
+    }
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -113,6 +142,16 @@ mod tests {
         assert!(validate_is_alphanumeric("$#test/").is_err());
     }
 
+    #[test]
+    fn test_is_duration() {
+        for valid in ["0", "10s", "500ms", "1m30s", "1.5h", "250us"] {
+            assert!(is_duration(valid), "{valid} should be a duration");
+        }
+        for invalid in ["", "10", "s", "ten seconds", "10x", "-5s", "1..5s"] {
+            assert!(!is_duration(invalid), "{invalid} should not be a duration");
+        }
+    }
+
     #[test]
     fn test_format_header_key() {
         assert_eq!(format_header_key("test"), "Test");
@@ -11,7 +11,7 @@ pub type TraefikResult<T = (), E = TraefikError> = color_eyre::Result<T, E>;
 #[derive(Error, Debug)]
 pub enum TraefikError {
     #[error("IO error: {0}")]
-    IOError(Box<dyn std::error::Error>),
+    IOError(Box<dyn std::error::Error + Send + Sync>),
 
     #[error("anyhow error: {0}")]
     AnyhowError(#[from] anyhow::Error),
@@ -22,6 +22,9 @@ pub enum TraefikError {
     #[error("Etcd error: {0}")]
     EtcdError(String),
 
+    #[error("Redis error: {0}")]
+    RedisError(String),
+
     #[error("Config error: {0}")]
     ConfigError(String),
 
@@ -46,6 +49,9 @@ pub enum TraefikError {
     #[error("Health check config error: {0}")]
     HealthCheckConfig(String),
 
+    #[error("Sticky config error: {0}")]
+    StickyConfig(String),
+
     #[error("Parse error: {0}")]
     ParsingError(#[from] color_eyre::Report),
 
@@ -58,9 +64,20 @@ pub enum TraefikError {
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::Error),
 
+    #[error("TOML error: {0}")]
+    TomlError(#[from] toml::ser::Error),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// A transient failure of the store, such as a leader election, that is
+    /// worth retrying
+    #[error("Unavailable: {0}")]
+    Unavailable(String),
+
     #[cfg(feature = "api")]
     #[error("Database error: {0}")]
     DatabaseError(String),
@@ -91,14 +108,20 @@ pub enum TraefikError {
     Utf8Error(#[from] Utf8Error),
 }
 
+impl TraefikError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, TraefikError::Unavailable(_))
+    }
+}
+
 impl From<tera::Error> for TraefikError {
     fn from(e: tera::Error) -> Self {
         TraefikError::Template(e.to_string())
     }
 }
 
-impl From<Box<dyn std::error::Error>> for TraefikError {
-    fn from(e: Box<dyn std::error::Error>) -> Self {
+impl From<Box<dyn std::error::Error + Send + Sync>> for TraefikError {
+    fn from(e: Box<dyn std::error::Error + Send + Sync>) -> Self {
         TraefikError::IOError(e)
     }
 }
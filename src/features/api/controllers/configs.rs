@@ -12,7 +12,7 @@ use crate::{
     features::{
         db,
         file_loader::{save_file_config, FileConfig},
-        models::SaveConfigRequest,
+        models::{ConfigDiffResponse, SaveConfigRequest},
         TraefikApiError, TraefikApiResult, TraefikConfigListItem, TraefikConfigSource,
     },
     TraefikConfig,
@@ -402,6 +402,44 @@ pub async fn get_config_history(
     Ok(history)
 }
 
+pub async fn get_config_version(
+    pool: &Pool<MySql>,
+    id: i64,
+) -> TraefikApiResult<ConfigVersionHistory> {
+    sqlx::query_as::<_, ConfigVersionHistory>(
+        r#"
+        SELECT id, config_id, name, config, created_at, version
+        FROM config_version_history
+        WHERE id = ?
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| TraefikApiError::NotFound(format!("Config version {} not found", id)))
+}
+
+/// Compare two stored versions the way `traefikctl diff --from --to` does
+pub async fn diff_config_versions(
+    pool: &Pool<MySql>,
+    from_id: i64,
+    to_id: i64,
+) -> TraefikApiResult<ConfigDiffResponse> {
+    let old_config: TraefikConfig =
+        serde_json::from_value(get_config_version(pool, from_id).await?.config)?;
+    let new_config: TraefikConfig =
+        serde_json::from_value(get_config_version(pool, to_id).await?.config)?;
+    let diff = new_config
+        .diff_against(&old_config)
+        .map_err(|e| TraefikApiError::unprocessable_entity([("config", e.to_string())]))?;
+    Ok(ConfigDiffResponse {
+        from: from_id,
+        to: to_id,
+        diff: diff.report(),
+        changes: diff.semantic(&new_config.rule_prefix),
+    })
+}
+
 pub async fn create_config_backup(
     pool: &Pool<MySql>,
     config_id: i64,
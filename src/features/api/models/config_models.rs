@@ -2,6 +2,8 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::features::etcd::{DiffReport, ObjectChange};
+
 #[derive(Serialize, Deserialize)]
 pub enum TraefikConfigSource {
     Database,
@@ -42,6 +44,20 @@ pub struct TraefikConfigListItem {
 //     }
 // }
 
+/// The difference between two stored versions of a config
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConfigDiffResponse {
+    /// The id of the old version
+    pub from: i64,
+    /// The id of the new version
+    pub to: i64,
+    #[schema(value_type = Object)]
+    pub diff: DiffReport,
+    /// The changes grouped by router, service and middleware
+    #[schema(value_type = Vec<Object>)]
+    pub changes: Vec<ObjectChange>,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct SaveConfigRequest {
     pub name: String,
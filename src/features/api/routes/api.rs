@@ -11,7 +11,10 @@ use utoipa_swagger_ui::SwaggerUi;
 use super::api;
 use crate::{
     config::traefik_config::{ConfigVersionHistory, TraefikConfigVersion},
-    features::{models::SaveConfigRequest, ServerConfig, TemplateInfo},
+    features::{
+        models::{ConfigDiffResponse, SaveConfigRequest},
+        ServerConfig, TemplateInfo,
+    },
     TraefikConfig,
 };
 
@@ -37,6 +40,7 @@ pub async fn handler() -> impl IntoResponse {
         api::configs::save_config_version,
         api::configs::delete_config,
         api::configs::get_config_history,
+        api::configs::diff_config_versions,
         api::configs::create_config_backup,
         // Templates
         api::templates::list_templates,
@@ -50,6 +54,7 @@ pub async fn handler() -> impl IntoResponse {
             SaveConfigRequest,
             TraefikConfigVersion,
             ConfigVersionHistory,
+            ConfigDiffResponse,
             TemplateInfo,
             TraefikConfig,
         )
@@ -5,7 +5,7 @@ use tracing::{debug, error};
 
 use crate::config::traefik_config::{ConfigVersionHistory, TraefikConfigVersion};
 use crate::features::controllers::configs::{get_database_config, SearchConfigsParams};
-use crate::features::models::SaveConfigRequest;
+use crate::features::models::{ConfigDiffResponse, SaveConfigRequest};
 use crate::features::routes::ApiContext;
 use crate::features::{controllers, TraefikApiResult, TraefikConfigListItem};
 use crate::TraefikConfig;
@@ -22,6 +22,7 @@ pub fn routes() -> Router {
         .route("/configs/id/:id", get(get_config_by_id))
         .route("/configs/update/:id", put(update_config))
         .route("/configs/history/:id", get(get_config_history))
+        .route("/configs/diff/:from/:to", get(diff_config_versions))
         .route("/configs/delete/:id", delete(delete_config))
 }
 
@@ -245,6 +246,28 @@ pub(crate) async fn get_config_history(
     Ok(Json(history))
 }
 
+/// Diff two versions from the history of a config
+#[utoipa::path(
+    get,
+    path = "/api/configs/diff/{from}/{to}",
+    params(
+        ("from" = i64, Path, description = "Id of the old version"),
+        ("to" = i64, Path, description = "Id of the new version")
+    ),
+    responses(
+        (status = 200, description = "Diff computed successfully", body = ConfigDiffResponse),
+        (status = 404, description = "Version not found")
+    ),
+    tags = ["config"]
+)]
+pub(crate) async fn diff_config_versions(
+    ctx: Extension<ApiContext>,
+    Path((from, to)): Path<(i64, i64)>,
+) -> TraefikApiResult<Json<ConfigDiffResponse>> {
+    let diff = controllers::configs::diff_config_versions(&ctx.db, from, to).await?;
+    Ok(Json(diff))
+}
+
 /// Create backup of current config state
 #[utoipa::path(
     post,
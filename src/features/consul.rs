@@ -0,0 +1,1076 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use color_eyre::eyre::eyre;
+use export_type::ExportType;
+use futures_util::{stream, StreamExt};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+
+use crate::{
+    core::client::{
+        StoreClientActor, StoreSnapshot, TxnCompare, TxnOp, WatchEvent, WatchEventKind, WatchStream,
+    },
+    error::{TraefikError, TraefikResult},
+};
+
+use super::KeyValue;
+
+/// Consul refuses sessions with a TTL outside of this range (in seconds)
+const SESSION_TTL_RANGE: (i64, i64) = (10, 86400);
+
+/// How long a blocking query waits for a change before returning
+const WATCH_WAIT: Duration = Duration::from_secs(60);
+
+/// The configuration for the consul client
+#[derive(Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct ConsulConfig {
+    /// The address of the consul HTTP API
+    pub address: String,
+    /// Never serialized, so rendering or exporting a config does not print it
+    #[serde(default, skip_serializing)]
+    pub token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datacenter: Option<String>,
+    pub timeout: u64,
+    /// The maximum number of operations sent in a single transaction, consul
+    /// accepts at most 64
+    pub max_txn_ops: usize,
+}
+
+// The config is logged on apply, so the token is never printed
+impl std::fmt::Debug for ConsulConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConsulConfig")
+            .field("address", &self.address)
+            .field("token", &self.token.as_ref().map(|_| "<hidden>"))
+            .field("datacenter", &self.datacenter)
+            .field("timeout", &self.timeout)
+            .field("max_txn_ops", &self.max_txn_ops)
+            .finish()
+    }
+}
+
+impl Default for ConsulConfig {
+    fn default() -> Self {
+        Self {
+            address: "http://127.0.0.1:8500".to_owned(),
+            token: None,
+            datacenter: None,
+            timeout: 2000,
+            max_txn_ops: 64,
+        }
+    }
+}
+
+/// A key as returned by the consul KV API, values are base64 encoded
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulEntry {
+    key: String,
+    value: Option<String>,
+    session: Option<String>,
+    modify_index: i64,
+}
+
+impl ConsulEntry {
+    fn decoded_value(&self) -> TraefikResult<Vec<u8>> {
+        match &self.value {
+            Some(value) => Ok(STANDARD
+                .decode(value)
+                .map_err(|e| eyre!("consul value for '{}' is not base64: {e}", self.key))?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn to_key_value(&self) -> TraefikResult<KeyValue> {
+        Ok(KeyValue {
+            key: self.key.as_bytes().to_vec(),
+            value: self.decoded_value()?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulSession {
+    #[serde(rename = "ID")]
+    id: String,
+}
+
+#[derive(Clone)]
+pub struct Consul {
+    pub client: Client,
+    pub config: ConsulConfig,
+}
+
+impl std::fmt::Debug for Consul {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Consul {{ address: {} }}", self.config.address)
+    }
+}
+
+impl Consul {
+    pub fn new(config: &ConsulConfig) -> TraefikResult<Self> {
+        debug!("Connecting to consul at {}", config.address);
+        let client = Client::builder()
+            .timeout(Duration::from_millis(config.timeout))
+            .build()
+            .map_err(|e| eyre!("consul client failed: {e}"))?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+        })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}/v1/{path}", self.config.address.trim_end_matches('/'));
+        let mut request = self.client.request(method, url);
+        if let Some(token) = &self.config.token {
+            request = request.header("X-Consul-Token", token);
+        }
+        if let Some(datacenter) = &self.config.datacenter {
+            request = request.query(&[("dc", datacenter)]);
+        }
+        request
+    }
+
+    fn kv(&self, method: Method, key: &[u8]) -> RequestBuilder {
+        self.request(method, &format!("kv/{}", encode_key(key)))
+    }
+
+    async fn send(request: RequestBuilder) -> TraefikResult<Response> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| eyre!("consul request failed: {e}"))?;
+        let status = response.status();
+        if status.is_success() || status == StatusCode::NOT_FOUND || status == StatusCode::CONFLICT
+        {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(eyre!("consul request failed with {status}: {body}").into())
+    }
+
+    async fn body<T: for<'de> Deserialize<'de>>(response: Response) -> TraefikResult<T> {
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| eyre!("consul response failed: {e}"))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// The `X-Consul-Index` of a response, the raft index the read was served at
+    fn index(response: &Response) -> i64 {
+        response
+            .headers()
+            .get("X-Consul-Index")
+            .and_then(|index| index.to_str().ok())
+            .and_then(|index| index.parse().ok())
+            .unwrap_or_default()
+    }
+
+    async fn entry(&self, key: &[u8]) -> TraefikResult<Option<ConsulEntry>> {
+        let response = Self::send(self.kv(Method::GET, key)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let entries: Vec<ConsulEntry> = Self::body(response).await?;
+        Ok(entries.into_iter().next())
+    }
+
+    /// Read every entry under `prefix`, blocking until the prefix changes
+    /// past `wait_index` when one is given
+    async fn entries(
+        &self,
+        prefix: &[u8],
+        wait_index: Option<i64>,
+    ) -> TraefikResult<(Vec<ConsulEntry>, i64)> {
+        let mut request = self.kv(Method::GET, prefix).query(&[("recurse", "true")]);
+        if let Some(index) = wait_index {
+            request = request
+                .query(&[
+                    ("index", index.to_string()),
+                    ("wait", format!("{}s", WATCH_WAIT.as_secs())),
+                ])
+                .timeout(WATCH_WAIT + Duration::from_millis(self.config.timeout));
+        }
+        let response = Self::send(request).await?;
+        let index = Self::index(&response);
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok((Vec::new(), index));
+        }
+        Ok((Self::body(response).await?, index))
+    }
+
+    async fn create_session(&self, ttl: i64) -> TraefikResult<String> {
+        let ttl = ttl.clamp(SESSION_TTL_RANGE.0, SESSION_TTL_RANGE.1);
+        let body = json!({ "TTL": format!("{ttl}s"), "Behavior": "delete", "LockDelay": "0s" });
+        let response = Self::send(
+            self.request(Method::PUT, "session/create")
+                .json_body(&body)?,
+        )
+        .await?;
+        let session: ConsulSession = Self::body(response).await?;
+        Ok(session.id)
+    }
+
+    fn txn_operation(verb: &str, key: &[u8]) -> serde_json::Value {
+        json!({ "KV": { "Verb": verb, "Key": String::from_utf8_lossy(key) } })
+    }
+
+    /// Fails the transaction unless `key` was last modified at `index`
+    fn check_index(key: &[u8], index: i64) -> serde_json::Value {
+        let mut operation = Self::txn_operation("check-index", key);
+        operation["KV"]["Index"] = json!(index);
+        operation
+    }
+}
+
+/// Percent-encode every segment of a key, keeping the `/` between them, so
+/// keys with `?`, `#`, `%` or spaces reach consul as written
+fn encode_key(key: &[u8]) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(*byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// reqwest is built without its `json` feature
+trait JsonBody {
+    fn json_body(self, body: &serde_json::Value) -> TraefikResult<RequestBuilder>;
+}
+
+impl JsonBody for RequestBuilder {
+    fn json_body(self, body: &serde_json::Value) -> TraefikResult<RequestBuilder> {
+        Ok(self
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_vec(body)?))
+    }
+}
+
+#[async_trait]
+impl StoreClientActor for Consul {
+    async fn get(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<KeyValue> {
+        self.entry(&key.into())
+            .await?
+            .ok_or_else(|| TraefikError::NotFound("data not found".into()))?
+            .to_key_value()
+    }
+
+    async fn get_with_prefix(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+    ) -> TraefikResult<Vec<KeyValue>> {
+        Ok(self.snapshot(key).await?.kvs)
+    }
+
+    async fn get_keys(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<Vec<KeyValue>> {
+        let response =
+            Self::send(self.kv(Method::GET, &key.into()).query(&[("keys", "true")])).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> = Self::body(response).await?;
+        Ok(keys
+            .into_iter()
+            .map(|key| KeyValue {
+                key: key.into_bytes(),
+                value: Vec::new(),
+            })
+            .collect())
+    }
+
+    async fn put(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<Option<KeyValue>> {
+        let key = key.into();
+        let prev = self.entry(&key).await?;
+        let mut request = self.kv(Method::PUT, &key).body(value.into());
+        if let Some(ttl) = ttl.filter(|ttl| *ttl > 0) {
+            // Keys acquired by a session are deleted when the session expires
+            let session = self.create_session(ttl).await?;
+            request = request.query(&[("acquire", session)]);
+        }
+        let written: bool = Self::body(Self::send(request).await?).await?;
+        if !written {
+            return Err(eyre!(
+                "consul put failed: '{}' is locked",
+                String::from_utf8_lossy(&key)
+            )
+            .into());
+        }
+        prev.map(|entry| entry.to_key_value()).transpose()
+    }
+
+    async fn delete(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
+        let key = key.into();
+        if self.entry(&key).await?.is_none() {
+            return Ok(0);
+        }
+        Self::send(self.kv(Method::DELETE, &key)).await?;
+        Ok(1)
+    }
+
+    async fn delete_with_prefix(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
+        let key = key.into();
+        let deleted = self.get_keys(key.clone()).await?.len() as i64;
+        Self::send(self.kv(Method::DELETE, &key).query(&[("recurse", "true")])).await?;
+        Ok(deleted)
+    }
+
+    async fn touch(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<()> {
+        if let Some(session) = self.entry(&key.into()).await?.and_then(|e| e.session) {
+            Self::send(self.request(Method::PUT, &format!("session/renew/{session}"))).await?;
+        }
+        Ok(())
+    }
+
+    async fn put_or_touch(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<()> {
+        let key = key.into();
+        if self.entry(&key).await?.is_some() {
+            self.touch(key).await
+        } else {
+            self.put(key, value, ttl).await.map(|_| ())
+        }
+    }
+
+    /// A check-and-set with index `0` only writes keys that do not exist
+    async fn put_if_absent(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<bool> {
+        let mut request = self
+            .kv(Method::PUT, &key.into())
+            .query(&[("cas", "0")])
+            .body(value.into());
+        if let Some(ttl) = ttl.filter(|ttl| *ttl > 0) {
+            let session = self.create_session(ttl).await?;
+            request = request.query(&[("acquire", session)]);
+        }
+        Self::body(Self::send(request).await?).await
+    }
+
+    async fn snapshot(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<StoreSnapshot> {
+        let (entries, revision) = self.entries(&prefix.into(), None).await?;
+        Ok(StoreSnapshot {
+            revision,
+            kvs: entries
+                .iter()
+                .map(ConsulEntry::to_key_value)
+                .collect::<TraefikResult<_>>()?,
+        })
+    }
+
+    /// Consul transactions cannot compare a whole prefix, so `UnchangedSince`
+    /// reads the prefix and checks it within the transaction: every key
+    /// written must still be at the index read or still be missing, and the
+    /// other keys of the prefix are checked as far as the transaction has
+    /// room for them. A put with a ttl moves the key to a new session.
+    async fn txn(&self, compares: Vec<TxnCompare>, ops: Vec<TxnOp>) -> TraefikResult<bool> {
+        let mut operations = Vec::with_capacity(compares.len() + ops.len());
+        let mut untouched = Vec::new();
+        for compare in compares {
+            match compare {
+                TxnCompare::UnchangedSince { prefix, revision } => {
+                    let (entries, index) = self.entries(&prefix, None).await?;
+                    if index > revision {
+                        return Ok(false);
+                    }
+                    let mut entries = entries
+                        .into_iter()
+                        .map(|entry| (entry.key.into_bytes(), entry.modify_index))
+                        .collect::<BTreeMap<_, _>>();
+                    let written = ops
+                        .iter()
+                        .map(TxnOp::key)
+                        .filter(|key| key.starts_with(&prefix))
+                        .collect::<BTreeSet<_>>();
+                    for key in written {
+                        operations.push(match entries.remove(key) {
+                            Some(index) => Self::check_index(key, index),
+                            None => Self::txn_operation("check-not-exists", key),
+                        });
+                    }
+                    untouched.extend(
+                        entries
+                            .iter()
+                            .map(|(key, index)| Self::check_index(key, *index)),
+                    );
+                }
+                TxnCompare::Absent { key } => {
+                    operations.push(Self::txn_operation("check-not-exists", &key));
+                }
+                TxnCompare::ValueEquals { key, value } => match self.entry(&key).await? {
+                    Some(entry) if entry.decoded_value()? == value => {
+                        operations.push(Self::check_index(&key, entry.modify_index));
+                    }
+                    _ => return Ok(false),
+                },
+            }
+        }
+        let mut writes = Vec::with_capacity(ops.len());
+        for op in ops {
+            match op {
+                TxnOp::Put { key, value, ttl } => {
+                    let session = match ttl.filter(|ttl| *ttl > 0) {
+                        Some(ttl) => {
+                            if let Some(held) = self.entry(&key).await?.and_then(|e| e.session) {
+                                let mut unlock = Self::txn_operation("unlock", &key);
+                                unlock["KV"]["Session"] = json!(held);
+                                writes.push(unlock);
+                            }
+                            Some(self.create_session(ttl).await?)
+                        }
+                        None => None,
+                    };
+                    let mut operation = match session {
+                        Some(session) => {
+                            let mut operation = Self::txn_operation("lock", &key);
+                            operation["KV"]["Session"] = json!(session);
+                            operation
+                        }
+                        None => Self::txn_operation("set", &key),
+                    };
+                    operation["KV"]["Value"] = json!(STANDARD.encode(value));
+                    writes.push(operation);
+                }
+                TxnOp::Delete { key } => writes.push(Self::txn_operation("delete", &key)),
+            }
+        }
+        let room = self
+            .config
+            .max_txn_ops
+            .saturating_sub(operations.len() + writes.len());
+        if untouched.len() > room {
+            debug!(
+                "Checking {room} of the {} keys the transaction does not write",
+                untouched.len()
+            );
+        }
+        operations.extend(untouched.into_iter().take(room));
+        operations.extend(writes);
+
+        let request = self
+            .request(Method::PUT, "txn")
+            .json_body(&serde_json::Value::Array(operations))?;
+        let response = Self::send(request).await?;
+        Ok(response.status() != StatusCode::CONFLICT)
+    }
+
+    /// Half of the operations are held back for the checks guarding the keys
+    /// a transaction writes
+    fn max_txn_ops(&self) -> usize {
+        self.config.max_txn_ops / 2
+    }
+
+    async fn watch(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<WatchStream> {
+        let prefix = prefix.into();
+        let (entries, index) = self.entries(&prefix, None).await?;
+        let known = entries
+            .into_iter()
+            .map(|entry| (entry.key.clone(), entry))
+            .collect::<BTreeMap<_, _>>();
+
+        // Each blocking query returns the whole prefix, so changes are found by
+        // comparing it against the previous result
+        let state = Some((self.clone(), prefix, index, known));
+        let events = stream::unfold(state, |state| async move {
+            let (consul, prefix, index, mut known) = state?;
+            match consul.entries(&prefix, Some(index)).await {
+                Ok((entries, next_index)) => {
+                    let events = changes(&mut known, entries, next_index);
+                    // Consul asks clients to start over when the index goes backwards
+                    let next_index = if next_index < index { 0 } else { next_index };
+                    Some((events, Some((consul, prefix, next_index, known))))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+        .flat_map(|events| {
+            let events = match events {
+                Ok(events) => events.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            stream::iter(events)
+        });
+        Ok(Box::pin(events))
+    }
+}
+
+/// The events that turn `known` into `entries`, `known` is updated in place
+fn changes(
+    known: &mut BTreeMap<String, ConsulEntry>,
+    entries: Vec<ConsulEntry>,
+    index: i64,
+) -> TraefikResult<Vec<WatchEvent>> {
+    let current = entries
+        .into_iter()
+        .map(|entry| (entry.key.clone(), entry))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut events = Vec::new();
+    for (key, entry) in current.iter() {
+        let changed = known
+            .get(key)
+            .is_none_or(|previous| previous.modify_index != entry.modify_index);
+        if changed {
+            events.push(WatchEvent {
+                kind: WatchEventKind::Put,
+                key: key.clone(),
+                value: String::from_utf8_lossy(&entry.decoded_value()?).to_string(),
+                revision: entry.modify_index,
+            });
+        }
+    }
+    for key in known.keys().filter(|key| !current.contains_key(*key)) {
+        events.push(WatchEvent {
+            kind: WatchEventKind::Delete,
+            key: key.clone(),
+            value: String::new(),
+            revision: index,
+        });
+    }
+    events.sort_by_key(|event| event.revision);
+
+    *known = current;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Bytes,
+        extract::{Path, Query, State},
+        http::{HeaderMap, StatusCode as AxumStatus},
+        response::{IntoResponse, Response as AxumResponse},
+        routing::{get, put},
+        Json, Router,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct MockEntry {
+        value: Vec<u8>,
+        modify_index: i64,
+        session: Option<String>,
+    }
+
+    /// Just enough of consul's KV, session and txn endpoints to exercise the client
+    #[derive(Debug, Default)]
+    struct MockConsul {
+        entries: BTreeMap<String, MockEntry>,
+        index: i64,
+        sessions: HashMap<String, u32>,
+        /// The operations of the last transaction
+        txn: Vec<serde_json::Value>,
+    }
+
+    impl MockConsul {
+        fn set(&mut self, key: &str, value: Vec<u8>, session: Option<String>) {
+            self.index += 1;
+            self.entries.insert(
+                key.to_string(),
+                MockEntry {
+                    value,
+                    modify_index: self.index,
+                    session,
+                },
+            );
+        }
+
+        fn delete(&mut self, key: &str) {
+            if self.entries.remove(key).is_some() {
+                self.index += 1;
+            }
+        }
+
+        fn json(&self, key: &str, entry: &MockEntry) -> serde_json::Value {
+            json!({
+                "Key": key,
+                "Value": STANDARD.encode(&entry.value),
+                "Session": entry.session,
+                "ModifyIndex": entry.modify_index,
+            })
+        }
+    }
+
+    type MockState = Arc<Mutex<MockConsul>>;
+
+    fn with_index(index: i64, response: impl IntoResponse) -> AxumResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Consul-Index", index.to_string().parse().unwrap());
+        (headers, response).into_response()
+    }
+
+    async fn kv_get(
+        State(state): State<MockState>,
+        Path(key): Path<String>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> AxumResponse {
+        let consul = state.lock().unwrap();
+        let matching = consul
+            .entries
+            .iter()
+            .filter(|(k, _)| {
+                if query.contains_key("recurse") || query.contains_key("keys") {
+                    k.starts_with(&key)
+                } else {
+                    **k == key
+                }
+            })
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            return with_index(consul.index, AxumStatus::NOT_FOUND);
+        }
+        let body = if query.contains_key("keys") {
+            json!(matching.iter().map(|(k, _)| k).collect::<Vec<_>>())
+        } else {
+            json!(matching
+                .iter()
+                .map(|(k, entry)| consul.json(k, entry))
+                .collect::<Vec<_>>())
+        };
+        with_index(consul.index, Json(body))
+    }
+
+    async fn kv_put(
+        State(state): State<MockState>,
+        Path(key): Path<String>,
+        Query(query): Query<HashMap<String, String>>,
+        body: Bytes,
+    ) -> Json<bool> {
+        let mut consul = state.lock().unwrap();
+        if query.get("cas").is_some_and(|cas| cas == "0") && consul.entries.contains_key(&key) {
+            return Json(false);
+        }
+        let session = query.get("acquire").cloned();
+        consul.set(&key, body.to_vec(), session);
+        Json(true)
+    }
+
+    async fn kv_delete(
+        State(state): State<MockState>,
+        Path(key): Path<String>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Json<bool> {
+        let mut consul = state.lock().unwrap();
+        let keys = consul
+            .entries
+            .keys()
+            .filter(|k| {
+                if query.contains_key("recurse") {
+                    k.starts_with(&key)
+                } else {
+                    **k == key
+                }
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys {
+            consul.delete(&key);
+        }
+        Json(true)
+    }
+
+    async fn session_create(
+        State(state): State<MockState>,
+        body: Bytes,
+    ) -> Json<serde_json::Value> {
+        let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(request["Behavior"], "delete");
+        let mut consul = state.lock().unwrap();
+        let id = format!("session-{}", consul.sessions.len());
+        consul.sessions.insert(id.clone(), 0);
+        Json(json!({ "ID": id }))
+    }
+
+    async fn session_renew(State(state): State<MockState>, Path(id): Path<String>) -> AxumStatus {
+        let mut consul = state.lock().unwrap();
+        *consul.sessions.get_mut(&id).unwrap() += 1;
+        AxumStatus::OK
+    }
+
+    async fn txn(State(state): State<MockState>, body: Bytes) -> AxumStatus {
+        let operations: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        let mut consul = state.lock().unwrap();
+        consul.txn = operations.clone();
+        for operation in operations.iter() {
+            let kv = &operation["KV"];
+            let key = kv["Key"].as_str().unwrap();
+            let holds = match kv["Verb"].as_str().unwrap() {
+                "check-not-exists" => !consul.entries.contains_key(key),
+                "check-index" => consul
+                    .entries
+                    .get(key)
+                    .is_some_and(|entry| json!(entry.modify_index) == kv["Index"]),
+                "unlock" => consul
+                    .entries
+                    .get(key)
+                    .is_some_and(|entry| json!(entry.session) == kv["Session"]),
+                _ => true,
+            };
+            if !holds {
+                return AxumStatus::CONFLICT;
+            }
+        }
+        for operation in operations.iter() {
+            let kv = &operation["KV"];
+            let key = kv["Key"].as_str().unwrap();
+            match kv["Verb"].as_str().unwrap() {
+                "set" => {
+                    let value = STANDARD.decode(kv["Value"].as_str().unwrap()).unwrap();
+                    consul.set(key, value, None);
+                }
+                "lock" => {
+                    let value = STANDARD.decode(kv["Value"].as_str().unwrap()).unwrap();
+                    let session = kv["Session"].as_str().map(str::to_string);
+                    consul.set(key, value, session);
+                }
+                "delete" => consul.delete(key),
+                _ => {}
+            }
+        }
+        AxumStatus::OK
+    }
+
+    async fn mock_consul() -> (Consul, MockState) {
+        let state = MockState::default();
+        let app = Router::new()
+            .route("/v1/kv/*key", get(kv_get).put(kv_put).delete(kv_delete))
+            .route("/v1/session/create", put(session_create))
+            .route("/v1/session/renew/:id", put(session_renew))
+            .route("/v1/txn", put(txn))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let consul = Consul::new(&ConsulConfig {
+            address: format!("http://{address}"),
+            max_txn_ops: 4,
+            ..Default::default()
+        })
+        .unwrap();
+        (consul, state)
+    }
+
+    #[test]
+    fn test_consul_config_default_values() {
+        let config = ConsulConfig::default();
+        assert_eq!(config.address, "http://127.0.0.1:8500");
+        assert_eq!(config.max_txn_ops, 64);
+    }
+
+    #[test]
+    fn test_consul_token_is_never_printed() {
+        let config = ConsulConfig {
+            token: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert!(!serde_json::to_string(&config).unwrap().contains("secret"));
+        assert!(!format!("{config:?}").contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_put_and_get() {
+        let (consul, _) = mock_consul().await;
+        assert!(consul.put("traefik/a", "1", None).await.unwrap().is_none());
+        let prev = consul.put("traefik/a", "2", None).await.unwrap();
+        assert_eq!(prev.unwrap().value_str(), Some("1"));
+        assert_eq!(
+            consul.get("traefik/a").await.unwrap().value_str(),
+            Some("2")
+        );
+        assert!(matches!(
+            consul.get("traefik/missing").await,
+            Err(TraefikError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_key() {
+        assert_eq!(encode_key(b"traefik/http/a-b_c.d"), "traefik/http/a-b_c.d");
+        assert_eq!(
+            encode_key("traefik/x?y#z%w v/é".as_bytes()),
+            "traefik/x%3Fy%23z%25w%20v/%C3%A9"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keys_with_reserved_characters() {
+        let (consul, state) = mock_consul().await;
+        let key = "traefik/http/middlewares/a b?c#d%e/headers/X-Id";
+        consul.put(key, "1", None).await.unwrap();
+        assert!(state.lock().unwrap().entries.contains_key(key));
+        assert_eq!(consul.get(key).await.unwrap().value_str(), Some("1"));
+        assert_eq!(consul.delete(key).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_recursive_get_and_delete() {
+        let (consul, _) = mock_consul().await;
+        consul.put("traefik/http/a", "1", None).await.unwrap();
+        consul.put("traefik/http/b", "2", None).await.unwrap();
+        consul.put("other/c", "3", None).await.unwrap();
+
+        let kvs = consul.get_with_prefix("traefik/").await.unwrap();
+        assert_eq!(kvs.len(), 2);
+        let keys = consul.get_keys("traefik/").await.unwrap();
+        assert_eq!(keys[0].key_str(), Some("traefik/http/a"));
+        assert!(keys[0].value.is_empty());
+
+        assert_eq!(consul.delete_with_prefix("traefik/").await.unwrap(), 2);
+        assert!(consul.get_with_prefix("traefik/").await.unwrap().is_empty());
+        assert_eq!(consul.delete("other/c").await.unwrap(), 1);
+        assert_eq!(consul.delete("other/c").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_ttl_keys_use_sessions() {
+        let (consul, state) = mock_consul().await;
+        consul.put("traefik/lease", "1", Some(30)).await.unwrap();
+        let session = state.lock().unwrap().entries["traefik/lease"]
+            .session
+            .clone()
+            .unwrap();
+
+        consul
+            .put_or_touch("traefik/lease", "2", Some(30))
+            .await
+            .unwrap();
+        assert_eq!(state.lock().unwrap().sessions[&session], 1);
+        assert_eq!(
+            consul.get("traefik/lease").await.unwrap().value_str(),
+            Some("1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_txn_ttl_put_moves_the_key_to_a_new_session() {
+        let (consul, state) = mock_consul().await;
+        consul.put("traefik/lease", "1", Some(30)).await.unwrap();
+        let held = |state: &MockState| {
+            state.lock().unwrap().entries["traefik/lease"]
+                .session
+                .clone()
+                .unwrap()
+        };
+        let session = held(&state);
+
+        let renewed = consul
+            .txn(
+                vec![TxnCompare::ValueEquals {
+                    key: "traefik/lease".into(),
+                    value: "1".into(),
+                }],
+                vec![TxnOp::put_with_ttl("traefik/lease", "1", 30)],
+            )
+            .await
+            .unwrap();
+        assert!(renewed);
+        let verbs = state
+            .lock()
+            .unwrap()
+            .txn
+            .iter()
+            .map(|operation| operation["KV"]["Verb"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(verbs, vec!["check-index", "unlock", "lock"]);
+        assert_ne!(held(&state), session);
+    }
+
+    #[tokio::test]
+    async fn test_put_if_absent_uses_cas() {
+        let (consul, _) = mock_consul().await;
+        assert!(consul
+            .put_if_absent("traefik/lock", "a", Some(30))
+            .await
+            .unwrap());
+        assert!(!consul
+            .put_if_absent("traefik/lock", "b", Some(30))
+            .await
+            .unwrap());
+        assert_eq!(
+            consul.get("traefik/lock").await.unwrap().value_str(),
+            Some("a")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_txn_refuses_changed_prefix() {
+        let (consul, _) = mock_consul().await;
+        consul.put("traefik/a", "1", None).await.unwrap();
+        let snapshot = consul.snapshot("traefik/").await.unwrap();
+        consul.put("traefik/a", "changed", None).await.unwrap();
+
+        let unchanged = TxnCompare::UnchangedSince {
+            prefix: "traefik/".into(),
+            revision: snapshot.revision,
+        };
+        let committed = consul
+            .txn(vec![unchanged], vec![TxnOp::put("traefik/b", "2")])
+            .await
+            .unwrap();
+        assert!(!committed);
+        assert!(consul.get("traefik/b").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_txn_checks_the_prefix_atomically() {
+        let (mut consul, state) = mock_consul().await;
+        consul.config.max_txn_ops = 64;
+        consul.put("traefik/a", "1", None).await.unwrap();
+        consul.put("traefik/b", "1", None).await.unwrap();
+        let snapshot = consul.snapshot("traefik/").await.unwrap();
+
+        let unchanged = TxnCompare::UnchangedSince {
+            prefix: "traefik/".into(),
+            revision: snapshot.revision,
+        };
+        let ops = vec![TxnOp::put("traefik/a", "2"), TxnOp::put("traefik/c", "2")];
+        assert!(consul.txn(vec![unchanged.clone()], ops).await.unwrap());
+        let checks = state
+            .lock()
+            .unwrap()
+            .txn
+            .iter()
+            .map(|operation| {
+                let kv = &operation["KV"];
+                format!(
+                    "{} {}",
+                    kv["Verb"].as_str().unwrap(),
+                    kv["Key"].as_str().unwrap()
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            checks,
+            [
+                "check-index traefik/a",
+                "check-not-exists traefik/c",
+                "check-index traefik/b",
+                "set traefik/a",
+                "set traefik/c",
+            ]
+        );
+
+        // A key deleted after the snapshot is a change
+        let snapshot = consul.snapshot("traefik/").await.unwrap();
+        consul.delete("traefik/b").await.unwrap();
+        let unchanged = TxnCompare::UnchangedSince {
+            prefix: "traefik/".into(),
+            revision: snapshot.revision,
+        };
+        let committed = consul
+            .txn(vec![unchanged], vec![TxnOp::put("traefik/d", "2")])
+            .await
+            .unwrap();
+        assert!(!committed);
+        assert!(consul.get("traefik/d").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_commit_through_store_client() {
+        use crate::core::client::StoreClient;
+
+        let (consul, _) = mock_consul().await;
+        let client = StoreClient::new(consul);
+        let snapshot = client.snapshot("traefik/").await.unwrap();
+        let ops = (0..5)
+            .map(|i| TxnOp::put(format!("traefik/{i}"), i.to_string()))
+            .collect();
+        client
+            .commit("traefik/", snapshot.revision, "_guard", ops)
+            .await
+            .unwrap();
+        assert_eq!(client.get_with_prefix("traefik/").await.unwrap().len(), 5);
+        assert!(client.get("_guard").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_guard_check_fails_when_held() {
+        let (consul, _) = mock_consul().await;
+        consul.put("_guard", "someone-else", None).await.unwrap();
+        let absent = TxnCompare::Absent {
+            key: "_guard".into(),
+        };
+        let committed = consul
+            .txn(vec![absent], vec![TxnOp::put("traefik/a", "1")])
+            .await
+            .unwrap();
+        assert!(!committed);
+    }
+
+    #[test]
+    fn test_changes_between_reads() {
+        let entry = |key: &str, value: &str, modify_index| ConsulEntry {
+            key: key.to_string(),
+            value: Some(STANDARD.encode(value)),
+            session: None,
+            modify_index,
+        };
+        let mut known = BTreeMap::from([
+            ("traefik/a".to_string(), entry("traefik/a", "1", 1)),
+            ("traefik/b".to_string(), entry("traefik/b", "1", 2)),
+        ]);
+        let events = changes(
+            &mut known,
+            vec![entry("traefik/a", "1", 1), entry("traefik/c", "3", 4)],
+            5,
+        )
+        .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                WatchEvent {
+                    kind: WatchEventKind::Put,
+                    key: "traefik/c".to_string(),
+                    value: "3".to_string(),
+                    revision: 4,
+                },
+                WatchEvent {
+                    kind: WatchEventKind::Delete,
+                    key: "traefik/b".to_string(),
+                    value: String::new(),
+                    revision: 5,
+                },
+            ]
+        );
+        assert_eq!(known.len(), 2);
+    }
+}
@@ -1,21 +1,34 @@
 use async_trait::async_trait;
 use color_eyre::eyre::{eyre, Result};
 use etcd_client::{
-    Certificate, Client, ConnectOptions, DeleteOptions, GetOptions, Identity, PutOptions,
-    TlsOptions as ECTlsOptions,
+    Certificate, Client, Compare, CompareOp, ConnectOptions, DeleteOptions, EventType, GetOptions,
+    Identity, LeaseKeepAliveStream, LeaseKeeper, PutOptions, TlsOptions as ECTlsOptions, Txn,
+    TxnOp as EtcdTxnOp, TxnOpResponse, WatchOptions, WatchResponse,
 };
 use export_type::ExportType;
+use futures_util::{stream, StreamExt};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Mutex;
+use tonic::Code;
 use tracing::debug;
 
 mod diff;
+mod semantic;
 
 pub use diff::*;
+pub use semantic::*;
 
 use crate::{
-    core::client::StoreClientActor,
+    core::client::{
+        RetryConfig, RevisionSummary, StoreClientActor, StoreSnapshot, TxnCompare, TxnOp,
+        WatchEvent, WatchEventKind, WatchStream, DEFAULT_MAX_TXN_OPS,
+    },
     error::{TraefikError, TraefikResult},
 };
 
@@ -33,9 +46,21 @@ pub struct EtcdConfig {
     pub keep_alive: u64,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tls: Option<TlsOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<EtcdAuth>,
+    /// The maximum number of operations sent in a single transaction, this
+    /// must not exceed the server's `--max-txn-ops`
+    pub max_txn_ops: usize,
+    /// How calls failing during a leader election or an endpoint outage are
+    /// retried
+    pub retry: RetryConfig,
 }
 
 /// The configuration for the TLS options
+///
+/// `cert`, `key` and `ca` are either paths to PEM files or the PEM content
+/// itself. `cert` and `key` are only needed when the server checks client
+/// certificates, without `ca` the system roots are trusted.
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
 #[cfg_attr(feature = "codegen", derive(ExportType))]
@@ -47,6 +72,97 @@ pub struct TlsOptions {
     pub ca: Option<String>,
 }
 
+/// The user to authenticate to etcd as when role based access control is
+/// enabled, the password is given by exactly one of `password`,
+/// `password_env` or `password_file`
+#[derive(Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct EtcdAuth {
+    pub username: String,
+    /// Never serialized, so rendering or exporting a config does not print it
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+    /// The environment variable holding the password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    /// A file holding the password, surrounding whitespace is ignored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<String>,
+}
+
+// The config is logged on connect, so an inline password is never printed
+impl std::fmt::Debug for EtcdAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EtcdAuth")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<hidden>"))
+            .field("password_env", &self.password_env)
+            .field("password_file", &self.password_file)
+            .finish()
+    }
+}
+
+impl EtcdAuth {
+    pub fn password(&self) -> Result<String> {
+        match (&self.password, &self.password_env, &self.password_file) {
+            (Some(password), None, None) => Ok(password.clone()),
+            (None, Some(var), None) => std::env::var(var)
+                .map_err(|e| eyre!("etcd auth.password_env '{var}' could not be read: {e}")),
+            (None, None, Some(path)) => Ok(std::fs::read_to_string(path.trim())
+                .map_err(|e| eyre!("etcd auth.password_file '{path}' could not be read: {e}"))?
+                .trim()
+                .to_string()),
+            (None, None, None) => Err(eyre!(
+                "etcd auth for '{}' needs one of password, password_env or password_file",
+                self.username
+            )),
+            _ => Err(eyre!(
+                "etcd auth for '{}' sets more than one of password, password_env and password_file",
+                self.username
+            )),
+        }
+    }
+}
+
+impl TlsOptions {
+    fn to_etcd_options(&self) -> Result<ECTlsOptions> {
+        let mut options = ECTlsOptions::new();
+        if let Some(ca) = &self.ca {
+            options = options.ca_certificate(Certificate::from_pem(read_pem("ca", ca)?));
+        }
+        match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => {
+                options = options.identity(Identity::from_pem(
+                    read_pem("cert", cert)?,
+                    read_pem("key", key)?,
+                ));
+            }
+            (Some(_), None) => return Err(eyre!("etcd tls.cert is set without a tls.key")),
+            (None, Some(_)) => return Err(eyre!("etcd tls.key is set without a tls.cert")),
+            (None, None) => {}
+        }
+        if let Some(domain) = &self.domain {
+            options = options.domain_name(domain);
+        }
+        Ok(options)
+    }
+}
+
+/// The PEM content of a tls option, given inline or as a path to a file
+fn read_pem(field: &str, value: &str) -> Result<String> {
+    let value = value.trim();
+    if value.starts_with("-----BEGIN") {
+        return Ok(value.to_string());
+    }
+    if value.is_empty() {
+        return Err(eyre!("etcd tls.{field} is empty"));
+    }
+    std::fs::read_to_string(value)
+        .map_err(|e| eyre!("etcd tls.{field} '{value}' could not be read: {e}"))
+}
+
 impl Default for EtcdConfig {
     fn default() -> Self {
         Self {
@@ -54,6 +170,9 @@ impl Default for EtcdConfig {
             timeout: 2000,
             keep_alive: 300,
             tls: None,
+            auth: None,
+            max_txn_ops: DEFAULT_MAX_TXN_OPS,
+            retry: RetryConfig::default(),
         }
     }
 }
@@ -64,9 +183,18 @@ impl From<String> for EtcdConfig {
     }
 }
 
+/// An open keep-alive stream for a lease
+type LeaseKeepAlive = (LeaseKeeper, LeaseKeepAliveStream);
+
 #[derive(Clone)]
 pub struct Etcd {
     pub client: Client,
+    pub max_txn_ops: usize,
+    pub retry: RetryConfig,
+    /// The endpoints the client was connected to, named in errors
+    endpoints: Vec<String>,
+    /// Keep-alive streams are reused across touches, keyed by lease id
+    keepers: Arc<Mutex<HashMap<i64, LeaseKeepAlive>>>,
 }
 
 impl std::fmt::Debug for Etcd {
@@ -91,13 +219,13 @@ impl StoreClientActor for Etcd {
             let lease = client
                 .lease_grant(ttl, None)
                 .await
-                .map_err(|e| eyre!("etcd lease_grant failed: {e}"))?;
+                .map_err(self.error("lease_grant"))?;
             PutOptions::new().with_lease(lease.id()).with_prev_key()
         };
         let put_rsp = client
             .put(key, value, Some(option))
             .await
-            .map_err(|e| eyre!("etcd put failed: {e}"))?;
+            .map_err(self.error("put"))?;
         Ok(put_rsp.prev_key().cloned().map(Into::into))
     }
 
@@ -106,7 +234,7 @@ impl StoreClientActor for Etcd {
             .to_owned()
             .get(key, Some(GetOptions::new().with_limit(1)))
             .await
-            .map_err(|e| eyre!("etcd get failed: {e}"))?
+            .map_err(self.error("get"))?
             .kvs()
             .first()
             .cloned()
@@ -123,7 +251,7 @@ impl StoreClientActor for Etcd {
             .to_owned()
             .get(key, Some(GetOptions::new().with_prefix()))
             .await
-            .map_err(|e| eyre!("etcd get failed: {e}"))?
+            .map_err(self.error("get"))?
             .kvs()
             .iter()
             .cloned()
@@ -135,9 +263,9 @@ impl StoreClientActor for Etcd {
         Ok(self
             .client
             .to_owned()
-            .get(key, Some(GetOptions::new().with_limit(1).with_from_key()))
+            .get(key, Some(GetOptions::new().with_prefix().with_keys_only()))
             .await
-            .map_err(|e| eyre!("etcd get failed: {e}"))?
+            .map_err(self.error("get"))?
             .kvs()
             .iter()
             .cloned()
@@ -151,7 +279,7 @@ impl StoreClientActor for Etcd {
             .to_owned()
             .delete(key, None)
             .await
-            .map_err(|e| eyre!("etcd delete failed: {e}"))?
+            .map_err(self.error("delete"))?
             .deleted())
     }
 
@@ -161,103 +289,570 @@ impl StoreClientActor for Etcd {
             .to_owned()
             .delete(key, Some(DeleteOptions::new().with_prefix()))
             .await
-            .map_err(|e| eyre!("etcd delete failed: {e}"))?
+            .map_err(self.error("delete"))?
             .deleted())
     }
 
-    #[allow(dead_code)]
     async fn touch(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<()> {
-        let mut client = self.client.clone();
-        let lease = client
-            .get(key, Some(GetOptions::new().with_limit(1)))
-            .await
-            .map_err(|e| eyre!("etcd get failed: {e}"))?
-            .kvs()
-            .first()
-            .map(|kv| kv.lease())
-            .unwrap_or(0);
-        if lease != 0 {
-            client
-                .lease_keep_alive(lease)
-                .await
-                .map_err(|e| eyre!("etcd lease_keep_alive failed: {e}"))?;
+        match self.lease(key).await? {
+            Some(lease) if lease != 0 => self.keep_alive(lease).await,
+            _ => Ok(()),
         }
-        Ok(())
     }
 
-    #[allow(dead_code)]
     async fn put_or_touch(
         &self,
         key: impl Into<Vec<u8>> + Send,
         value: impl Into<Vec<u8>> + Send,
         ttl: Option<i64>,
     ) -> TraefikResult<()> {
-        let mut client = self.client.clone();
         let key = key.into();
-        if let Some(prev) = client
-            .get(key.clone(), Some(GetOptions::new().with_limit(1)))
+        let renewed = match self.lease(key.clone()).await? {
+            Some(0) => Ok(()),
+            Some(lease) => self.keep_alive(lease).await,
+            None => Err(TraefikError::NotFound("data not found".into())),
+        };
+        match renewed {
+            // The key is missing or about to be removed with its lease
+            Err(TraefikError::NotFound(_)) => self.put(key, value, ttl).await.map(|_| ()),
+            renewed => renewed,
+        }
+    }
+
+    async fn put_if_absent(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<bool> {
+        let key = key.into();
+        let mut client = self.client.to_owned();
+        let lease = self.grant(ttl).await?;
+        let options = lease.map(|lease| PutOptions::new().with_lease(lease));
+        let txn = Txn::new()
+            .when([Compare::version(key.clone(), CompareOp::Equal, 0)])
+            .and_then([EtcdTxnOp::put(key, value, options)]);
+        let created = client
+            .txn(txn)
             .await
-            .map_err(|e| eyre!("etcd get failed: {e}"))?
-            .kvs()
-            .first()
-        {
-            client
-                .lease_keep_alive(prev.lease())
+            .map_err(self.error("txn"))?
+            .succeeded();
+        if !created {
+            self.revoke(lease).await?;
+        }
+        Ok(created)
+    }
+
+    async fn snapshot(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<StoreSnapshot> {
+        let mut response = self
+            .client
+            .to_owned()
+            .get(prefix, Some(GetOptions::new().with_prefix()))
+            .await
+            .map_err(self.error("get"))?;
+        let revision = response
+            .header()
+            .map(|header| header.revision())
+            .unwrap_or_default();
+        Ok(StoreSnapshot {
+            revision,
+            kvs: response.take_kvs().into_iter().map(Into::into).collect(),
+        })
+    }
+
+    /// A compare only sees keys that still exist, so `UnchangedSince` also
+    /// requires every key the prefix held at the revision to still exist,
+    /// which catches keys deleted after it
+    async fn txn(&self, compares: Vec<TxnCompare>, ops: Vec<TxnOp>) -> TraefikResult<bool> {
+        let mut etcd_compares = Vec::new();
+        for compare in compares {
+            match compare {
+                TxnCompare::UnchangedSince { prefix, revision } => {
+                    etcd_compares.push(
+                        Compare::mod_revision(prefix.clone(), CompareOp::Less, revision + 1)
+                            .with_prefix(),
+                    );
+                    etcd_compares.extend(
+                        self.keys_at(prefix, revision)
+                            .await?
+                            .into_iter()
+                            .map(|key| Compare::version(key, CompareOp::Greater, 0)),
+                    );
+                }
+                TxnCompare::Absent { key } => {
+                    etcd_compares.push(Compare::version(key, CompareOp::Equal, 0))
+                }
+                TxnCompare::ValueEquals { key, value } => {
+                    etcd_compares.push(Compare::value(key, CompareOp::Equal, value))
+                }
+            }
+        }
+        let mut leases = Vec::new();
+        let mut etcd_ops = Vec::with_capacity(ops.len());
+        for op in ops {
+            etcd_ops.push(match op {
+                TxnOp::Put { key, value, ttl } => {
+                    let lease = self.grant(ttl).await?;
+                    leases.extend(lease);
+                    let options = lease.map(|lease| PutOptions::new().with_lease(lease));
+                    EtcdTxnOp::put(key, value, options)
+                }
+                TxnOp::Delete { key } => EtcdTxnOp::delete(key, None),
+            });
+        }
+
+        // etcd bounds the compares of a transaction like its operations, so
+        // they are spread over nested transactions, each one only running the
+        // next when its own compares hold
+        let mut chunks = etcd_compares.chunks(self.max_txn_ops.max(1)).rev();
+        let mut txn = Txn::new()
+            .when(chunks.next().unwrap_or_default())
+            .and_then(etcd_ops);
+        let mut nested = 0;
+        for chunk in chunks {
+            txn = Txn::new().when(chunk).and_then([EtcdTxnOp::txn(txn)]);
+            nested += 1;
+        }
+        let mut response = self
+            .client
+            .to_owned()
+            .txn(txn)
+            .await
+            .map_err(self.error("txn"))?;
+        for _ in 0..nested {
+            if !response.succeeded() {
+                break;
+            }
+            response = match response.op_responses().into_iter().next() {
+                Some(TxnOpResponse::Txn(inner)) => inner,
+                _ => {
+                    return Err(TraefikError::EtcdError(
+                        "nested txn returned no response".into(),
+                    ))
+                }
+            };
+        }
+        let committed = response.succeeded();
+        if !committed {
+            for lease in leases {
+                self.revoke(Some(lease)).await?;
+            }
+        }
+        Ok(committed)
+    }
+
+    fn max_txn_ops(&self) -> usize {
+        self.max_txn_ops
+    }
+
+    fn retry_config(&self) -> RetryConfig {
+        self.retry
+    }
+
+    async fn watch(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<WatchStream> {
+        let (watcher, responses) = self
+            .client
+            .to_owned()
+            .watch(prefix, Some(WatchOptions::new().with_prefix()))
+            .await
+            .map_err(self.error("watch"))?;
+
+        // The watcher cancels the watch when dropped, so it lives as long as the stream
+        let events = stream::unfold(
+            (watcher, responses),
+            |(watcher, mut responses)| async move {
+                let item = match responses.message().await {
+                    Ok(Some(response)) => Ok(watch_events(&response)),
+                    Ok(None) => return None,
+                    Err(e) => Err(TraefikError::EtcdError(format!("etcd watch failed: {e}"))),
+                };
+                Some((item, (watcher, responses)))
+            },
+        )
+        .flat_map(|item| {
+            let items = match item {
+                Ok(events) => events.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            stream::iter(items)
+        });
+        Ok(Box::pin(events))
+    }
+
+    /// Replays the prefix from the oldest revision etcd still has
+    ///
+    /// The watch is read until etcd answers a progress request, which it only
+    /// does once every historical event has been delivered.
+    async fn history(
+        &self,
+        prefix: impl Into<Vec<u8>> + Send,
+    ) -> TraefikResult<Vec<RevisionSummary>> {
+        let prefix = prefix.into();
+        let mut client = self.client.to_owned();
+        let current = client
+            .get(
+                prefix.clone(),
+                Some(GetOptions::new().with_prefix().with_count_only()),
+            )
+            .await
+            .map_err(self.error("get"))?
+            .header()
+            .map(|header| header.revision())
+            .unwrap_or_default();
+
+        let mut start_revision = 1;
+        let mut events = Vec::new();
+        'replay: loop {
+            let (mut watcher, mut responses) = client
+                .watch(
+                    prefix.clone(),
+                    Some(
+                        WatchOptions::new()
+                            .with_prefix()
+                            .with_start_revision(start_revision),
+                    ),
+                )
+                .await
+                .map_err(self.error("watch"))?;
+            watcher
+                .request_progress()
                 .await
-                .map_err(|e| eyre!("etcd lease_keep_alive failed: {e}"))?;
+                .map_err(self.error("watch"))?;
+            loop {
+                let response = match tokio::time::timeout(
+                    Duration::from_millis(HISTORY_PROGRESS_INTERVAL),
+                    responses.message(),
+                )
+                .await
+                {
+                    Ok(response) => response
+                        .map_err(|e| TraefikError::EtcdError(format!("etcd watch failed: {e}")))?,
+                    Err(_) => {
+                        // Progress requests are ignored until the watcher caught up
+                        watcher
+                            .request_progress()
+                            .await
+                            .map_err(self.error("watch"))?;
+                        continue;
+                    }
+                };
+                let Some(response) = response else {
+                    break 'replay;
+                };
+                if response.compact_revision() > 0 {
+                    // Older revisions were compacted away, start from the oldest one left
+                    debug!(
+                        "history compacted before revision {}",
+                        response.compact_revision()
+                    );
+                    start_revision = response.compact_revision();
+                    events.clear();
+                    continue 'replay;
+                }
+                events.extend(watch_events(&response));
+                let revision = response
+                    .header()
+                    .map(|header| header.revision())
+                    .unwrap_or_default();
+                if response.events().is_empty() && !response.created() && revision >= current {
+                    let _ = watcher.cancel().await;
+                    break 'replay;
+                }
+            }
+        }
+        Ok(RevisionSummary::from_events(&events))
+    }
+
+    async fn snapshot_at(
+        &self,
+        prefix: impl Into<Vec<u8>> + Send,
+        revision: i64,
+    ) -> TraefikResult<StoreSnapshot> {
+        // etcd reads the latest revision when asked for revision 0
+        if revision < 1 {
+            return Err(TraefikError::ConfigError(format!(
+                "revision {revision} is invalid, revisions start at 1"
+            )));
+        }
+        let mut response = self
+            .client
+            .to_owned()
+            .get(
+                prefix,
+                Some(GetOptions::new().with_prefix().with_revision(revision)),
+            )
+            .await
+            .map_err(self.error(&format!("get at revision {revision}")))?;
+        Ok(StoreSnapshot {
+            revision,
+            kvs: response.take_kvs().into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+/// How long to wait for history before asking etcd for progress again, in milliseconds
+const HISTORY_PROGRESS_INTERVAL: u64 = 250;
+
+fn watch_events(response: &WatchResponse) -> Vec<WatchEvent> {
+    response
+        .events()
+        .iter()
+        .filter_map(|event| {
+            let kv = event.kv()?;
+            let kind = match event.event_type() {
+                EventType::Put => WatchEventKind::Put,
+                EventType::Delete => WatchEventKind::Delete,
+            };
+            Some(WatchEvent {
+                kind,
+                key: String::from_utf8_lossy(kv.key()).to_string(),
+                value: String::from_utf8_lossy(kv.value()).to_string(),
+                revision: kv.mod_revision(),
+            })
+        })
+        .collect()
+}
+
+/// Whether a failed request may succeed when sent again, as during a leader
+/// election or while an endpoint is unreachable
+fn is_retryable(e: &etcd_client::Error) -> bool {
+    match e {
+        etcd_client::Error::GRpcStatus(status) => matches!(
+            status.code(),
+            Code::Unavailable | Code::DeadlineExceeded | Code::Aborted | Code::ResourceExhausted
+        ),
+        etcd_client::Error::TransportError(_) | etcd_client::Error::IoError(_) => true,
+        _ => false,
+    }
+}
+
+/// Map a client error to [`TraefikError::Unavailable`] when it is worth
+/// retrying, naming the endpoints the request was sent to
+fn etcd_error<'a>(
+    action: &'a str,
+    endpoints: &'a [String],
+) -> impl Fn(etcd_client::Error) -> TraefikError + 'a {
+    move |e| {
+        let message = format!("etcd {action} on {} failed: {e}", endpoints.join(","));
+        if is_retryable(&e) {
+            TraefikError::Unavailable(message)
         } else {
-            self.put(key, value, ttl).await?;
+            TraefikError::EtcdError(message)
         }
-        Ok(())
     }
 }
 
 impl Etcd {
-    pub async fn new(config: &EtcdConfig) -> Result<Self> {
-        debug!("Connecting to etcd with config: {:?}", config);
-        let mut connect_options = ConnectOptions::new()
-            .with_connect_timeout(Duration::from_millis(config.timeout))
-            .with_keep_alive(
-                Duration::from_secs(config.keep_alive),
-                Duration::from_millis(config.timeout),
+    fn error<'a>(&'a self, action: &'a str) -> impl Fn(etcd_client::Error) -> TraefikError + 'a {
+        etcd_error(action, &self.endpoints)
+    }
+
+    /// A lease for a key written with a positive `ttl`
+    async fn grant(&self, ttl: Option<i64>) -> TraefikResult<Option<i64>> {
+        match ttl.filter(|ttl| *ttl > 0) {
+            Some(ttl) => Ok(Some(
+                self.client
+                    .to_owned()
+                    .lease_grant(ttl, None)
+                    .await
+                    .map_err(self.error("lease_grant"))?
+                    .id(),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Revoke a lease granted for a write that was not made
+    async fn revoke(&self, lease: Option<i64>) -> TraefikResult<()> {
+        if let Some(lease) = lease {
+            self.client
+                .to_owned()
+                .lease_revoke(lease)
+                .await
+                .map_err(self.error("lease_revoke"))?;
+        }
+        Ok(())
+    }
+
+    /// The keys under `prefix` as of `revision`
+    async fn keys_at(&self, prefix: Vec<u8>, revision: i64) -> TraefikResult<Vec<Vec<u8>>> {
+        let mut response = self
+            .client
+            .to_owned()
+            .get(
+                prefix,
+                Some(
+                    GetOptions::new()
+                        .with_prefix()
+                        .with_keys_only()
+                        .with_revision(revision),
+                ),
             )
-            .with_keep_alive_while_idle(true)
-            .with_timeout(Duration::from_millis(config.timeout));
-
-        if let Some(tls) = &config.tls {
-            if let Some(cert) = &tls.cert {
-                // --cacert=/etc/etcd/ca.pem --cert=/etc/etcd/server.pem --key=/etc/etcd/server-key.pem"
-                let cert = std::fs::read_to_string(cert.trim())?;
-                let key = std::fs::read_to_string(tls.key.as_ref().unwrap().trim())?;
-                let ca = std::fs::read_to_string(tls.ca.as_ref().unwrap().trim())?;
-                let domain = tls.domain.clone().unwrap_or_default();
-
-                let ca = Certificate::from_pem(ca);
-                let cert = Certificate::from_pem(cert);
-                let key = Identity::from_pem(cert, key);
-                let tls_config = ECTlsOptions::new()
-                    .ca_certificate(ca)
-                    .identity(key)
-                    .domain_name(domain);
-
-                connect_options = connect_options.with_tls(tls_config);
+            .await
+            .map_err(self.error("get"))?;
+        Ok(response
+            .take_kvs()
+            .into_iter()
+            .map(|kv| kv.key().to_vec())
+            .collect())
+    }
+
+    /// The lease attached to `key`, `0` when it has none and `None` when the key does not exist
+    async fn lease(&self, key: impl Into<Vec<u8>>) -> TraefikResult<Option<i64>> {
+        Ok(self
+            .client
+            .to_owned()
+            .get(key, Some(GetOptions::new().with_limit(1)))
+            .await
+            .map_err(self.error("get"))?
+            .kvs()
+            .first()
+            .map(|kv| kv.lease()))
+    }
+
+    /// Renew `lease` once, returning `NotFound` when it has already expired
+    ///
+    /// The keep-alive stream for a lease is opened on the first renewal and
+    /// kept for the next ones, so a key can be kept alive indefinitely by
+    /// touching it more often than its TTL.
+    async fn keep_alive(&self, lease: i64) -> TraefikResult<()> {
+        let mut keepers = self.keepers.lock().await;
+        if let Entry::Vacant(entry) = keepers.entry(lease) {
+            let keep_alive = self
+                .client
+                .to_owned()
+                .lease_keep_alive(lease)
+                .await
+                .map_err(self.error("lease_keep_alive"))?;
+            entry.insert(keep_alive);
+        }
+        let (keeper, responses) = keepers.get_mut(&lease).unwrap();
+        let renewed = match keeper.keep_alive().await {
+            Ok(()) => responses.message().await,
+            Err(e) => Err(e),
+        };
+        match renewed {
+            Ok(Some(response)) if response.ttl() > 0 => Ok(()),
+            Ok(_) => {
+                keepers.remove(&lease);
+                Err(TraefikError::NotFound(format!(
+                    "lease {lease:x} has expired"
+                )))
+            }
+            Err(e) => {
+                keepers.remove(&lease);
+                Err(self.error("lease_keep_alive")(e))
             }
         }
+    }
 
-        let client = Client::connect(&config.endpoints, Some(connect_options))
+    pub async fn new(config: &EtcdConfig) -> Result<Self> {
+        debug!("Connecting to etcd with config: {:?}", config);
+        let client = Client::connect(&config.endpoints, Some(config.connect_options()?))
             .await
-            .map_err(|e| eyre!("etcd connect failed: {e}"))?;
-        Ok(Self { client })
+            .map_err(etcd_error("connect", &config.endpoints))?;
+        Ok(Self {
+            client,
+            max_txn_ops: config.max_txn_ops,
+            retry: config.retry,
+            endpoints: config.endpoints.clone(),
+            keepers: Arc::default(),
+        })
+    }
+
+    /// Ask every configured endpoint, one at a time, for its status
+    pub async fn endpoint_status(config: &EtcdConfig) -> Result<Vec<EndpointStatus>> {
+        let connect_options = config.connect_options()?;
+        let mut statuses = Vec::with_capacity(config.endpoints.len());
+        for endpoint in config.endpoints.iter() {
+            let endpoints = [endpoint.clone()];
+            let response = match Client::connect(&endpoints, Some(connect_options.clone())).await {
+                Ok(mut client) => client.status().await,
+                Err(e) => Err(e),
+            };
+            let status = match response {
+                Ok(response) => EndpointStatus {
+                    endpoint: endpoint.clone(),
+                    healthy: response.leader() != 0 && response.errors().is_empty(),
+                    member_id: Some(format!(
+                        "{:x}",
+                        response.header().map_or(0, |h| h.member_id())
+                    )),
+                    is_leader: response
+                        .header()
+                        .is_some_and(|h| h.member_id() == response.leader()),
+                    leader: (response.leader() != 0).then(|| format!("{:x}", response.leader())),
+                    raft_term: Some(response.raft_term()),
+                    db_size: Some(response.db_size()),
+                    version: Some(response.version().to_string()),
+                    errors: response.errors().to_vec(),
+                },
+                Err(e) => EndpointStatus {
+                    endpoint: endpoint.clone(),
+                    healthy: false,
+                    member_id: None,
+                    is_leader: false,
+                    leader: None,
+                    raft_term: None,
+                    db_size: None,
+                    version: None,
+                    errors: vec![etcd_error("status", &endpoints)(e).to_string()],
+                },
+            };
+            statuses.push(status);
+        }
+        Ok(statuses)
     }
 }
 
+/// The health of a single etcd endpoint, as reported by its maintenance API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EndpointStatus {
+    pub endpoint: String,
+    /// The endpoint answered, knows the leader and reports no errors
+    pub healthy: bool,
+    /// The id of the member serving the endpoint, in hex as `etcdctl` prints it
+    pub member_id: Option<String>,
+    pub is_leader: bool,
+    /// The id of the leader this member follows
+    pub leader: Option<String>,
+    pub raft_term: Option<u64>,
+    /// The size of the backend database, in bytes
+    pub db_size: Option<i64>,
+    pub version: Option<String>,
+    /// Alarms raised by the member, or why it could not be reached
+    pub errors: Vec<String>,
+}
+
 impl EtcdConfig {
+    fn connect_options(&self) -> Result<ConnectOptions> {
+        let mut connect_options = ConnectOptions::new()
+            .with_connect_timeout(Duration::from_millis(self.timeout))
+            .with_keep_alive(
+                Duration::from_secs(self.keep_alive),
+                Duration::from_millis(self.timeout),
+            )
+            .with_keep_alive_while_idle(true)
+            .with_timeout(Duration::from_millis(self.timeout));
+
+        if let Some(tls) = &self.tls {
+            connect_options = connect_options.with_tls(tls.to_etcd_options()?);
+        }
+        if let Some(auth) = &self.auth {
+            connect_options = connect_options.with_user(&auth.username, auth.password()?);
+        }
+        Ok(connect_options)
+    }
+
     pub fn merge(self, other: PartialEtcdConfig) -> Self {
         Self {
             endpoints: other.endpoints.unwrap_or(self.endpoints),
             timeout: other.timeout.unwrap_or(self.timeout),
             keep_alive: other.keep_alive.unwrap_or(self.keep_alive),
             tls: other.tls.or(self.tls),
+            auth: other.auth.or(self.auth),
+            max_txn_ops: other.max_txn_ops.unwrap_or(self.max_txn_ops),
+            retry: other.retry.unwrap_or(self.retry),
         }
     }
 }
@@ -272,6 +867,9 @@ pub struct PartialEtcdConfig {
     pub timeout: Option<u64>,
     pub keep_alive: Option<u64>,
     pub tls: Option<TlsOptions>,
+    pub auth: Option<EtcdAuth>,
+    pub max_txn_ops: Option<usize>,
+    pub retry: Option<RetryConfig>,
 }
 
 impl From<String> for PartialEtcdConfig {
@@ -299,4 +897,82 @@ mod tests {
         let result = Etcd::new(&config).await;
         assert!(result.is_ok());
     }
+
+    fn tls(cert: Option<&str>, key: Option<&str>, ca: Option<&str>) -> TlsOptions {
+        TlsOptions {
+            domain: None,
+            cert: cert.map(str::to_string),
+            key: key.map(str::to_string),
+            ca: ca.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_tls_options_report_missing_fields() {
+        let pem = "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----";
+        let err = tls(Some(pem), None, Some(pem))
+            .to_etcd_options()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("tls.cert is set without a tls.key"));
+        let err = tls(None, Some(pem), None).to_etcd_options().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("tls.key is set without a tls.cert"));
+        let err = tls(None, None, Some("/does/not/exist.pem"))
+            .to_etcd_options()
+            .unwrap_err();
+        assert!(err.to_string().contains("tls.ca '/does/not/exist.pem'"));
+    }
+
+    #[test]
+    fn test_tls_options_accept_ca_only_and_inline_pem() {
+        let pem = "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----";
+        assert!(tls(None, None, Some(pem)).to_etcd_options().is_ok());
+        assert_eq!(read_pem("ca", &format!("  {pem}\n")).unwrap(), pem);
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), pem).unwrap();
+        let path = file.path().to_str().unwrap();
+        assert_eq!(read_pem("ca", path).unwrap(), pem);
+    }
+
+    #[test]
+    fn test_auth_password_sources() {
+        let auth = |password: Option<&str>, env: Option<&str>, file: Option<&str>| EtcdAuth {
+            username: "traefikctl".to_string(),
+            password: password.map(str::to_string),
+            password_env: env.map(str::to_string),
+            password_file: file.map(str::to_string),
+        };
+        assert_eq!(
+            auth(Some("secret"), None, None).password().unwrap(),
+            "secret"
+        );
+
+        std::env::set_var("TRAEFIKCTL_TEST_ETCD_PASSWORD", "from-env");
+        let from_env = auth(None, Some("TRAEFIKCTL_TEST_ETCD_PASSWORD"), None);
+        assert_eq!(from_env.password().unwrap(), "from-env");
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "from-file\n").unwrap();
+        let from_file = auth(None, None, file.path().to_str());
+        assert_eq!(from_file.password().unwrap(), "from-file");
+
+        assert!(auth(None, None, None).password().is_err());
+        assert!(auth(Some("a"), Some("B"), None).password().is_err());
+    }
+
+    #[test]
+    fn test_auth_password_is_never_printed() {
+        let auth = EtcdAuth {
+            username: "traefikctl".to_string(),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let serialized = serde_json::to_string(&auth).unwrap();
+        assert!(!serialized.contains("secret"));
+        assert!(!format!("{auth:?}").contains("secret"));
+    }
 }
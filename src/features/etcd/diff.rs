@@ -1,8 +1,8 @@
-use crate::core::client::StoreClient;
+use crate::core::client::{StoreClient, StoreClientActor};
 use crate::core::etcd_trait::EtcdPair;
 use crate::error::TraefikResult;
-use crate::features::etcd::Etcd;
 use colored::Colorize;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Default)]
@@ -13,7 +13,32 @@ pub struct EtcdDiff {
     pub unchanged: Vec<EtcdPair>,
 }
 
+/// A key and its value in the JSON rendering of a diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffEntry {
+    pub key: String,
+    pub value: String,
+}
+
+/// A changed key in the JSON rendering of a diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffModification {
+    pub key: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// The JSON rendering of a diff, every array is sorted by key
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DiffReport {
+    pub added: Vec<DiffEntry>,
+    pub removed: Vec<DiffEntry>,
+    pub modified: Vec<DiffModification>,
+}
+
 impl EtcdDiff {
+    /// When a key is listed more than once the last value wins, as it would
+    /// when the pairs are written to the store in order
     pub fn create(current: &[EtcdPair], comparison: &[EtcdPair]) -> Self {
         let current_map: HashMap<_, _> = current.iter().map(|p| (p.key(), p.value())).collect();
 
@@ -62,6 +87,123 @@ impl EtcdDiff {
         diff
     }
 
+    pub fn has_changes(&self) -> bool {
+        !self.added.is_empty() || !self.removed.is_empty() || !self.modified.is_empty()
+    }
+
+    pub fn report(&self) -> DiffReport {
+        let entry = |pair: &EtcdPair| DiffEntry {
+            key: pair.key().to_string(),
+            value: pair.value().to_string(),
+        };
+        let mut report = DiffReport {
+            added: self.added.iter().map(entry).collect(),
+            removed: self.removed.iter().map(entry).collect(),
+            modified: self
+                .modified
+                .iter()
+                .map(|(old, new)| DiffModification {
+                    key: old.key().to_string(),
+                    old: old.value().to_string(),
+                    new: new.value().to_string(),
+                })
+                .collect(),
+        };
+        report.added.sort_by(|a, b| a.key.cmp(&b.key));
+        report.removed.sort_by(|a, b| a.key.cmp(&b.key));
+        report.modified.sort_by(|a, b| a.key.cmp(&b.key));
+        report
+    }
+
+    /// Render the changes as a patch from `from` to `to`, one `key = value`
+    /// line per side, in key order
+    pub fn to_unified(&self, from: &str, to: &str) -> String {
+        let report = self.report();
+        let mut lines: Vec<(&str, Vec<String>)> = vec![];
+        for entry in report.removed.iter() {
+            lines.push((
+                &entry.key,
+                vec![format!("-{} = {}", entry.key, entry.value)],
+            ));
+        }
+        for entry in report.added.iter() {
+            lines.push((
+                &entry.key,
+                vec![format!("+{} = {}", entry.key, entry.value)],
+            ));
+        }
+        for modification in report.modified.iter() {
+            lines.push((
+                &modification.key,
+                vec![
+                    format!("-{} = {}", modification.key, modification.old),
+                    format!("+{} = {}", modification.key, modification.new),
+                ],
+            ));
+        }
+        lines.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut patch = format!("--- {from}\n+++ {to}\n");
+        for (_key, key_lines) in lines {
+            for line in key_lines {
+                patch.push_str(&line);
+                patch.push('\n');
+            }
+        }
+        patch
+    }
+
+    /// Render the changes as a markdown table, for comments on pull requests
+    pub fn to_markdown(&self) -> String {
+        let report = self.report();
+        if !self.has_changes() {
+            return "No changes\n".to_string();
+        }
+        let mut rows: Vec<(&str, &str, String, String)> = vec![];
+        for entry in report.added.iter() {
+            rows.push((
+                &entry.key,
+                "added",
+                String::new(),
+                markdown_code(&entry.value),
+            ));
+        }
+        for entry in report.removed.iter() {
+            rows.push((
+                &entry.key,
+                "removed",
+                markdown_code(&entry.value),
+                String::new(),
+            ));
+        }
+        for modification in report.modified.iter() {
+            rows.push((
+                &modification.key,
+                "modified",
+                markdown_code(&modification.old),
+                markdown_code(&modification.new),
+            ));
+        }
+        rows.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut table = format!(
+            "**{} added, {} removed, {} modified**\n\n| Change | Key | Old | New |\n| --- | --- | --- | --- |\n",
+            report.added.len(),
+            report.removed.len(),
+            report.modified.len()
+        );
+        for (key, change, old, new) in rows {
+            table.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                change,
+                markdown_code(key),
+                old,
+                new
+            ));
+        }
+        table
+    }
+
     pub fn display(&self, detailed: bool) {
         if detailed {
             println!("\nDetailed Configuration Changes:");
@@ -171,8 +313,22 @@ impl EtcdDiff {
     }
 }
 
-pub async fn compare_etcd_configs(
-    client: &StoreClient<Etcd>,
+/// A markdown code span of `value`, safe inside a table cell
+///
+/// Rules hold backticks, so the span is fenced with more backticks than the
+/// value contains in a row.
+fn markdown_code(value: &str) -> String {
+    let longest_run = value.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run + 1);
+    let value = value.replace('|', "\\|");
+    match longest_run {
+        0 => format!("{fence}{value}{fence}"),
+        _ => format!("{fence} {value} {fence}"),
+    }
+}
+
+pub async fn compare_etcd_configs<T: StoreClientActor>(
+    client: &StoreClient<T>,
     new_pairs: Vec<EtcdPair>,
     base_key: &str,
 ) -> TraefikResult<EtcdDiff> {
@@ -237,3 +393,67 @@ pub async fn compare_etcd_configs(
 
     Ok(diff)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff() -> EtcdDiff {
+        let current = vec![
+            EtcdPair::new("t/http/routers/web/rule", "Host(`new.example.com`)"),
+            EtcdPair::new("t/http/routers/web/priority", "10"),
+            EtcdPair::new("t/http/routers/web/service", "web"),
+        ];
+        let comparison = vec![
+            EtcdPair::new("t/http/routers/web/rule", "Host(`old.example.com`)"),
+            EtcdPair::new("t/http/routers/web/service", "web"),
+            EtcdPair::new("t/http/routers/web/tls", "true"),
+        ];
+        EtcdDiff::create(&current, &comparison)
+    }
+
+    #[test]
+    fn test_report_is_sorted_json() {
+        let diff = diff();
+        assert!(diff.has_changes());
+        let json = serde_json::to_value(diff.report()).unwrap();
+        assert_eq!(json["added"][0]["key"], "t/http/routers/web/priority");
+        assert_eq!(json["removed"][0]["value"], "true");
+        assert_eq!(json["modified"][0]["old"], "Host(`old.example.com`)");
+        assert_eq!(json["modified"][0]["new"], "Host(`new.example.com`)");
+        assert!(!EtcdDiff::create(&[], &[]).has_changes());
+    }
+
+    #[test]
+    fn test_duplicate_keys_keep_the_last_value() {
+        let current = vec![
+            EtcdPair::new("t/http/routers/web/priority", "10"),
+            EtcdPair::new("t/http/routers/web/priority", "20"),
+        ];
+        let comparison = vec![EtcdPair::new("t/http/routers/web/priority", "20")];
+        assert!(!EtcdDiff::create(&current, &comparison).has_changes());
+    }
+
+    #[test]
+    fn test_unified_patch() {
+        assert_eq!(
+            diff().to_unified("live", "config"),
+            "--- live\n+++ config\n\
+             +t/http/routers/web/priority = 10\n\
+             -t/http/routers/web/rule = Host(`old.example.com`)\n\
+             +t/http/routers/web/rule = Host(`new.example.com`)\n\
+             -t/http/routers/web/tls = true\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown_table() {
+        let table = diff().to_markdown();
+        assert!(table.starts_with("**1 added, 1 removed, 1 modified**"));
+        assert!(table.contains(
+            "| modified | `t/http/routers/web/rule` | `` Host(`old.example.com`) `` | `` Host(`new.example.com`) `` |"
+        ));
+        assert_eq!(markdown_code("a|b"), "`a\\|b`");
+        assert_eq!(EtcdDiff::default().to_markdown(), "No changes\n");
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::core::{
+    etcd_trait::EtcdPair,
+    ownership::{describe_object, object_of},
+};
+
+use super::EtcdDiff;
+
+/// The fields that name an element of an indexed list, in order of preference
+const ELEMENT_NAME_FIELDS: [&str; 3] = ["url", "address", "name"];
+
+/// How a router, service or middleware changed as a whole
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ObjectStatus {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A change to one field of an object, indexed lists like
+/// `loadBalancer/servers/N/url` are compared by element rather than index
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "kebab-case")]
+pub enum FieldChange {
+    Set {
+        field: String,
+        value: String,
+    },
+    Unset {
+        field: String,
+        old: String,
+    },
+    Changed {
+        field: String,
+        old: String,
+        new: String,
+    },
+    ItemAdded {
+        list: String,
+        item: String,
+    },
+    ItemRemoved {
+        list: String,
+        item: String,
+    },
+    /// The elements kept in a list are in a different order
+    Reordered {
+        list: String,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldChange::Set { field, value } => write!(f, "{field} set to {value}"),
+            FieldChange::Unset { field, old } => write!(f, "{field} unset (was {old})"),
+            FieldChange::Changed { field, old, new } => {
+                write!(f, "{field} changed from {old} to {new}")
+            }
+            FieldChange::ItemAdded { list, item } => write!(f, "{} {item} added", item_label(list)),
+            FieldChange::ItemRemoved { list, item } => {
+                write!(f, "{} {item} removed", item_label(list))
+            }
+            FieldChange::Reordered { list, old, new } => write!(
+                f,
+                "{list} reordered from [{}] to [{}]",
+                old.join(", "),
+                new.join(", ")
+            ),
+        }
+    }
+}
+
+/// The changes to one router, service or middleware
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ObjectChange {
+    /// `{protocol}/{kind}/{name}` relative to the rule prefix
+    pub object: String,
+    pub status: ObjectStatus,
+    /// Empty for added and removed objects
+    pub changes: Vec<FieldChange>,
+}
+
+impl std::fmt::Display for ObjectChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let object = describe_object(&self.object);
+        match self.status {
+            ObjectStatus::Added => write!(f, "{object} added"),
+            ObjectStatus::Removed => write!(f, "{object} removed"),
+            ObjectStatus::Modified => {
+                let changes = self
+                    .changes
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                write!(f, "{object}: {}", changes.join(", "))
+            }
+        }
+    }
+}
+
+/// The keys of one object, with the indexed lists reassembled
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ObjectState {
+    /// Values by path relative to the object
+    fields: BTreeMap<String, String>,
+    /// The rendered elements of each list in index order, by the path of
+    /// the list
+    lists: BTreeMap<String, Vec<String>>,
+}
+
+impl ObjectState {
+    fn collect<'a>(
+        rule_prefix: &str,
+        pairs: impl Iterator<Item = &'a EtcdPair>,
+    ) -> BTreeMap<String, Self> {
+        // Elements of the lists as their fields, by object, list and index
+        type Elements = BTreeMap<(String, String, usize), BTreeMap<String, String>>;
+        let mut objects: BTreeMap<String, Self> = BTreeMap::new();
+        let mut elements: Elements = BTreeMap::new();
+        for pair in pairs {
+            let relative = pair
+                .key()
+                .strip_prefix(rule_prefix)
+                .unwrap_or(pair.key())
+                .trim_start_matches('/');
+            let object = object_of(relative);
+            let path = relative[object.len()..].trim_start_matches('/');
+            let state = objects.entry(object.clone()).or_default();
+
+            let segments = path.split('/').collect::<Vec<_>>();
+            match segments
+                .iter()
+                .position(|segment| segment.parse::<usize>().is_ok())
+            {
+                Some(at) if at > 0 => {
+                    let list = segments[..at].join("/");
+                    let index = segments[at].parse::<usize>().unwrap_or_default();
+                    state.lists.entry(list.clone()).or_default();
+                    elements
+                        .entry((object, list, index))
+                        .or_default()
+                        .insert(segments[at + 1..].join("/"), pair.value().to_string());
+                }
+                _ => {
+                    state
+                        .fields
+                        .insert(path.to_string(), pair.value().to_string());
+                }
+            }
+        }
+        for ((object, list, _index), fields) in elements {
+            if let Some(items) = objects
+                .get_mut(&object)
+                .and_then(|state| state.lists.get_mut(&list))
+            {
+                items.push(render_element(&fields));
+            }
+        }
+        objects
+    }
+
+    fn changes(&self, new: &Self) -> Vec<FieldChange> {
+        let mut changes = vec![];
+        let fields: BTreeSet<&String> = self.fields.keys().chain(new.fields.keys()).collect();
+        for field in fields {
+            let change = match (self.fields.get(field), new.fields.get(field)) {
+                (None, Some(value)) => FieldChange::Set {
+                    field: field.clone(),
+                    value: value.clone(),
+                },
+                (Some(old), None) => FieldChange::Unset {
+                    field: field.clone(),
+                    old: old.clone(),
+                },
+                (Some(old), Some(value)) if old != value => FieldChange::Changed {
+                    field: field.clone(),
+                    old: old.clone(),
+                    new: value.clone(),
+                },
+                _ => continue,
+            };
+            changes.push(change);
+        }
+
+        let lists: BTreeSet<&String> = self.lists.keys().chain(new.lists.keys()).collect();
+        for list in lists {
+            let old_items = self.lists.get(list).cloned().unwrap_or_default();
+            let new_items = new.lists.get(list).cloned().unwrap_or_default();
+            for item in old_items.iter().filter(|item| !new_items.contains(item)) {
+                changes.push(FieldChange::ItemRemoved {
+                    list: list.clone(),
+                    item: item.clone(),
+                });
+            }
+            for item in new_items.iter().filter(|item| !old_items.contains(item)) {
+                changes.push(FieldChange::ItemAdded {
+                    list: list.clone(),
+                    item: item.clone(),
+                });
+            }
+            // Middlewares run in list order, so moving one is a change
+            let kept_old = old_items
+                .iter()
+                .filter(|item| new_items.contains(item))
+                .collect::<Vec<_>>();
+            let kept_new = new_items
+                .iter()
+                .filter(|item| old_items.contains(item))
+                .collect::<Vec<_>>();
+            if kept_old != kept_new {
+                changes.push(FieldChange::Reordered {
+                    list: list.clone(),
+                    old: old_items.clone(),
+                    new: new_items.clone(),
+                });
+            }
+        }
+        changes
+    }
+}
+
+/// An element of a list as its value, or its naming field followed by the
+/// other fields, like `web (weight=2)`
+fn render_element(fields: &BTreeMap<String, String>) -> String {
+    if let Some(value) = fields.get("") {
+        return value.clone();
+    }
+    let name_field = ELEMENT_NAME_FIELDS
+        .iter()
+        .find(|field| fields.contains_key(**field))
+        .map(|field| field.to_string())
+        .or_else(|| fields.keys().next().cloned())
+        .unwrap_or_default();
+    let rest = fields
+        .iter()
+        .filter(|(field, _)| **field != name_field)
+        .map(|(field, value)| format!("{field}={value}"))
+        .collect::<Vec<_>>();
+    let name = fields.get(&name_field).cloned().unwrap_or_default();
+    match rest.is_empty() {
+        true => name,
+        false => format!("{} ({})", name, rest.join(", ")),
+    }
+}
+
+/// The name of one element of a list, `loadBalancer/servers` gives `server`
+/// and `entryPoints` gives `entry point`
+fn item_label(list: &str) -> String {
+    let last = list.rsplit('/').next().unwrap_or(list);
+    let singular = last.strip_suffix('s').unwrap_or(last);
+    let mut label = String::new();
+    for c in singular.chars() {
+        if c.is_uppercase() {
+            label.push(' ');
+            label.extend(c.to_lowercase());
+        } else {
+            label.push(c);
+        }
+    }
+    label
+}
+
+impl EtcdDiff {
+    /// Group the changed keys into the routers, services and middlewares
+    /// they belong to
+    ///
+    /// Lists are compared by element, so a server moving to another index
+    /// after one before it was removed is not reported.
+    pub fn semantic(&self, rule_prefix: &str) -> Vec<ObjectChange> {
+        let old = ObjectState::collect(
+            rule_prefix,
+            self.removed
+                .iter()
+                .chain(self.modified.iter().map(|(old, _new)| old))
+                .chain(self.unchanged.iter()),
+        );
+        let new = ObjectState::collect(
+            rule_prefix,
+            self.added
+                .iter()
+                .chain(self.modified.iter().map(|(_old, new)| new))
+                .chain(self.unchanged.iter()),
+        );
+
+        let objects: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        objects
+            .into_iter()
+            .filter_map(|object| {
+                let (status, changes) = match (old.get(object), new.get(object)) {
+                    (None, Some(_)) => (ObjectStatus::Added, vec![]),
+                    (Some(_), None) => (ObjectStatus::Removed, vec![]),
+                    (Some(old), Some(new)) => {
+                        let changes = old.changes(new);
+                        if changes.is_empty() {
+                            return None;
+                        }
+                        (ObjectStatus::Modified, changes)
+                    }
+                    (None, None) => return None,
+                };
+                Some(ObjectChange {
+                    object: object.clone(),
+                    status,
+                    changes,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::pairs;
+
+    use super::*;
+
+    #[test]
+    fn test_router_changes_are_described() {
+        let live = pairs(&[
+            ("traefik/http/routers/web/rule", "Host(`a`)"),
+            ("traefik/http/routers/web/priority", "10"),
+            ("traefik/http/routers/web/middlewares/0", "headers"),
+            ("traefik/http/routers/web/tls", "true"),
+        ]);
+        let desired = pairs(&[
+            ("traefik/http/routers/web/rule", "Host(`b`)"),
+            ("traefik/http/routers/web/priority", "20"),
+            ("traefik/http/routers/web/middlewares/0", "headers"),
+            ("traefik/http/routers/web/middlewares/1", "auth"),
+            ("traefik/http/routers/web/service", "web"),
+        ]);
+        let changes = EtcdDiff::create(&desired, &live).semantic("traefik");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            changes[0].to_string(),
+            "http router 'web': priority changed from 10 to 20, \
+             rule changed from Host(`a`) to Host(`b`), service set to web, \
+             tls unset (was true), middleware auth added"
+        );
+    }
+
+    #[test]
+    fn test_lists_are_compared_by_element() {
+        let live = pairs(&[
+            (
+                "traefik/http/services/api/loadBalancer/servers/0/url",
+                "http://10.0.0.1",
+            ),
+            (
+                "traefik/http/services/api/loadBalancer/servers/1/url",
+                "http://10.0.0.2",
+            ),
+            (
+                "traefik/http/services/api/loadBalancer/servers/2/url",
+                "http://10.0.0.3",
+            ),
+            (
+                "traefik/http/services/split/weighted/services/0/name",
+                "blue",
+            ),
+            (
+                "traefik/http/services/split/weighted/services/0/weight",
+                "1",
+            ),
+            (
+                "traefik/http/middlewares/old/headers/customRequestHeaders/X",
+                "1",
+            ),
+        ]);
+        // 10.0.0.2 is gone and 10.0.0.3 moved to index 1
+        let desired = pairs(&[
+            (
+                "traefik/http/services/api/loadBalancer/servers/0/url",
+                "http://10.0.0.1",
+            ),
+            (
+                "traefik/http/services/api/loadBalancer/servers/1/url",
+                "http://10.0.0.3",
+            ),
+            (
+                "traefik/http/services/split/weighted/services/0/name",
+                "blue",
+            ),
+            (
+                "traefik/http/services/split/weighted/services/0/weight",
+                "3",
+            ),
+            ("traefik/http/routers/new/rule", "Host(`new`)"),
+        ]);
+        let changes = EtcdDiff::create(&desired, &live)
+            .semantic("traefik")
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                "http middleware 'old' removed",
+                "http router 'new' added",
+                "http service 'api': server http://10.0.0.2 removed",
+                "http service 'split': service blue (weight=1) removed, \
+                 service blue (weight=3) added",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reordered_middlewares_are_reported() {
+        let live = pairs(&[
+            ("traefik/http/routers/web/middlewares/0", "auth"),
+            ("traefik/http/routers/web/middlewares/1", "headers"),
+        ]);
+        let desired = pairs(&[
+            ("traefik/http/routers/web/middlewares/0", "headers"),
+            ("traefik/http/routers/web/middlewares/1", "auth"),
+        ]);
+        let changes = EtcdDiff::create(&desired, &live).semantic("traefik");
+        assert_eq!(
+            changes[0].to_string(),
+            "http router 'web': middlewares reordered from [auth, headers] to [headers, auth]"
+        );
+        assert!(EtcdDiff::create(&live, &live)
+            .semantic("traefik")
+            .is_empty());
+        assert_eq!(item_label("entryPoints"), "entry point");
+    }
+}
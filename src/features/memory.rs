@@ -0,0 +1,836 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures_util::stream;
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+use crate::{
+    core::{
+        client::{
+            RevisionSummary, StoreClientActor, StoreSnapshot, TxnCompare, TxnOp, WatchEvent,
+            WatchEventKind, WatchStream, DEFAULT_MAX_TXN_OPS,
+        },
+        etcd_trait::EtcdPair,
+    },
+    error::{TraefikError, TraefikResult},
+};
+
+use super::KeyValue;
+
+#[derive(Debug, Clone)]
+struct MemoryEntry {
+    value: Vec<u8>,
+    ttl: Option<i64>,
+    expires_at: Option<Instant>,
+}
+
+impl MemoryEntry {
+    fn new(value: Vec<u8>, ttl: Option<i64>) -> Self {
+        let ttl = ttl.filter(|ttl| *ttl > 0);
+        Self {
+            value,
+            ttl,
+            expires_at: ttl.map(expires_at),
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+fn expires_at(ttl: i64) -> Instant {
+    Instant::now() + Duration::from_secs(ttl as u64)
+}
+
+/// How many unread events a watcher may fall behind by before it lags
+const WATCH_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug)]
+struct MemoryState {
+    entries: BTreeMap<Vec<u8>, MemoryEntry>,
+    /// Bumped on every write, like etcd's store revision
+    revision: i64,
+    events: broadcast::Sender<WatchEvent>,
+    /// Every change made to the store, oldest first, so past revisions can
+    /// be read back like etcd's MVCC history
+    log: Vec<WatchEvent>,
+}
+
+impl Default for MemoryState {
+    fn default() -> Self {
+        let (events, _) = broadcast::channel(WATCH_CHANNEL_CAPACITY);
+        Self {
+            entries: BTreeMap::new(),
+            revision: 0,
+            events,
+            log: Vec::new(),
+        }
+    }
+}
+
+impl MemoryState {
+    /// Delete every entry whose TTL has elapsed
+    fn purge_expired(&mut self) {
+        let now = Instant::now();
+        let expired = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in expired {
+            self.delete(&key);
+        }
+    }
+
+    /// Record a change and notify watchers, it is fine for nobody to be listening
+    fn notify(&mut self, kind: WatchEventKind, key: &[u8], value: &[u8]) {
+        let event = WatchEvent {
+            kind,
+            key: String::from_utf8_lossy(key).to_string(),
+            value: String::from_utf8_lossy(value).to_string(),
+            revision: self.revision,
+        };
+        self.log.push(event.clone());
+        let _ = self.events.send(event);
+    }
+
+    fn prefixed<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (&'a Vec<u8>, &'a MemoryEntry)> {
+        self.entries
+            .range(prefix.to_vec()..)
+            .take_while(move |(key, _)| key.starts_with(prefix))
+    }
+
+    fn put(&mut self, key: Vec<u8>, value: Vec<u8>, ttl: Option<i64>) -> Option<MemoryEntry> {
+        self.revision += 1;
+        self.put_at_revision(key, value, ttl)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Option<MemoryEntry> {
+        if !self.entries.contains_key(key) {
+            return None;
+        }
+        self.revision += 1;
+        self.delete_at_revision(key)
+    }
+
+    /// Put without bumping the revision, for writes grouped in a transaction
+    fn put_at_revision(
+        &mut self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl: Option<i64>,
+    ) -> Option<MemoryEntry> {
+        self.notify(WatchEventKind::Put, &key, &value);
+        self.entries.insert(key, MemoryEntry::new(value, ttl))
+    }
+
+    /// Delete without bumping the revision, for writes grouped in a transaction
+    fn delete_at_revision(&mut self, key: &[u8]) -> Option<MemoryEntry> {
+        let removed = self.entries.remove(key);
+        if removed.is_some() {
+            self.notify(WatchEventKind::Delete, key, &[]);
+        }
+        removed
+    }
+
+    fn holds(&self, compare: &TxnCompare) -> bool {
+        match compare {
+            // The log also holds deletes, which the live entries cannot show
+            TxnCompare::UnchangedSince { prefix, revision } => !self
+                .log
+                .iter()
+                .rev()
+                .take_while(|event| event.revision > *revision)
+                .any(|event| event.key.as_bytes().starts_with(prefix)),
+            TxnCompare::Absent { key } => !self.entries.contains_key(key),
+            TxnCompare::ValueEquals { key, value } => self
+                .entries
+                .get(key)
+                .is_some_and(|entry| &entry.value == value),
+        }
+    }
+}
+
+/// An in-memory key-value store
+///
+/// The memory store implements the same semantics as the etcd backend
+/// (prefix reads and deletes, TTL keys, touching, revisions and
+/// transactions) without a server, which makes it useful for offline runs
+/// and tests. Clones share the same underlying data.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    state: Arc<RwLock<MemoryState>>,
+    max_txn_ops: Option<usize>,
+}
+
+/// The accepted layouts of a memory store seed file
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MemorySeed {
+    Map(BTreeMap<String, serde_yaml::Value>),
+    Pairs(Vec<EtcdPair>),
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the number of operations accepted in a single transaction
+    pub fn with_max_txn_ops(mut self, max_txn_ops: usize) -> Self {
+        self.max_txn_ops = Some(max_txn_ops);
+        self
+    }
+
+    /// Create a memory store seeded with the given pairs
+    pub fn from_pairs(pairs: &[EtcdPair]) -> Self {
+        let store = Self::new();
+        {
+            let mut state = store.state.write().unwrap();
+            for pair in pairs {
+                state.put(
+                    pair.key().as_bytes().to_vec(),
+                    pair.value().as_bytes().to_vec(),
+                    None,
+                );
+            }
+        }
+        store
+    }
+
+    /// Create a memory store from a JSON or YAML seed file
+    ///
+    /// The seed file is either a map of keys to values or a list of
+    /// `[key, value]` pairs.
+    pub fn from_file(path: &Path) -> TraefikResult<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let seed: MemorySeed = serde_yaml::from_str(&content)?;
+        let pairs = match seed {
+            MemorySeed::Map(map) => map
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        serde_yaml::Value::String(value) => value,
+                        serde_yaml::Value::Null => String::new(),
+                        other => serde_yaml::to_string(&other)?.trim_end().to_string(),
+                    };
+                    Ok(EtcdPair::new(key, value))
+                })
+                .collect::<TraefikResult<Vec<_>>>()?,
+            MemorySeed::Pairs(pairs) => pairs,
+        };
+        debug!("Seeding memory store with {} pairs", pairs.len());
+        Ok(Self::from_pairs(&pairs))
+    }
+}
+
+#[async_trait]
+impl StoreClientActor for MemoryStore {
+    async fn get(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<KeyValue> {
+        let key = key.into();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        state
+            .entries
+            .get(&key)
+            .map(|entry| KeyValue {
+                key: key.clone(),
+                value: entry.value.clone(),
+            })
+            .ok_or_else(|| TraefikError::NotFound("data not found".into()))
+    }
+
+    async fn get_with_prefix(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+    ) -> TraefikResult<Vec<KeyValue>> {
+        Ok(self.snapshot(key).await?.kvs)
+    }
+
+    async fn get_keys(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<Vec<KeyValue>> {
+        let prefix = key.into();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        Ok(state
+            .prefixed(&prefix)
+            .map(|(key, _)| KeyValue {
+                key: key.clone(),
+                value: Vec::new(),
+            })
+            .collect())
+    }
+
+    async fn put(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<Option<KeyValue>> {
+        let key = key.into();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        let prev = state.put(key.clone(), value.into(), ttl);
+        Ok(prev.map(|entry| KeyValue {
+            key,
+            value: entry.value,
+        }))
+    }
+
+    async fn delete(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
+        let key = key.into();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        Ok(state.delete(&key).map_or(0, |_| 1))
+    }
+
+    async fn delete_with_prefix(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
+        let prefix = key.into();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        let keys = state
+            .prefixed(&prefix)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in keys.iter() {
+            state.delete(key);
+        }
+        Ok(keys.len() as i64)
+    }
+
+    async fn touch(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<()> {
+        let key = key.into();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        if let Some(entry) = state.entries.get_mut(&key) {
+            if let Some(ttl) = entry.ttl {
+                entry.expires_at = Some(expires_at(ttl));
+            }
+        }
+        Ok(())
+    }
+
+    async fn put_or_touch(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<()> {
+        let key = key.into();
+        let exists = {
+            let mut state = self.state.write().unwrap();
+            state.purge_expired();
+            state.entries.contains_key(&key)
+        };
+        if exists {
+            self.touch(key).await?;
+        } else {
+            self.put(key, value, ttl).await?;
+        }
+        Ok(())
+    }
+
+    async fn put_if_absent(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<bool> {
+        let key = key.into();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        if state.entries.contains_key(&key) {
+            return Ok(false);
+        }
+        state.put(key, value.into(), ttl);
+        Ok(true)
+    }
+
+    async fn snapshot(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<StoreSnapshot> {
+        let prefix = prefix.into();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        Ok(StoreSnapshot {
+            revision: state.revision,
+            kvs: state
+                .prefixed(&prefix)
+                .map(|(key, entry)| KeyValue {
+                    key: key.clone(),
+                    value: entry.value.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    async fn txn(&self, compares: Vec<TxnCompare>, ops: Vec<TxnOp>) -> TraefikResult<bool> {
+        if ops.len() > self.max_txn_ops() {
+            return Err(TraefikError::EtcdError(format!(
+                "too many operations in txn request: {} > {}",
+                ops.len(),
+                self.max_txn_ops()
+            )));
+        }
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        if !compares.iter().all(|compare| state.holds(compare)) {
+            return Ok(false);
+        }
+        // Like etcd, every write in a transaction shares a single revision
+        if !ops.is_empty() {
+            state.revision += 1;
+        }
+        for op in ops {
+            match op {
+                TxnOp::Put { key, value, ttl } => {
+                    state.put_at_revision(key, value, ttl);
+                }
+                TxnOp::Delete { key } => {
+                    state.delete_at_revision(&key);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn max_txn_ops(&self) -> usize {
+        self.max_txn_ops.unwrap_or(DEFAULT_MAX_TXN_OPS)
+    }
+
+    async fn watch(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<WatchStream> {
+        let prefix = String::from_utf8_lossy(&prefix.into()).to_string();
+        let receiver = self.state.read().unwrap().events.subscribe();
+        let events = stream::unfold(receiver, move |mut receiver| {
+            let prefix = prefix.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) if event.key.starts_with(&prefix) => {
+                            return Some((Ok(event), receiver))
+                        }
+                        Ok(_) => continue,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            let error = TraefikError::EtcdError(format!(
+                                "watch fell behind, {skipped} events were dropped"
+                            ));
+                            return Some((Err(error), receiver));
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+        Ok(Box::pin(events))
+    }
+
+    async fn history(
+        &self,
+        prefix: impl Into<Vec<u8>> + Send,
+    ) -> TraefikResult<Vec<RevisionSummary>> {
+        let prefix = String::from_utf8_lossy(&prefix.into()).to_string();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        Ok(RevisionSummary::from_events(
+            state
+                .log
+                .iter()
+                .filter(|event| event.key.starts_with(&prefix)),
+        ))
+    }
+
+    async fn snapshot_at(
+        &self,
+        prefix: impl Into<Vec<u8>> + Send,
+        revision: i64,
+    ) -> TraefikResult<StoreSnapshot> {
+        if revision < 1 {
+            return Err(TraefikError::ConfigError(format!(
+                "revision {revision} is invalid, revisions start at 1"
+            )));
+        }
+        let prefix = String::from_utf8_lossy(&prefix.into()).to_string();
+        let mut state = self.state.write().unwrap();
+        state.purge_expired();
+        if revision > state.revision {
+            return Err(TraefikError::NotFound(format!(
+                "revision {revision} is in the future, the store is at {}",
+                state.revision
+            )));
+        }
+        let mut entries = BTreeMap::new();
+        for event in state
+            .log
+            .iter()
+            .take_while(|event| event.revision <= revision)
+            .filter(|event| event.key.starts_with(&prefix))
+        {
+            match event.kind {
+                WatchEventKind::Put => {
+                    entries.insert(event.key.clone(), event.value.clone());
+                }
+                WatchEventKind::Delete => {
+                    entries.remove(&event.key);
+                }
+            }
+        }
+        Ok(StoreSnapshot {
+            revision,
+            kvs: entries
+                .into_iter()
+                .map(|(key, value)| KeyValue {
+                    key: key.into_bytes(),
+                    value: value.into_bytes(),
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expire_now(store: &MemoryStore, key: &str) {
+        let mut state = store.state.write().unwrap();
+        let entry = state.entries.get_mut(key.as_bytes()).unwrap();
+        entry.expires_at = Some(Instant::now());
+    }
+
+    #[tokio::test]
+    async fn test_put_and_get() {
+        let store = MemoryStore::new();
+        let prev = store.put("traefik/a", "1", None).await.unwrap();
+        assert!(prev.is_none());
+        let prev = store.put("traefik/a", "2", None).await.unwrap();
+        assert_eq!(prev.unwrap().value_str(), Some("1"));
+        let kv = store.get("traefik/a").await.unwrap();
+        assert_eq!(kv.value_str(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn test_get_missing_key_is_not_found() {
+        let store = MemoryStore::new();
+        let result = store.get("traefik/missing").await;
+        assert!(matches!(result, Err(TraefikError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_with_prefix_only_returns_prefixed_keys() {
+        let store = MemoryStore::from_pairs(&[
+            EtcdPair::new("traefik/http/routers/a/rule", "Host(`a`)"),
+            EtcdPair::new("traefik/http/routers/b/rule", "Host(`b`)"),
+            EtcdPair::new("other/key", "value"),
+        ]);
+        let kvs = store.get_with_prefix("traefik/").await.unwrap();
+        let keys: Vec<_> = kvs.iter().map(|kv| kv.key_str().unwrap()).collect();
+        assert_eq!(
+            keys,
+            vec!["traefik/http/routers/a/rule", "traefik/http/routers/b/rule"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_keys_omits_values() {
+        let store = MemoryStore::from_pairs(&[EtcdPair::new("traefik/a", "1")]);
+        let kvs = store.get_keys("traefik").await.unwrap();
+        assert_eq!(kvs.len(), 1);
+        assert_eq!(kvs[0].key_str(), Some("traefik/a"));
+        assert!(kvs[0].value.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_with_prefix() {
+        let store = MemoryStore::from_pairs(&[
+            EtcdPair::new("traefik/a", "1"),
+            EtcdPair::new("traefik/b", "2"),
+            EtcdPair::new("other/c", "3"),
+        ]);
+        assert_eq!(store.delete_with_prefix("traefik").await.unwrap(), 2);
+        assert_eq!(store.delete("other/c").await.unwrap(), 1);
+        assert_eq!(store.delete("other/c").await.unwrap(), 0);
+        assert!(store.get_with_prefix("").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ttl_keys_expire() {
+        let store = MemoryStore::new();
+        store.put("traefik/lease", "1", Some(30)).await.unwrap();
+        store.put("traefik/static", "1", None).await.unwrap();
+        expire_now(&store, "traefik/lease");
+        assert!(store.get("traefik/lease").await.is_err());
+        assert!(store.get("traefik/static").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_touch_extends_ttl() {
+        let store = MemoryStore::new();
+        store.put("traefik/lease", "1", Some(30)).await.unwrap();
+        {
+            // Pretend the lease is about to run out
+            let mut state = store.state.write().unwrap();
+            state
+                .entries
+                .get_mut("traefik/lease".as_bytes())
+                .unwrap()
+                .expires_at = Some(Instant::now() + Duration::from_secs(1));
+        }
+        store.touch("traefik/lease").await.unwrap();
+        let state = store.state.read().unwrap();
+        let entry = state.entries.get("traefik/lease".as_bytes()).unwrap();
+        assert!(entry.expires_at.unwrap() > Instant::now() + Duration::from_secs(20));
+    }
+
+    #[tokio::test]
+    async fn test_put_or_touch_keeps_existing_value() {
+        let store = MemoryStore::new();
+        store
+            .put_or_touch("traefik/lease", "first", Some(30))
+            .await
+            .unwrap();
+        store
+            .put_or_touch("traefik/lease", "second", Some(30))
+            .await
+            .unwrap();
+        let kv = store.get("traefik/lease").await.unwrap();
+        assert_eq!(kv.value_str(), Some("first"));
+    }
+
+    #[tokio::test]
+    async fn test_put_if_absent_only_creates() {
+        let store = MemoryStore::new();
+        assert!(store.put_if_absent("lock", "a", Some(60)).await.unwrap());
+        assert!(!store.put_if_absent("lock", "b", Some(60)).await.unwrap());
+        assert_eq!(store.get("lock").await.unwrap().value_str(), Some("a"));
+        expire_now(&store, "lock");
+        assert!(store.put_if_absent("lock", "b", None).await.unwrap());
+    }
+
+    #[test]
+    fn test_from_file_with_map_seed() {
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            tmpfile.path(),
+            "traefik/http/routers/a/rule: \"Host(`a`)\"\ntraefik/http/routers/a/priority: 1010\n",
+        )
+        .unwrap();
+        let store = MemoryStore::from_file(tmpfile.path()).unwrap();
+        let entries = &store.state.read().unwrap().entries;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries
+                .get("traefik/http/routers/a/priority".as_bytes())
+                .unwrap()
+                .value,
+            b"1010"
+        );
+    }
+
+    #[test]
+    fn test_from_file_with_pairs_seed() {
+        let tmpfile = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            tmpfile.path(),
+            r#"[["traefik/a", "value with spaces"], ["traefik/b", ""]]"#,
+        )
+        .unwrap();
+        let store = MemoryStore::from_file(tmpfile.path()).unwrap();
+        let entries = &store.state.read().unwrap().entries;
+        assert_eq!(
+            entries.get("traefik/a".as_bytes()).unwrap().value,
+            b"value with spaces"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_tracks_revision() {
+        let store = MemoryStore::new();
+        let before = store.snapshot("traefik").await.unwrap();
+        store.put("traefik/a", "1", None).await.unwrap();
+        store.put("other/b", "1", None).await.unwrap();
+        let after = store.snapshot("traefik").await.unwrap();
+        assert_eq!(after.revision, before.revision + 2);
+        assert_eq!(after.kvs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_txn_commits_when_unchanged() {
+        let store = MemoryStore::from_pairs(&[EtcdPair::new("traefik/a", "1")]);
+        let snapshot = store.snapshot("traefik").await.unwrap();
+        let committed = store
+            .txn(
+                vec![TxnCompare::UnchangedSince {
+                    prefix: "traefik".into(),
+                    revision: snapshot.revision,
+                }],
+                vec![TxnOp::delete("traefik/a"), TxnOp::put("traefik/b", "2")],
+            )
+            .await
+            .unwrap();
+        assert!(committed);
+        assert!(store.get("traefik/a").await.is_err());
+        assert_eq!(store.get("traefik/b").await.unwrap().value_str(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn test_txn_writes_nothing_on_conflict() {
+        let store = MemoryStore::from_pairs(&[EtcdPair::new("traefik/a", "1")]);
+        let snapshot = store.snapshot("traefik").await.unwrap();
+        store.put("traefik/a", "changed", None).await.unwrap();
+        let committed = store
+            .txn(
+                vec![TxnCompare::UnchangedSince {
+                    prefix: "traefik".into(),
+                    revision: snapshot.revision,
+                }],
+                vec![TxnOp::put("traefik/b", "2")],
+            )
+            .await
+            .unwrap();
+        assert!(!committed);
+        assert!(store.get("traefik/b").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_txn_sees_deletes_after_the_revision() {
+        let store = MemoryStore::from_pairs(&[
+            EtcdPair::new("traefik/a", "1"),
+            EtcdPair::new("traefik/b", "1"),
+        ]);
+        let snapshot = store.snapshot("traefik").await.unwrap();
+        store.delete("traefik/a").await.unwrap();
+        let committed = store
+            .txn(
+                vec![TxnCompare::UnchangedSince {
+                    prefix: "traefik".into(),
+                    revision: snapshot.revision,
+                }],
+                vec![TxnOp::put("traefik/c", "2")],
+            )
+            .await
+            .unwrap();
+        assert!(!committed);
+        assert!(store.get("traefik/c").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_txn_absent_and_value_equals() {
+        let store = MemoryStore::from_pairs(&[EtcdPair::new("guard", "me")]);
+        let absent = TxnCompare::Absent {
+            key: "guard".into(),
+        };
+        assert!(!store.txn(vec![absent], vec![]).await.unwrap());
+        let ours = TxnCompare::ValueEquals {
+            key: "guard".into(),
+            value: "me".into(),
+        };
+        assert!(store
+            .txn(vec![ours], vec![TxnOp::delete("guard")])
+            .await
+            .unwrap());
+        assert!(store.get("guard").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watch_streams_prefixed_changes() {
+        use futures_util::StreamExt;
+
+        let store = MemoryStore::new();
+        let mut events = store.watch("traefik/").await.unwrap();
+        store.put("traefik/a", "1", None).await.unwrap();
+        store.put("other/b", "1", None).await.unwrap();
+        store.delete("traefik/a").await.unwrap();
+
+        let put = events.next().await.unwrap().unwrap();
+        assert_eq!(put.kind, WatchEventKind::Put);
+        assert_eq!(put.key, "traefik/a");
+        assert_eq!(put.value, "1");
+        assert_eq!(put.revision, 1);
+
+        let delete = events.next().await.unwrap().unwrap();
+        assert_eq!(delete.kind, WatchEventKind::Delete);
+        assert_eq!(delete.key, "traefik/a");
+        assert_eq!(delete.revision, 3);
+    }
+
+    #[tokio::test]
+    async fn test_txn_writes_share_a_revision() {
+        let store = MemoryStore::new();
+        store
+            .txn(
+                vec![],
+                vec![TxnOp::put("traefik/a", "1"), TxnOp::put("traefik/b", "2")],
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.snapshot("traefik").await.unwrap().revision, 1);
+    }
+
+    #[tokio::test]
+    async fn test_history_lists_prefixed_revisions() {
+        let store = MemoryStore::new();
+        store
+            .txn(
+                vec![],
+                vec![TxnOp::put("traefik/a", "1"), TxnOp::put("traefik/b", "2")],
+            )
+            .await
+            .unwrap();
+        store.put("other/c", "1", None).await.unwrap();
+        store.delete("traefik/a").await.unwrap();
+
+        let history = store.history("traefik/").await.unwrap();
+        assert_eq!(
+            history,
+            vec![
+                RevisionSummary {
+                    revision: 1,
+                    puts: 2,
+                    deletes: 0
+                },
+                RevisionSummary {
+                    revision: 3,
+                    puts: 0,
+                    deletes: 1
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_at_reads_past_revisions() {
+        let store = MemoryStore::new();
+        store.put("traefik/a", "1", None).await.unwrap();
+        store.put("traefik/a", "2", None).await.unwrap();
+        store.put("traefik/b", "1", None).await.unwrap();
+        store.delete("traefik/a").await.unwrap();
+
+        let at = |revision| {
+            let store = store.clone();
+            async move {
+                store
+                    .snapshot_at("traefik/", revision)
+                    .await
+                    .unwrap()
+                    .kvs
+                    .into_iter()
+                    .map(|kv| EtcdPair::from(kv).to_string())
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(at(1).await, vec!["traefik/a 1"]);
+        assert_eq!(at(3).await, vec!["traefik/a 2", "traefik/b 1"]);
+        assert_eq!(at(4).await, vec!["traefik/b 1"]);
+        assert!(store.snapshot_at("traefik/", 5).await.is_err());
+        assert!(store.snapshot_at("traefik/", 0).await.is_err());
+    }
+}
@@ -2,6 +2,13 @@
 pub mod etcd;
 #[cfg(feature = "etcd")]
 pub use etcd_client::KeyValue as KV;
+pub mod memory;
+
+#[cfg(feature = "consul")]
+pub mod consul;
+
+#[cfg(feature = "redis")]
+pub mod redis;
 use serde::de::DeserializeOwned;
 
 #[cfg(feature = "api")]
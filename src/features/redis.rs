@@ -0,0 +1,1122 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use async_trait::async_trait;
+use export_type::ExportType;
+use futures_util::StreamExt;
+use redis::{
+    aio::{ConnectionManager, ConnectionManagerConfig},
+    AsyncCommands, Client, ErrorKind, Script,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    core::{
+        client::{
+            StoreClientActor, StoreSnapshot, TxnCompare, TxnOp, WatchEvent, WatchEventKind,
+            WatchStream,
+        },
+        meta_key, META_BASE_KEY,
+    },
+    error::{TraefikError, TraefikResult},
+};
+
+use super::KeyValue;
+
+/// How many keys a single `SCAN` call is asked to return
+const SCAN_COUNT: usize = 500;
+
+/// Applies a transaction atomically, see [`txn_args`] for the layout of
+/// `KEYS` and `ARGV`
+const TXN_SCRIPT: &str = r#"
+local k = 2
+local i = 1
+local compares = tonumber(ARGV[i])
+i = i + 1
+for _ = 1, compares do
+  local kind, key, arg = ARGV[i], KEYS[k], ARGV[i + 1]
+  i = i + 2
+  k = k + 1
+  if kind == 'unchanged' then
+    if tonumber(redis.call('GET', key) or '0') > tonumber(arg) then return 0 end
+  elseif kind == 'absent' then
+    if redis.call('EXISTS', key) == 1 then return 0 end
+  elseif kind == 'equals' then
+    if redis.call('GET', key) ~= arg then return 0 end
+  end
+end
+local ops = tonumber(ARGV[i])
+i = i + 1
+for _ = 1, ops do
+  local verb, key, value, ttl = ARGV[i], KEYS[k], ARGV[i + 1], tonumber(ARGV[i + 2])
+  i = i + 3
+  k = k + 1
+  if verb == 'put' and ttl > 0 then
+    redis.call('SET', key, value, 'EX', ttl)
+    redis.call('HSET', KEYS[1], key, ttl)
+  else
+    if verb == 'put' then
+      redis.call('SET', key, value)
+    else
+      redis.call('DEL', key)
+    end
+    redis.call('HDEL', KEYS[1], key)
+  end
+end
+for j = k, #KEYS do
+  redis.call('INCR', KEYS[j])
+end
+return 1
+"#;
+
+/// Creates `KEYS[2]` only if it does not exist, with a TTL in seconds in
+/// `ARGV[2]` when it is positive, and bumps the revision in `KEYS[3]` when
+/// one is given
+const PUT_IF_ABSENT_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[2]) == 1 then return 0 end
+local ttl = tonumber(ARGV[2])
+if ttl > 0 then
+  redis.call('SET', KEYS[2], ARGV[1], 'EX', ttl)
+  redis.call('HSET', KEYS[1], KEYS[2], ttl)
+else
+  redis.call('SET', KEYS[2], ARGV[1])
+  redis.call('HDEL', KEYS[1], KEYS[2])
+end
+if KEYS[3] then redis.call('INCR', KEYS[3]) end
+return 1
+"#;
+
+/// The configuration for the redis client
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema, sqlx::FromRow))]
+#[cfg_attr(feature = "codegen", derive(ExportType))]
+#[export_type(rename_all = "camelCase", path = "generated/types")]
+pub struct RedisConfig {
+    /// A `redis://` or `rediss://` connection url, including the database
+    pub url: String,
+    pub timeout: u64,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://127.0.0.1:6379".to_owned(),
+            timeout: 2000,
+        }
+    }
+}
+
+/// A redis backed store
+///
+/// Redis has no revisions, so writes made through traefikctl bump a counter
+/// kept for the first segment of the key, `_traefikctl/{segment}/revision`.
+/// Snapshots and transactions use the counter of their prefix, so a prefix
+/// should end at a segment boundary, and writes under another segment or to
+/// traefikctl's own keys, like locks, are not a change. Changes made to
+/// redis by other clients are not detected. TTLs are remembered in the `_traefikctl/ttl` hash so touching a
+/// key can restore its full expiry.
+#[derive(Clone)]
+pub struct Redis {
+    pub client: Client,
+    pub connection: ConnectionManager,
+}
+
+impl std::fmt::Debug for Redis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Redis {{ client: <hidden> }}")
+    }
+}
+
+/// Whether a failure came from the connection or a server that cannot serve
+/// the request right now, rather than from the request itself
+fn is_retryable(e: &redis::RedisError) -> bool {
+    e.is_io_error()
+        || e.is_connection_refusal()
+        || e.is_connection_dropped()
+        || e.is_timeout()
+        || matches!(
+            e.kind(),
+            ErrorKind::TryAgain
+                | ErrorKind::BusyLoadingError
+                | ErrorKind::MasterDown
+                | ErrorKind::ClusterDown
+        )
+}
+
+/// Map a client error to [`TraefikError::Unavailable`] when it is worth
+/// retrying and to [`TraefikError::RedisError`] otherwise
+fn redis_error(action: &str) -> impl Fn(redis::RedisError) -> TraefikError + '_ {
+    move |e| {
+        let message = format!("redis {action} failed: {e}");
+        if is_retryable(&e) {
+            TraefikError::Unavailable(message)
+        } else {
+            TraefikError::RedisError(message)
+        }
+    }
+}
+
+/// The counter of the first segment of `key`
+fn revision_key(key: &[u8]) -> String {
+    let segment = key.split(|byte| *byte == b'/').next().unwrap_or_default();
+    meta_key(&String::from_utf8_lossy(segment), "revision")
+}
+
+/// The counter a write to `key` bumps, none for traefikctl's own keys
+fn bumped_revision_key(key: &[u8]) -> Option<String> {
+    let meta = key
+        .strip_prefix(META_BASE_KEY.as_bytes())
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(b"/"));
+    (!meta).then(|| revision_key(key))
+}
+
+fn ttl_key() -> String {
+    format!("{META_BASE_KEY}/ttl")
+}
+
+/// Escape the glob characters of `prefix` so it can be used as a `MATCH` pattern
+fn match_pattern(prefix: &[u8]) -> Vec<u8> {
+    let mut pattern = Vec::with_capacity(prefix.len() + 1);
+    for byte in prefix {
+        if matches!(byte, b'*' | b'?' | b'[' | b']' | b'\\') {
+            pattern.push(b'\\');
+        }
+        pattern.push(*byte);
+    }
+    pattern.push(b'*');
+    pattern
+}
+
+/// Flatten a transaction into the `KEYS` and `ARGV` of [`TXN_SCRIPT`]
+///
+/// `KEYS` holds the ttl hash, then the key of each comparison (the revision
+/// counter of the prefix for `unchanged`), the key of each operation and
+/// last the counters the operations bump. `ARGV` holds the number of
+/// comparisons followed by a `(kind, arg)` pair for each, then the number of
+/// operations followed by a `(verb, value, ttl)` triple for each, with a ttl
+/// of 0 for none.
+fn txn_args(compares: &[TxnCompare], ops: &[TxnOp]) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+    let mut keys = vec![ttl_key().into_bytes()];
+    let mut args = vec![compares.len().to_string().into_bytes()];
+    for compare in compares {
+        let (kind, key, arg) = match compare {
+            TxnCompare::UnchangedSince { prefix, revision } => (
+                "unchanged",
+                revision_key(prefix).into_bytes(),
+                revision.to_string().into_bytes(),
+            ),
+            TxnCompare::Absent { key } => ("absent", key.clone(), Vec::new()),
+            TxnCompare::ValueEquals { key, value } => ("equals", key.clone(), value.clone()),
+        };
+        keys.push(key);
+        args.extend([kind.as_bytes().to_vec(), arg]);
+    }
+    args.push(ops.len().to_string().into_bytes());
+    for op in ops {
+        let (verb, value, ttl) = match op {
+            TxnOp::Put { value, ttl, .. } => ("put", value.clone(), ttl.unwrap_or_default()),
+            TxnOp::Delete { .. } => ("delete", Vec::new(), 0),
+        };
+        keys.push(op.key().to_vec());
+        args.extend([
+            verb.as_bytes().to_vec(),
+            value,
+            ttl.to_string().into_bytes(),
+        ]);
+    }
+    let bumped = ops
+        .iter()
+        .filter_map(|op| bumped_revision_key(op.key()))
+        .collect::<BTreeSet<_>>();
+    keys.extend(bumped.into_iter().map(String::into_bytes));
+    (keys, args)
+}
+
+impl Redis {
+    pub async fn new(config: &RedisConfig) -> TraefikResult<Self> {
+        debug!("Connecting to redis");
+        let client = Client::open(config.url.as_str()).map_err(redis_error("connect"))?;
+        let timeout = Duration::from_millis(config.timeout);
+        let connection = client
+            .get_connection_manager_with_config(
+                ConnectionManagerConfig::new()
+                    .set_connection_timeout(timeout)
+                    .set_response_timeout(timeout),
+            )
+            .await
+            .map_err(redis_error("connect"))?;
+        Ok(Self { client, connection })
+    }
+
+    async fn scan(&self, prefix: &[u8]) -> TraefikResult<Vec<Vec<u8>>> {
+        let mut connection = self.connection.clone();
+        let pattern = match_pattern(prefix);
+        let mut cursor: u64 = 0;
+        let mut keys = Vec::new();
+        loop {
+            let (next, batch): (u64, Vec<Vec<u8>>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut connection)
+                .await
+                .map_err(redis_error("scan"))?;
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        // SCAN may return a key more than once
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    /// The revision of the segment holding `prefix`
+    async fn revision(&self, prefix: &[u8]) -> TraefikResult<i64> {
+        let mut connection = self.connection.clone();
+        let revision: Option<i64> = connection
+            .get(revision_key(prefix))
+            .await
+            .map_err(redis_error("get"))?;
+        Ok(revision.unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl StoreClientActor for Redis {
+    async fn get(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<KeyValue> {
+        let key = key.into();
+        let mut connection = self.connection.clone();
+        let value: Option<Vec<u8>> = connection.get(&key).await.map_err(redis_error("get"))?;
+        value
+            .map(|value| KeyValue { key, value })
+            .ok_or_else(|| TraefikError::NotFound("data not found".into()))
+    }
+
+    async fn get_with_prefix(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+    ) -> TraefikResult<Vec<KeyValue>> {
+        let keys = self.scan(&key.into()).await?;
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut connection = self.connection.clone();
+        let values: Vec<Option<Vec<u8>>> = redis::cmd("MGET")
+            .arg(&keys)
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error("mget"))?;
+        // Keys deleted between the scan and the read are skipped
+        Ok(keys
+            .into_iter()
+            .zip(values)
+            .filter_map(|(key, value)| value.map(|value| KeyValue { key, value }))
+            .collect())
+    }
+
+    async fn get_keys(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<Vec<KeyValue>> {
+        Ok(self
+            .scan(&key.into())
+            .await?
+            .into_iter()
+            .map(|key| KeyValue {
+                key,
+                value: Vec::new(),
+            })
+            .collect())
+    }
+
+    async fn put(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<Option<KeyValue>> {
+        let key = key.into();
+        let mut connection = self.connection.clone();
+        let mut pipe = redis::pipe();
+        pipe.atomic().get(&key);
+        match ttl.filter(|ttl| *ttl > 0) {
+            Some(ttl) => {
+                pipe.set_ex(&key, value.into(), ttl as u64)
+                    .ignore()
+                    .hset(ttl_key(), &key, ttl)
+                    .ignore();
+            }
+            None => {
+                pipe.set(&key, value.into())
+                    .ignore()
+                    .hdel(ttl_key(), &key)
+                    .ignore();
+            }
+        }
+        if let Some(revision_key) = bumped_revision_key(&key) {
+            pipe.incr(revision_key, 1).ignore();
+        }
+        let (prev,): (Option<Vec<u8>>,) = pipe
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error("set"))?;
+        Ok(prev.map(|value| KeyValue { key, value }))
+    }
+
+    async fn delete(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
+        let key = key.into();
+        let mut connection = self.connection.clone();
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&key).hdel(ttl_key(), &key).ignore();
+        if let Some(revision_key) = bumped_revision_key(&key) {
+            pipe.incr(revision_key, 1).ignore();
+        }
+        let (deleted,): (i64,) = pipe
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error("del"))?;
+        Ok(deleted)
+    }
+
+    async fn delete_with_prefix(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<i64> {
+        let keys = self.scan(&key.into()).await?;
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let mut connection = self.connection.clone();
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&keys).hdel(ttl_key(), &keys).ignore();
+        let bumped = keys
+            .iter()
+            .filter_map(|key| bumped_revision_key(key))
+            .collect::<BTreeSet<_>>();
+        for revision_key in bumped {
+            pipe.incr(revision_key, 1).ignore();
+        }
+        let (deleted,): (i64,) = pipe
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error("del"))?;
+        Ok(deleted)
+    }
+
+    async fn touch(&self, key: impl Into<Vec<u8>> + Send) -> TraefikResult<()> {
+        let key = key.into();
+        let mut connection = self.connection.clone();
+        let ttl: Option<i64> = connection
+            .hget(ttl_key(), &key)
+            .await
+            .map_err(redis_error("hget"))?;
+        if let Some(ttl) = ttl {
+            let _: bool = connection
+                .expire(&key, ttl)
+                .await
+                .map_err(redis_error("expire"))?;
+        }
+        Ok(())
+    }
+
+    async fn put_or_touch(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<()> {
+        let key = key.into();
+        let mut connection = self.connection.clone();
+        let exists: bool = connection
+            .exists(&key)
+            .await
+            .map_err(redis_error("exists"))?;
+        if exists {
+            self.touch(key).await
+        } else {
+            self.put(key, value, ttl).await.map(|_| ())
+        }
+    }
+
+    async fn put_if_absent(
+        &self,
+        key: impl Into<Vec<u8>> + Send,
+        value: impl Into<Vec<u8>> + Send,
+        ttl: Option<i64>,
+    ) -> TraefikResult<bool> {
+        let key = key.into();
+        let mut connection = self.connection.clone();
+        let script = Script::new(PUT_IF_ABSENT_SCRIPT);
+        let mut invocation = script.key(ttl_key());
+        invocation.key(&key);
+        if let Some(revision_key) = bumped_revision_key(&key) {
+            invocation.key(revision_key);
+        }
+        let created: i64 = invocation
+            .arg(value.into())
+            .arg(ttl.unwrap_or_default())
+            .invoke_async(&mut connection)
+            .await
+            .map_err(redis_error("put if absent"))?;
+        Ok(created == 1)
+    }
+
+    /// The revision is read before the keys, so a write racing the scan can
+    /// only make the snapshot look older than it is and fail the next compare
+    async fn snapshot(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<StoreSnapshot> {
+        let prefix = prefix.into();
+        let revision = self.revision(&prefix).await?;
+        let kvs = self.get_with_prefix(prefix).await?;
+        Ok(StoreSnapshot { revision, kvs })
+    }
+
+    async fn txn(&self, compares: Vec<TxnCompare>, ops: Vec<TxnOp>) -> TraefikResult<bool> {
+        let mut connection = self.connection.clone();
+        let script = Script::new(TXN_SCRIPT);
+        let (keys, args) = txn_args(&compares, &ops);
+        let mut invocation = script.prepare_invoke();
+        for key in keys {
+            invocation.key(key);
+        }
+        for arg in args {
+            invocation.arg(arg);
+        }
+        let committed: i64 = invocation
+            .invoke_async(&mut connection)
+            .await
+            .map_err(redis_error("txn"))?;
+        Ok(committed == 1)
+    }
+
+    /// Redis executes a script atomically whatever its size
+    fn max_txn_ops(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Uses keyspace notifications, which need `notify-keyspace-events` to
+    /// include `K`, `$`, `g` and `x` on the server
+    async fn watch(&self, prefix: impl Into<Vec<u8>> + Send) -> TraefikResult<WatchStream> {
+        let prefix = String::from_utf8_lossy(&prefix.into()).to_string();
+        let database = self.client.get_connection_info().redis.db;
+        let channel_prefix = format!("__keyspace@{database}__:");
+        let mut pubsub = self
+            .client
+            .get_async_pubsub()
+            .await
+            .map_err(redis_error("subscribe"))?;
+        pubsub
+            .psubscribe(format!(
+                "{channel_prefix}{}",
+                String::from_utf8_lossy(&match_pattern(prefix.as_bytes()))
+            ))
+            .await
+            .map_err(redis_error("subscribe"))?;
+
+        let redis = self.clone();
+        let events = pubsub.into_on_message().then(move |message| {
+            let redis = redis.clone();
+            let channel_prefix = channel_prefix.clone();
+            async move {
+                let key = message
+                    .get_channel_name()
+                    .strip_prefix(&channel_prefix)
+                    .unwrap_or_default()
+                    .to_string();
+                let operation: String = message.get_payload().map_err(redis_error("watch"))?;
+                let kind = match operation.as_str() {
+                    "set" => WatchEventKind::Put,
+                    "del" | "expired" => WatchEventKind::Delete,
+                    _ => return Ok(None),
+                };
+                let value = match kind {
+                    WatchEventKind::Put => match redis.get(key.as_str()).await {
+                        Ok(kv) => String::from_utf8_lossy(&kv.value).to_string(),
+                        // Deleted again before we could read it
+                        Err(TraefikError::NotFound(_)) => return Ok(None),
+                        Err(e) => return Err(e),
+                    },
+                    WatchEventKind::Delete => String::new(),
+                };
+                let revision = redis.revision(key.as_bytes()).await?;
+                Ok(Some(WatchEvent {
+                    kind,
+                    key,
+                    value,
+                    revision,
+                }))
+            }
+        });
+        let events = events.filter_map(|event| async move { event.transpose() });
+        Ok(Box::pin(events))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    enum Reply {
+        Status(&'static str),
+        Error(String),
+        Int(i64),
+        Bulk(Option<Vec<u8>>),
+        Array(Vec<Reply>),
+    }
+
+    impl Reply {
+        fn encode(&self, out: &mut Vec<u8>) {
+            match self {
+                Reply::Status(status) => out.extend(format!("+{status}\r\n").as_bytes()),
+                Reply::Error(error) => out.extend(format!("-{error}\r\n").as_bytes()),
+                Reply::Int(n) => out.extend(format!(":{n}\r\n").as_bytes()),
+                Reply::Bulk(None) => out.extend(b"$-1\r\n"),
+                Reply::Bulk(Some(value)) => {
+                    out.extend(format!("${}\r\n", value.len()).as_bytes());
+                    out.extend(value);
+                    out.extend(b"\r\n");
+                }
+                Reply::Array(items) => {
+                    out.extend(format!("*{}\r\n", items.len()).as_bytes());
+                    for item in items {
+                        item.encode(out);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Just enough of redis to exercise the client. The lua scripts are
+    /// recognised by their hash and run natively, and expiries are only
+    /// recorded, never applied.
+    #[derive(Debug, Default)]
+    pub(crate) struct MockRedis {
+        strings: BTreeMap<Vec<u8>, Vec<u8>>,
+        hashes: HashMap<Vec<u8>, HashMap<Vec<u8>, Vec<u8>>>,
+        expiries: HashMap<Vec<u8>, i64>,
+    }
+
+    pub(crate) type MockState = Arc<Mutex<MockRedis>>;
+
+    impl MockRedis {
+        fn int(&self, key: &[u8]) -> i64 {
+            self.strings
+                .get(key)
+                .map(|value| String::from_utf8_lossy(value).parse().unwrap())
+                .unwrap_or_default()
+        }
+
+        fn set(&mut self, key: &[u8], value: &[u8], ttl: Option<i64>) {
+            self.strings.insert(key.to_vec(), value.to_vec());
+            match ttl {
+                Some(ttl) => self.expiries.insert(key.to_vec(), ttl),
+                None => self.expiries.remove(key),
+            };
+        }
+
+        fn del(&mut self, key: &[u8]) -> i64 {
+            self.expiries.remove(key);
+            self.strings.remove(key).map_or(0, |_| 1)
+        }
+
+        fn incr(&mut self, key: &[u8], by: i64) -> i64 {
+            let value = self.int(key) + by;
+            self.strings
+                .insert(key.to_vec(), value.to_string().into_bytes());
+            value
+        }
+
+        fn hset(&mut self, hash: &[u8], field: &[u8], value: &[u8]) {
+            self.hashes
+                .entry(hash.to_vec())
+                .or_default()
+                .insert(field.to_vec(), value.to_vec());
+        }
+
+        fn hdel(&mut self, hash: &[u8], field: &[u8]) -> i64 {
+            self.hashes
+                .get_mut(hash)
+                .and_then(|fields| fields.remove(field))
+                .map_or(0, |_| 1)
+        }
+
+        fn txn(&mut self, keys: &[Vec<u8>], args: &[Vec<u8>]) -> i64 {
+            let number = |arg: &[u8]| -> usize { String::from_utf8_lossy(arg).parse().unwrap() };
+            let compares = number(&args[0]);
+            let (mut k, mut i) = (1, 1);
+            for _ in 0..compares {
+                let (kind, key, arg) = (&args[i], &keys[k], &args[i + 1]);
+                i += 2;
+                k += 1;
+                let passed = match kind.as_slice() {
+                    b"unchanged" => self.int(key) <= number(arg) as i64,
+                    b"absent" => !self.strings.contains_key(key),
+                    _ => self.strings.get(key) == Some(arg),
+                };
+                if !passed {
+                    return 0;
+                }
+            }
+            let ops = number(&args[i]);
+            i += 1;
+            for _ in 0..ops {
+                let (verb, key, value, ttl) = (&args[i], &keys[k], &args[i + 1], &args[i + 2]);
+                i += 3;
+                k += 1;
+                let ttl = number(ttl) as i64;
+                if verb == b"put" && ttl > 0 {
+                    self.set(key, value, Some(ttl));
+                    self.hset(&keys[0], key, ttl.to_string().as_bytes());
+                    continue;
+                }
+                if verb == b"put" {
+                    self.set(key, value, None);
+                } else {
+                    self.del(key);
+                }
+                self.hdel(&keys[0], key);
+            }
+            for key in &keys[k..] {
+                self.incr(key, 1);
+            }
+            1
+        }
+
+        fn put_if_absent(&mut self, keys: &[Vec<u8>], args: &[Vec<u8>]) -> i64 {
+            if self.strings.contains_key(&keys[1]) {
+                return 0;
+            }
+            let ttl: i64 = String::from_utf8_lossy(&args[1]).parse().unwrap();
+            if ttl > 0 {
+                self.set(&keys[1], &args[0], Some(ttl));
+                self.hset(&keys[0], &keys[1], ttl.to_string().as_bytes());
+            } else {
+                self.set(&keys[1], &args[0], None);
+                self.hdel(&keys[0], &keys[1]);
+            }
+            if let Some(revision_key) = keys.get(2) {
+                self.incr(revision_key, 1);
+            }
+            1
+        }
+
+        fn execute(&mut self, command: &[Vec<u8>]) -> Reply {
+            let name = String::from_utf8_lossy(&command[0]).to_uppercase();
+            let args = &command[1..];
+            match name.as_str() {
+                "CLIENT" | "SELECT" => Reply::Status("OK"),
+                "PING" => Reply::Status("PONG"),
+                "GET" => Reply::Bulk(self.strings.get(&args[0]).cloned()),
+                "SET" => {
+                    let ttl = args
+                        .get(3)
+                        .map(|ttl| String::from_utf8_lossy(ttl).parse().unwrap());
+                    self.set(&args[0], &args[1], ttl);
+                    Reply::Status("OK")
+                }
+                "SETEX" => {
+                    let ttl = String::from_utf8_lossy(&args[1]).parse().unwrap();
+                    self.set(&args[0], &args[2], Some(ttl));
+                    Reply::Status("OK")
+                }
+                "DEL" => Reply::Int(args.iter().map(|key| self.del(key)).sum()),
+                "EXISTS" => Reply::Int(self.strings.contains_key(&args[0]) as i64),
+                "INCRBY" => {
+                    let by: i64 = String::from_utf8_lossy(&args[1]).parse().unwrap();
+                    Reply::Int(self.incr(&args[0], by))
+                }
+                "HGET" => Reply::Bulk(
+                    self.hashes
+                        .get(&args[0])
+                        .and_then(|fields| fields.get(&args[1]))
+                        .cloned(),
+                ),
+                "HSET" => {
+                    self.hset(&args[0], &args[1], &args[2]);
+                    Reply::Int(1)
+                }
+                "HDEL" => Reply::Int(args[1..].iter().map(|f| self.hdel(&args[0], f)).sum()),
+                "EXPIRE" => {
+                    if !self.strings.contains_key(&args[0]) {
+                        return Reply::Int(0);
+                    }
+                    let ttl = String::from_utf8_lossy(&args[1]).parse().unwrap();
+                    self.expiries.insert(args[0].clone(), ttl);
+                    Reply::Int(1)
+                }
+                "MGET" => Reply::Array(
+                    args.iter()
+                        .map(|key| Reply::Bulk(self.strings.get(key).cloned()))
+                        .collect(),
+                ),
+                "SCAN" => {
+                    // Only the `prefix*` patterns built by `match_pattern`
+                    let pattern = &args[2][..args[2].len() - 1];
+                    let mut prefix = Vec::new();
+                    let mut escaped = false;
+                    for byte in pattern {
+                        if *byte == b'\\' && !escaped {
+                            escaped = true;
+                            continue;
+                        }
+                        escaped = false;
+                        prefix.push(*byte);
+                    }
+                    let keys = self
+                        .strings
+                        .keys()
+                        .filter(|key| key.starts_with(&prefix))
+                        .map(|key| Reply::Bulk(Some(key.clone())))
+                        .collect();
+                    Reply::Array(vec![Reply::Bulk(Some(b"0".to_vec())), Reply::Array(keys)])
+                }
+                "EVALSHA" => {
+                    let count: usize = String::from_utf8_lossy(&args[1]).parse().unwrap();
+                    let (keys, script_args) = args[2..].split_at(count);
+                    let hash = String::from_utf8_lossy(&args[0]);
+                    if hash == Script::new(TXN_SCRIPT).get_hash() {
+                        Reply::Int(self.txn(keys, script_args))
+                    } else if hash == Script::new(PUT_IF_ABSENT_SCRIPT).get_hash() {
+                        Reply::Int(self.put_if_absent(keys, script_args))
+                    } else {
+                        Reply::Error("NOSCRIPT No matching script".into())
+                    }
+                }
+                _ => Reply::Error(format!("ERR unknown command '{name}'")),
+            }
+        }
+    }
+
+    async fn read_command(
+        reader: &mut BufReader<TcpStream>,
+    ) -> std::io::Result<Option<Vec<Vec<u8>>>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let count: usize = line.trim_end()[1..].parse().unwrap();
+        let mut command = Vec::with_capacity(count);
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).await?;
+            let len: usize = line.trim_end()[1..].parse().unwrap();
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).await?;
+            arg.truncate(len);
+            command.push(arg);
+        }
+        Ok(Some(command))
+    }
+
+    async fn serve(stream: TcpStream, state: MockState) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut queued: Option<Vec<Vec<Vec<u8>>>> = None;
+        while let Some(command) = read_command(&mut reader).await? {
+            let name = String::from_utf8_lossy(&command[0]).to_uppercase();
+            let reply = match (name.as_str(), queued.as_mut()) {
+                ("MULTI", _) => {
+                    queued = Some(Vec::new());
+                    Reply::Status("OK")
+                }
+                ("EXEC", Some(_)) => {
+                    let mut redis = state.lock().unwrap();
+                    Reply::Array(
+                        queued
+                            .take()
+                            .unwrap()
+                            .iter()
+                            .map(|command| redis.execute(command))
+                            .collect(),
+                    )
+                }
+                (_, Some(commands)) => {
+                    commands.push(command);
+                    Reply::Status("QUEUED")
+                }
+                (_, None) => state.lock().unwrap().execute(&command),
+            };
+            let mut out = Vec::new();
+            reply.encode(&mut out);
+            reader.get_mut().write_all(&out).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn mock_redis() -> (Redis, MockState) {
+        let state = MockState::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, server_state.clone()));
+            }
+        });
+        let redis = Redis::new(&RedisConfig {
+            url: format!("redis://{address}"),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        (redis, state)
+    }
+
+    #[test]
+    fn test_redis_config_default_values() {
+        let config = RedisConfig::default();
+        assert_eq!(config.url, "redis://127.0.0.1:6379");
+    }
+
+    #[test]
+    fn test_match_pattern_escapes_globs() {
+        assert_eq!(match_pattern(b"traefik/http"), b"traefik/http*".to_vec());
+        assert_eq!(match_pattern(b"a*b?[c]"), b"a\\*b\\?\\[c\\]*".to_vec());
+    }
+
+    #[test]
+    fn test_txn_args_layout() {
+        let compares = vec![
+            TxnCompare::UnchangedSince {
+                prefix: "traefik".into(),
+                revision: 7,
+            },
+            TxnCompare::Absent {
+                key: "_traefikctl/traefik/guard".into(),
+            },
+        ];
+        let ops = vec![
+            TxnOp::put_with_ttl("traefik/a", "1", 30),
+            TxnOp::delete("traefik/b"),
+            TxnOp::delete("_traefikctl/traefik/guard"),
+        ];
+        let strings = |items: Vec<Vec<u8>>| -> Vec<String> {
+            items
+                .into_iter()
+                .map(|item| String::from_utf8(item).unwrap())
+                .collect()
+        };
+        let (keys, args) = txn_args(&compares, &ops);
+        assert_eq!(
+            strings(keys),
+            vec![
+                "_traefikctl/ttl",
+                "_traefikctl/traefik/revision",
+                "_traefikctl/traefik/guard",
+                "traefik/a",
+                "traefik/b",
+                "_traefikctl/traefik/guard",
+                "_traefikctl/traefik/revision",
+            ]
+        );
+        assert_eq!(
+            strings(args),
+            vec![
+                "2",
+                "unchanged",
+                "7",
+                "absent",
+                "",
+                "3",
+                "put",
+                "1",
+                "30",
+                "delete",
+                "",
+                "0",
+                "delete",
+                "",
+                "0"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_revision_key_per_segment() {
+        assert_eq!(revision_key(b"traefik"), "_traefikctl/traefik/revision");
+        assert_eq!(
+            revision_key(b"traefik/http/routers"),
+            "_traefikctl/traefik/revision"
+        );
+        assert_eq!(
+            bumped_revision_key(b"traefik/http/routers/a/rule").as_deref(),
+            Some("_traefikctl/traefik/revision")
+        );
+        assert_eq!(bumped_revision_key(b"_traefikctl/traefik/lock"), None);
+        assert_eq!(
+            bumped_revision_key(b"_traefikctlx/a").as_deref(),
+            Some("_traefikctl/_traefikctlx/revision")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redis_new_returns_error_for_invalid_url() {
+        let config = RedisConfig {
+            url: "not-a-url".to_string(),
+            ..Default::default()
+        };
+        assert!(Redis::new(&config).await.is_err());
+    }
+
+    #[test]
+    fn test_redis_error_mapping() {
+        let refused =
+            redis::RedisError::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+        assert!(matches!(
+            redis_error("get")(refused),
+            TraefikError::Unavailable(_)
+        ));
+        let loading = redis::RedisError::from((ErrorKind::BusyLoadingError, "loading"));
+        assert!(redis_error("get")(loading).is_retryable());
+        let wrong_type = redis::RedisError::from((ErrorKind::TypeError, "wrong type"));
+        assert!(matches!(
+            redis_error("get")(wrong_type),
+            TraefikError::RedisError(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_and_put() {
+        let (redis, state) = mock_redis().await;
+        assert!(matches!(
+            redis.get("traefik/a").await,
+            Err(TraefikError::NotFound(_))
+        ));
+        assert!(redis.put("traefik/a", "1", None).await.unwrap().is_none());
+        let prev = redis.put("traefik/a", "2", None).await.unwrap().unwrap();
+        assert_eq!(prev.value, b"1");
+        assert_eq!(redis.get("traefik/a").await.unwrap().value, b"2");
+        assert_eq!(redis.revision(b"traefik").await.unwrap(), 2);
+
+        redis.put("traefik/b", "1", Some(30)).await.unwrap();
+        let state = state.lock().unwrap();
+        assert_eq!(state.expiries.get(b"traefik/b".as_slice()), Some(&30));
+        assert!(!state.expiries.contains_key(b"traefik/a".as_slice()));
+    }
+
+    #[tokio::test]
+    async fn test_txn_compare_failure_applies_nothing() {
+        let (redis, _state) = mock_redis().await;
+        redis.put("traefik/a", "1", None).await.unwrap();
+
+        let stale = vec![TxnCompare::UnchangedSince {
+            prefix: "traefik".into(),
+            revision: 0,
+        }];
+        assert!(!redis
+            .txn(stale, vec![TxnOp::put("traefik/b", "1")])
+            .await
+            .unwrap());
+        let taken = vec![TxnCompare::Absent {
+            key: "traefik/a".into(),
+        }];
+        assert!(!redis
+            .txn(taken, vec![TxnOp::put("traefik/b", "1")])
+            .await
+            .unwrap());
+        assert!(redis.get("traefik/b").await.is_err());
+        assert_eq!(redis.revision(b"traefik").await.unwrap(), 1);
+
+        let current = vec![TxnCompare::ValueEquals {
+            key: "traefik/a".into(),
+            value: "1".into(),
+        }];
+        assert!(redis
+            .txn(
+                current,
+                vec![TxnOp::put("traefik/b", "1"), TxnOp::delete("traefik/a")]
+            )
+            .await
+            .unwrap());
+        assert_eq!(redis.get("traefik/b").await.unwrap().value, b"1");
+        assert!(redis.get("traefik/a").await.is_err());
+        assert_eq!(redis.revision(b"traefik").await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_put_if_absent() {
+        let (redis, _state) = mock_redis().await;
+        assert!(redis.put_if_absent("lock", "me", Some(10)).await.unwrap());
+        assert!(!redis.put_if_absent("lock", "you", None).await.unwrap());
+        assert_eq!(redis.get("lock").await.unwrap().value, b"me");
+        assert_eq!(redis.revision(b"lock").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_only_writes_to_the_prefix_are_a_change() {
+        let (redis, _state) = mock_redis().await;
+        redis.put("traefik/a", "1", None).await.unwrap();
+        let snapshot = redis.snapshot("traefik").await.unwrap();
+
+        // Taking a lock, writing bookkeeping keys or another prefix
+        assert!(redis
+            .put_if_absent("_traefikctl/traefik/lock", "me", Some(10))
+            .await
+            .unwrap());
+        redis
+            .put("_traefikctl/traefik/owners/a", "team", None)
+            .await
+            .unwrap();
+        redis.put("other/a", "1", None).await.unwrap();
+        let unchanged = TxnCompare::UnchangedSince {
+            prefix: "traefik".into(),
+            revision: snapshot.revision,
+        };
+        assert!(redis
+            .txn(vec![unchanged.clone()], vec![TxnOp::put("traefik/b", "1")])
+            .await
+            .unwrap());
+
+        // The commit above was a change to the prefix
+        assert!(!redis
+            .txn(vec![unchanged], vec![TxnOp::put("traefik/c", "1")])
+            .await
+            .unwrap());
+        assert!(redis.get("traefik/c").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scan_matches_prefix_literally() {
+        let (redis, _state) = mock_redis().await;
+        for key in ["traefik/a*b/1", "traefik/a*b/2", "traefik/axb/1", "other/a"] {
+            redis.put(key, "v", None).await.unwrap();
+        }
+        let kvs = redis.get_with_prefix("traefik/a*b").await.unwrap();
+        assert_eq!(
+            kvs.iter().map(|kv| kv.key.as_slice()).collect::<Vec<_>>(),
+            vec![b"traefik/a*b/1".as_slice(), b"traefik/a*b/2".as_slice()]
+        );
+        assert_eq!(redis.get_keys("traefik/").await.unwrap().len(), 3);
+        assert_eq!(redis.delete_with_prefix("traefik/").await.unwrap(), 3);
+        assert!(redis.get_keys("traefik/").await.unwrap().is_empty());
+        assert_eq!(redis.get_keys("other/").await.unwrap().len(), 1);
+        assert_eq!(redis.revision(b"traefik").await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_touch_restores_the_stored_ttl() {
+        let (redis, state) = mock_redis().await;
+        redis.put("traefik/a", "1", Some(30)).await.unwrap();
+        redis.put("traefik/b", "1", None).await.unwrap();
+        state
+            .lock()
+            .unwrap()
+            .expiries
+            .insert(b"traefik/a".to_vec(), 5);
+
+        redis.touch("traefik/a").await.unwrap();
+        redis.touch("traefik/b").await.unwrap();
+        let state = state.lock().unwrap();
+        assert_eq!(state.expiries.get(b"traefik/a".as_slice()), Some(&30));
+        assert!(!state.expiries.contains_key(b"traefik/b".as_slice()));
+    }
+}
@@ -11,6 +11,7 @@ use crate::{
     },
     core::{
         etcd_trait::EtcdPair,
+        lock::LockConfig,
         templating::{TemplateContext, TemplateOr, TemplateResolver},
     },
     error::TraefikResult,
@@ -18,6 +19,13 @@ use crate::{
     TraefikConfig,
 };
 
+pub fn pairs(items: &[(&str, &str)]) -> Vec<EtcdPair> {
+    items
+        .iter()
+        .map(|(key, value)| EtcdPair::new(*key, *value))
+        .collect()
+}
+
 pub fn assert_contains_pair(pairs: &[EtcdPair], expected_value: &str) {
     let pair_strs: Vec<String> = pairs.iter().map(|p| p.to_string()).collect();
     assert!(pair_strs.contains(&expected_value.to_string()));
@@ -46,6 +54,10 @@ pub fn create_test_deployment() -> DeploymentConfig {
         middlewares: None,
         middleware_templates: None,
         variables: None,
+        tcp: None,
+        udp: None,
+        health_check: None,
+        sticky: None,
     }
 }
 
@@ -58,6 +70,7 @@ pub fn create_test_host() -> HostConfig {
         selection: None,
         forward_host: false,
         variables: None,
+        health_check: None,
     };
 
     // Add a default blue deployment
@@ -75,6 +88,10 @@ pub fn create_test_host() -> HostConfig {
             middlewares: None,
             middleware_templates: None,
             variables: None,
+            tcp: None,
+            udp: None,
+            health_check: None,
+            sticky: None,
         },
     );
 
@@ -97,6 +114,10 @@ pub fn create_test_host() -> HostConfig {
                     middlewares: None,
                     middleware_templates: None,
                     variables: None,
+                    tcp: None,
+                    udp: None,
+                    health_check: None,
+                    sticky: None,
                 },
             );
             map
@@ -209,6 +230,10 @@ pub fn create_test_config(host_configs: Option<Vec<HostConfig>>) -> TraefikConfi
                     middlewares: None,
                     middleware_templates: None,
                     variables: None,
+                    tcp: None,
+                    udp: None,
+                    health_check: None,
+                    sticky: None,
                 },
             )]),
             middlewares: vec!["enable-headers".to_string()],
@@ -219,19 +244,27 @@ pub fn create_test_config(host_configs: Option<Vec<HostConfig>>) -> TraefikConfi
         deployments: HashMap::from([("blue".to_string(), DeploymentConfig::default())]),
         middlewares: vec![],
         variables: None,
+        health_check: None,
     }]);
 
     TraefikConfig {
         #[cfg(feature = "etcd")]
         etcd: Default::default(),
+        store: None,
+        #[cfg(feature = "consul")]
+        consul: None,
+        #[cfg(feature = "redis")]
+        redis: None,
         middlewares: create_test_middleware(),
         hosts: host_configs,
         rule_prefix: "test".to_string(),
+        owner: None,
         name: Some("test".to_string()),
         description: Some("test".to_string()),
         services: None,
         entry_points: None,
         variables: None,
+        lock: LockConfig::default(),
     }
 }
 
@@ -247,6 +280,8 @@ pub fn create_test_middleware() -> HashMap<String, MiddlewareConfig> {
                 basic_auth: None,
                 compress: false,
                 circuit_breaker: None,
+                in_flight_conn: None,
+                ip_allow_list: None,
                 redirect_regex: None,
                 redirect_scheme: None,
                 headers: Some(HeadersConfig {
@@ -332,6 +367,8 @@ pub fn create_test_middleware() -> HashMap<String, MiddlewareConfig> {
                 basic_auth: None,
                 compress: false,
                 circuit_breaker: None,
+                in_flight_conn: None,
+                ip_allow_list: None,
                 redirect_regex: None,
                 redirect_scheme: None,
             },
@@ -362,6 +399,8 @@ pub fn create_base_middleware_config() -> MiddlewareConfig {
         basic_auth: None,
         compress: false,
         circuit_breaker: None,
+        in_flight_conn: None,
+        ip_allow_list: None,
         name: "test-middleware".to_string(),
         protocol: "http".to_string(),
     }